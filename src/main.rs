@@ -1,18 +1,125 @@
-use iced::{Application, Settings};
+use iced::{Application, Settings, window};
 
 mod notes;
 
+/// Lock for a command writing into a storage, exits when the storage is open in the app
+/// so the command never writes under it
+#[cfg(not(target_arch = "wasm32"))]
+fn lock_for_command(storage_path: &std::path::Path) -> Option<notes::instance_lock::InstanceLock> {
+    use notes::instance_lock::{Acquired, SecondInstanceMode};
+
+    match notes::instance_lock::acquire(storage_path, SecondInstanceMode::Refuse) {
+        Ok(Acquired::Owner(lock)) => Some(lock),
+        Ok(_) => {
+            eprintln!("{} is already open in another instance", storage_path.display());
+            std::process::exit(1)
+        }
+        Err(error @ notes::instance_lock::LockError::AlreadyRunning { .. }) => {
+            eprintln!("{}", error);
+            std::process::exit(1)
+        }
+        // same as the app, a storage that can't be locked is still usable
+        Err(error) => {
+            eprintln!("{}, continuing without a lock", error);
+            None
+        }
+    }
+}
+
 pub fn main() -> iced::Result {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(target) = notes::get_migration_target() {
+        let source = notes::get_storage();
+        let _lock = lock_for_command(target.path());
+        return match async_std::task::block_on(notes::storage::migrate(source.as_ref(), target.as_ref())) {
+            Ok(count) => {
+                println!(
+                    "Migrated {} notes from {} to {}",
+                    count, source.path().display(), target.path().display(),
+                );
+                Ok(())
+            }
+            Err(error) => {
+                eprintln!("{}", error);
+                std::process::exit(1)
+            }
+        };
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(dir) = notes::get_markdown_export_dir() {
+        let storage = notes::get_storage();
+        return match async_std::task::block_on(notes::markdown_folder::export(storage.as_ref(), dir.clone())) {
+            Ok(count) => {
+                println!("Exported {} notes from {} to {}", count, storage.path().display(), dir.display());
+                Ok(())
+            }
+            Err(error) => {
+                eprintln!("{}", error);
+                std::process::exit(1)
+            }
+        };
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(dir) = notes::get_markdown_import_dir() {
+        let storage = notes::get_storage();
+        let _lock = lock_for_command(storage.path());
+        return match async_std::task::block_on(notes::markdown_folder::import(storage.as_ref(), dir.clone())) {
+            Ok(report) => {
+                println!("Imported {} into {}: {}", dir.display(), storage.path().display(), report);
+                for conflict in &report.conflicts {
+                    eprintln!("Conflict in {}", conflict);
+                }
+                Ok(())
+            }
+            Err(error) => {
+                eprintln!("{}", error);
+                std::process::exit(1)
+            }
+        };
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let flags = {
+        use notes::instance_lock::{Acquired, LockError};
+
+        let storage = notes::get_storage();
+        match notes::instance_lock::acquire(storage.path(), notes::get_second_instance_mode()) {
+            Ok(Acquired::Owner(lock)) => notes::app::Flags { storage, instance_lock: Some(lock), is_read_only: false },
+            Ok(Acquired::ReadOnly) => {
+                println!("{} is open in another instance, opening it read-only", storage.path().display());
+                notes::app::Flags { storage, instance_lock: None, is_read_only: true }
+            }
+            Ok(Acquired::Focused) => {
+                println!("{} is already open, switched to that window", storage.path().display());
+                return Ok(());
+            }
+            Err(error @ LockError::AlreadyRunning { .. }) => {
+                eprintln!("{}", error);
+                std::process::exit(1)
+            }
+            // a storage that can't be locked, like one on a read-only share, is still usable
+            Err(error) => {
+                eprintln!("{}, opening without a lock", error);
+                notes::app::Flags { storage, instance_lock: None, is_read_only: false }
+            }
+        }
+    };
+    #[cfg(target_arch = "wasm32")]
+    let flags = notes::app::Flags { storage: notes::get_storage(), is_read_only: false };
+
     notes::app::IcedApplication::run(Settings {
         fonts: vec![
             include_bytes!("../fonts/material-icons.ttf")
                 .as_slice()
                 .into(),
         ],
-        ..Settings::default()
+        window: window::Settings {
+            // pending changes are flushed before the window is closed
+            exit_on_close_request: false,
+            ..window::Settings::default()
+        },
+        ..Settings::with_flags(flags)
     })
 }
-
-
-
-
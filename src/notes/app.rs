@@ -1,27 +1,242 @@
-use iced::{Application, Command, Element, Length, Theme, widget};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use iced::{Application, Command, Element, event, Event, keyboard, Length, Subscription, Theme, widget, window};
 use iced::widget::{column, container};
+use uuid::Uuid;
+
+use crate::notes::{AUTO_LOCK_CHECK_INTERVAL, AUTOSAVE_INTERVAL, STORAGE_WATCH_INTERVAL, buttons, category_list, common, error_banner, get_auto_lock_after, get_settings_file, get_trash_retention, lock_screen, Message, models, palette, post_list, settings, toast, type_to_async, UNDO_TOAST_CHECK_INTERVAL, UNDO_TOAST_DURATION, wiki_links};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::notes::{INSTANCE_FOCUS_POLL_INTERVAL, instance_lock};
+use crate::notes::settings::ShortcutAction;
+use crate::notes::storage::{self, Storage, StorageError, StorageKind};
+use crate::notes::undo::UndoAction;
 
-use crate::notes::{buttons, common, Message, models, post_list, type_to_async};
-use crate::notes::storage::JsonStorage;
+/// What the app starts with, the storage and whether this instance may write to it
+pub struct Flags {
+    pub storage: Box<dyn Storage>,
+    /// Held while the app runs, `None` when another instance owns the storage
+    #[cfg(not(target_arch = "wasm32"))]
+    pub instance_lock: Option<instance_lock::InstanceLock>,
+    pub is_read_only: bool,
+}
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct IcedApplication {
     pub notes: models::NotesState,
+    pub storage: Box<dyn Storage>,
     pub is_dark_theme: bool,
+    pub is_closing: bool,
+    /// Saving stays blocked while set, so an unreadable file is never overwritten
+    pub load_error: Option<StorageError>,
+    pub save_error: Option<StorageError>,
+    /// Backup the notes were loaded from because the storage file couldn't be read
+    pub restored_backup_path: Option<PathBuf>,
+    pub other_file_path: String,
+    /// Post offered for undo in the toast after it was moved to the trash
+    pub undo_post_id: Option<Uuid>,
+    /// The toast is hidden by the first tick after this, each deletion pushes it back
+    pub undo_toast_expires_at: chrono::DateTime<chrono::Utc>,
+    pub trash_retention: chrono::Duration,
+    pub settings: settings::Settings,
+    pub settings_path: PathBuf,
+    pub palette: Option<palette::CommandPalette>,
+    /// Storage modification time after the last load, save or reload,
+    /// `None` until it's taken again after one of them
+    pub storage_modified_at: Option<SystemTime>,
+    /// A save is waiting for the check for external changes, autosaves and reloads wait for it too
+    pub is_checking_before_save: bool,
+    /// A reload was dropped because a save was running, the next check reloads anyway
+    pub is_reload_needed: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub instance_lock: Option<instance_lock::InstanceLock>,
+    /// Another instance owns the storage, changes are shown but never saved
+    pub is_read_only: bool,
+    /// Wiped from memory when it's replaced and once it's handed to the storage
+    pub unlock_passphrase: zeroize::Zeroizing<String>,
+    pub unlock_error: Option<StorageError>,
+    pub is_unlocking: bool,
+    pub passphrase_form: Option<lock_screen::PassphraseForm>,
+    /// Encrypted notes lock after this long without input, `None` never
+    pub auto_lock_after: Option<chrono::Duration>,
+    pub last_input_at: chrono::DateTime<chrono::Utc>,
+    /// Locking waits for the running save to finish
+    pub is_lock_pending: bool,
+}
+
+
+impl IcedApplication {
+    /// Checks for changes made outside the app first, so the save never writes over them
+    fn save(&mut self) -> Command<Message> {
+        self.is_checking_before_save = true;
+        Command::perform(self.storage.modified_at(), Message::StorageCheckedBeforeSave)
+    }
+    fn start_saving(&mut self) -> Command<Message> {
+        self.is_checking_before_save = false;
+        let changes = self.notes.start_saving();
+        Command::perform(
+            self.storage.save_changes(&self.notes.data, &changes),
+            Message::NotesStorageSaved,
+        )
+    }
+    fn is_saving(&self) -> bool {
+        self.is_checking_before_save || self.notes.is_saving()
+    }
+    fn search(&self) -> Command<Message> {
+        let query = self.notes.search_input_value.clone();
+        if self.notes.get_search_query().is_empty() {
+            return Command::none();
+        }
+        Command::perform(
+            self.storage.search(&query),
+            move |result| Message::StorageSearchCompleted(query, result),
+        )
+    }
+    fn load(&mut self) -> Command<Message> {
+        self.notes.is_loading = true;
+        self.storage_modified_at = None;
+        Command::perform(self.storage.load(), Message::NotesStorageLoaded)
+    }
+    fn can_reload(&self) -> bool {
+        !self.notes.is_loading && self.load_error.is_none()
+    }
+    fn can_save(&self) -> bool {
+        self.can_reload() && !self.is_read_only
+    }
+    fn close(&mut self, id: window::Id) -> Command<Message> {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.instance_lock = None;
+        }
+        window::close(id)
+    }
+    /// Locks a storage opened from the app, it's read-only while another instance holds it
+    #[cfg(not(target_arch = "wasm32"))]
+    fn lock_storage(&mut self) {
+        self.instance_lock = None;
+        match instance_lock::acquire(self.storage.path(), instance_lock::SecondInstanceMode::ReadOnly) {
+            Ok(instance_lock::Acquired::Owner(lock)) => {
+                self.instance_lock = Some(lock);
+                self.is_read_only = false;
+            }
+            Ok(_) => self.is_read_only = true,
+            Err(error) => {
+                eprintln!("{}, opening without a lock", error);
+                self.is_read_only = false;
+            }
+        }
+    }
+    /// Forgets the key and everything decrypted with it, the unlock screen takes over
+    fn lock(&mut self) -> Command<Message> {
+        self.storage.lock();
+        self.notes = models::NotesState::default();
+        self.notes.set_post_sort(self.settings.post_sort);
+        self.palette = None;
+        self.passphrase_form = None;
+        self.undo_post_id = None;
+        self.storage_modified_at = None;
+        self.is_reload_needed = false;
+        self.load_error = Some(StorageError::Locked { path: self.storage.path().to_path_buf() });
+        widget::text_input::focus(lock_screen::passphrase_input_id())
+    }
+    /// Locks once pending changes are saved, so locking never drops an edit
+    fn lock_when_saved(&mut self) -> Command<Message> {
+        if self.is_saving() {
+            self.is_lock_pending = true;
+            return Command::none();
+        }
+        if self.notes.is_dirty && self.can_save() {
+            self.is_lock_pending = true;
+            return self.save();
+        }
+        self.is_lock_pending = false;
+        self.lock()
+    }
+    fn run_shortcut(&mut self, action: ShortcutAction) -> Command<Message> {
+        match action {
+            ShortcutAction::NewNote => self.update(Message::ButtonCreatePressed),
+            ShortcutAction::DeleteNote => match self.notes.selected_post_id {
+                Some(post_id) => self.update(Message::ButtonDeletePressed(post_id)),
+                None => Command::none(),
+            },
+            ShortcutAction::FocusSearch => {
+                self.notes.is_trash_open = false;
+                widget::text_input::focus(post_list::search_input_id())
+            }
+            ShortcutAction::NextNote => {
+                self.notes.select_adjacent_post(1);
+                Command::none()
+            }
+            ShortcutAction::PreviousNote => {
+                self.notes.select_adjacent_post(-1);
+                Command::none()
+            }
+            ShortcutAction::ToggleTheme => self.update(Message::ToggleIsDarkMode(!self.is_dark_theme)),
+            ShortcutAction::SaveNow => {
+                if self.can_save() && self.notes.is_dirty && !self.is_saving() {
+                    self.save()
+                } else {
+                    Command::none()
+                }
+            }
+            ShortcutAction::CommandPalette => match self.palette {
+                Some(_) => {
+                    self.palette = None;
+                    Command::none()
+                }
+                None => {
+                    self.palette = Some(palette::CommandPalette::default());
+                    widget::text_input::focus(palette::input_id())
+                }
+            },
+            ShortcutAction::Undo => self.update(Message::Undo),
+            ShortcutAction::Redo => self.update(Message::Redo),
+        }
+    }
 }
 
 
 impl Application for IcedApplication {
     type Executor = iced::executor::Default;
     type Message = Message;
-    type Flags = ();
+    type Flags = Flags;
     type Theme = Theme;
 
-    fn new(_flags: ()) -> (Self, Command<Message>) {
-        (
-            IcedApplication::default(),
-            Command::perform(JsonStorage::load(), Message::NotesStorageLoaded),
-        )
+    fn new(flags: Flags) -> (Self, Command<Message>) {
+        let settings_path = get_settings_file();
+        let settings = settings::load(&settings_path);
+        let mut app = IcedApplication {
+            notes: models::NotesState::default(),
+            storage: flags.storage,
+            is_dark_theme: false,
+            is_closing: false,
+            load_error: None,
+            save_error: None,
+            restored_backup_path: None,
+            other_file_path: String::new(),
+            undo_post_id: None,
+            undo_toast_expires_at: chrono::Utc::now(),
+            trash_retention: get_trash_retention(),
+            settings,
+            settings_path,
+            palette: None,
+            storage_modified_at: None,
+            is_checking_before_save: false,
+            is_reload_needed: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            instance_lock: flags.instance_lock,
+            is_read_only: flags.is_read_only,
+            unlock_passphrase: Default::default(),
+            unlock_error: None,
+            is_unlocking: false,
+            passphrase_form: None,
+            auto_lock_after: get_auto_lock_after(),
+            last_input_at: chrono::Utc::now(),
+            is_lock_pending: false,
+        };
+        app.notes.set_post_sort(app.settings.post_sort);
+        let command = app.load();
+        (app, command)
     }
 
 
@@ -30,16 +245,183 @@ impl Application for IcedApplication {
     }
 
     fn update(&mut self, message: Message) -> Command<Message> {
+        if is_user_input(&message) {
+            self.last_input_at = chrono::Utc::now();
+        }
+        // another instance owns the notes, edits here could never be saved
+        if self.is_read_only && is_edit(&message) {
+            return Command::none();
+        }
         match message {
             Message::NotesStorageLoaded(data) => {
                 match data {
                     Ok(data) => {
-                        self.notes.data = data;
+                        self.notes.set_data(data);
+                        self.load_error = None;
+                        self.restored_backup_path = self.storage.take_restored_backup();
+                        if self.notes.purge_expired_trash(self.trash_retention) > 0 {
+                            self.notes.mark_dirty();
+                        }
+                    }
+                    Err(error) if error.is_not_found() => {
+                        // first run, the file is created on the first save
+                        self.notes.set_data(models::Data::default());
+                        self.load_error = None;
+                    }
+                    Err(error) if error.is_locked() => {
+                        self.load_error = Some(error);
+                        self.notes.is_loading = false;
+                        return widget::text_input::focus(lock_screen::passphrase_input_id());
                     }
                     Err(error) => {
-                        print!("Message::JsonStorageLoaded {:?}", error);
+                        self.load_error = Some(error);
                     }
                 };
+                self.notes.is_loading = false;
+                Command::none()
+            }
+            Message::UnlockPassphraseChanged(passphrase) => {
+                self.unlock_passphrase = zeroize::Zeroizing::new(passphrase);
+                Command::none()
+            }
+            Message::UnlockSubmitted => {
+                if self.is_unlocking || self.unlock_passphrase.is_empty() {
+                    return Command::none();
+                }
+                self.is_unlocking = true;
+                self.unlock_error = None;
+                let passphrase = std::mem::take(&mut self.unlock_passphrase);
+                Command::perform(self.storage.unlock(passphrase), Message::NotesStorageUnlocked)
+            }
+            Message::NotesStorageUnlocked(result) => {
+                self.is_unlocking = false;
+                match result {
+                    Ok(data) => {
+                        self.storage_modified_at = None;
+                        self.update(Message::NotesStorageLoaded(Ok(data)))
+                    }
+                    Err(error) => {
+                        self.unlock_error = Some(error);
+                        widget::text_input::focus(lock_screen::passphrase_input_id())
+                    }
+                }
+            }
+            Message::ButtonLockPressed => {
+                match self.storage.is_encrypted() && self.load_error.is_none() {
+                    true => self.lock_when_saved(),
+                    false => Command::none(),
+                }
+            }
+            Message::AutoLockTick => {
+                let Some(auto_lock_after) = self.auto_lock_after else {
+                    return Command::none();
+                };
+                let is_idle = chrono::Utc::now() - self.last_input_at >= auto_lock_after;
+                if !is_idle || self.is_lock_pending || !self.storage.is_encrypted() || self.load_error.is_some() {
+                    return Command::none();
+                }
+                self.lock_when_saved()
+            }
+            Message::ButtonPassphrasePressed => {
+                if !self.storage.supports_encryption() {
+                    return Command::none();
+                }
+                self.passphrase_form = match self.passphrase_form {
+                    Some(_) => None,
+                    None => Some(lock_screen::PassphraseForm::default()),
+                };
+                Command::none()
+            }
+            Message::PassphraseFormChanged(field, value) => {
+                if let Some(form) = &mut self.passphrase_form {
+                    form.set(field, value);
+                }
+                Command::none()
+            }
+            Message::PassphraseFormSubmitted => {
+                let can_save = self.can_save();
+                let is_saving = self.is_saving();
+                let is_encrypted = self.storage.is_encrypted();
+                let Some(form) = &mut self.passphrase_form else {
+                    return Command::none();
+                };
+                if form.is_saving {
+                    return Command::none();
+                }
+                if !can_save || is_saving {
+                    form.error = Some(String::from("The notes can't be saved right now, try again in a moment"));
+                    return Command::none();
+                }
+                let new = match form.validate(is_encrypted) {
+                    Ok(new) => new,
+                    Err(error) => {
+                        form.error = Some(error);
+                        return Command::none();
+                    }
+                };
+                form.is_saving = true;
+                let current = is_encrypted.then(|| form.current.clone());
+                // a regular save cycle, so autosaves and reloads wait for it
+                self.notes.start_saving();
+                Command::perform(
+                    self.storage.change_passphrase(&self.notes.data, current, new),
+                    Message::PassphraseChanged,
+                )
+            }
+            Message::PassphraseChanged(result) => {
+                self.notes.finish_saving(result.is_ok());
+                self.storage_modified_at = None;
+                match result {
+                    Ok(()) => self.passphrase_form = None,
+                    Err(error) => {
+                        if let Some(form) = &mut self.passphrase_form {
+                            form.is_saving = false;
+                            form.error = Some(error.to_string());
+                        }
+                    }
+                }
+                Command::none()
+            }
+            Message::ButtonRetryLoadPressed => {
+                self.load()
+            }
+            Message::OtherFilePathChanged(path) => {
+                self.other_file_path = path;
+                Command::none()
+            }
+            Message::ButtonOpenOtherFilePressed => {
+                let path = self.other_file_path.trim();
+                if path.is_empty() {
+                    return Command::none();
+                }
+                let path = PathBuf::from(path);
+                self.storage = storage::open(StorageKind::from_path(&path), path);
+                #[cfg(not(target_arch = "wasm32"))]
+                self.lock_storage();
+                self.other_file_path.clear();
+                self.load()
+            }
+            Message::ButtonStartFreshPressed => {
+                Command::perform(self.storage.set_aside(), Message::NotesStorageSetAside)
+            }
+            Message::NotesStorageSetAside(result) => {
+                match result {
+                    Ok(_broken_path) => {
+                        self.notes.set_data(models::Data::default());
+                        self.load_error = None;
+                    }
+                    Err(error) => {
+                        self.load_error = Some(error);
+                    }
+                };
+                Command::none()
+            }
+            Message::SaveErrorDismissed => {
+                self.save_error = None;
+                Command::none()
+            }
+            Message::RestoredBackupDismissed => {
+                self.restored_backup_path = None;
                 Command::none()
             }
             Message::ToggleIsDarkMode(value) => {
@@ -47,36 +429,76 @@ impl Application for IcedApplication {
                 Command::none()
             }
             Message::ButtonDeletePressed(post_id) => {
-                self.notes.remove_post(post_id);
-                Command::perform(
-                    JsonStorage::save::<models::Data>(self.notes.data.clone()),
-                    Message::NotesStorageSaved,
-                )
+                if self.notes.record(UndoAction::DeletePost, |notes| notes.trash_post(post_id).is_some()) {
+                    self.undo_post_id = Some(post_id);
+                    self.undo_toast_expires_at = chrono::Utc::now() + chrono::Duration::from_std(UNDO_TOAST_DURATION).unwrap_or_default();
+                    self.notes.mark_dirty();
+                }
+                Command::none()
+            }
+            Message::ButtonUndoDeletePressed(post_id) => {
+                self.undo_post_id = None;
+                if self.notes.record(UndoAction::RestorePost, |notes| notes.restore_post(post_id).is_some()) {
+                    self.notes.set_selected_post(Some(post_id));
+                    self.notes.mark_dirty();
+                }
+                Command::none()
+            }
+            Message::UndoToastTick => {
+                if chrono::Utc::now() >= self.undo_toast_expires_at {
+                    self.undo_post_id = None;
+                }
+                Command::none()
+            }
+            Message::UndoToastExpired => {
+                self.undo_post_id = None;
+                Command::none()
+            }
+            Message::ButtonTrashPressed => {
+                self.notes.toggle_trash();
+                Command::none()
+            }
+            Message::ButtonRestorePostPressed(post_id) => {
+                if self.notes.record(UndoAction::RestorePost, |notes| notes.restore_post(post_id).is_some()) {
+                    self.notes.mark_dirty();
+                }
+                Command::none()
+            }
+            Message::ButtonPurgePostPressed(post_id) => {
+                if self.notes.purge_post(post_id).is_some() {
+                    self.notes.mark_dirty();
+                }
+                Command::none()
+            }
+            Message::ButtonEmptyTrashPressed => {
+                if self.notes.empty_trash() {
+                    self.notes.mark_dirty();
+                }
+                Command::none()
             }
             Message::ButtonCreatePressed => {
-                let new_post = self.notes.add_post("New", "");
+                let new_post = self.notes.record(UndoAction::CreatePost, |notes| notes.add_post("New", ""));
                 self.notes.selected_post_id = Some(new_post.id);
-                Command::perform(
-                    JsonStorage::save::<models::Data>(self.notes.data.clone()),
-                    Message::NotesStorageSaved,
-                )
+                self.notes.mark_dirty();
+                Command::none()
             }
             Message::SelectPost(post_id) => {
                 self.notes.set_selected_post(Some(post_id));
                 Command::none()
             }
             Message::PostTitleUpdated(next_title) => {
-                self.notes.update_selected_post_title(next_title);
-                Command::perform(
-                    JsonStorage::save::<models::Data>(self.notes.data.clone()),
-                    Message::NotesStorageSaved,
-                )
+                self.notes.record(UndoAction::EditTitle, |notes| notes.update_selected_post_title(next_title));
+                self.notes.mark_dirty();
+                Command::none()
             }
             Message::OnTextEditorAction(action) => {
-                self.notes.is_dirty = self.notes.is_dirty || action.is_edit();
+                let is_edit = action.is_edit();
 
                 self.notes.text_editor_state.perform(action);
 
+                if !is_edit {
+                    return Command::none();
+                }
                 Command::perform(
                     type_to_async(
                         Message::PostContentUpdated(self.notes.text_editor_state.text())
@@ -85,21 +507,373 @@ impl Application for IcedApplication {
                 )
             }
             Message::PostContentUpdated(text) => {
-                self.notes.update_selected_post_content(text);
-                Command::perform(
-                    JsonStorage::save::<models::Data>(self.notes.data.clone()),
-                    Message::NotesStorageSaved,
-                )
+                self.notes.record(UndoAction::EditContent, |notes| notes.update_selected_post_content(text));
+                self.notes.mark_dirty();
+                Command::none()
             }
-            _ => {
-                Command::perform(
-                    JsonStorage::save::<models::Data>(self.notes.data.clone()),
-                    Message::NotesStorageSaved,
-                )
+            Message::EditorModeSelected(editor_mode) => {
+                self.notes.set_editor_mode(editor_mode);
+                Command::none()
+            }
+            Message::MarkdownLinkPressed(url) => {
+                match url.strip_prefix(wiki_links::NOTE_URL_PREFIX) {
+                    Some(target) => match self.notes.resolve_link(target) {
+                        Some(post_id) => self.update(Message::SelectPost(post_id)),
+                        None => Command::none(),
+                    },
+                    None => {
+                        common::open_url(&url);
+                        Command::none()
+                    }
+                }
+            }
+            Message::ButtonRewriteLinksPressed => {
+                if self.notes.record(UndoAction::RewriteLinks, |notes| notes.rewrite_renamed_links()) {
+                    self.notes.mark_dirty();
+                }
+                Command::none()
+            }
+            Message::KeyPressed(key, modifiers) => {
+                // the open palette takes the keys it needs to be driven from its input
+                if let Some(palette) = &mut self.palette {
+                    let offset = match key.as_ref() {
+                        keyboard::Key::Named(keyboard::key::Named::Escape) => {
+                            self.palette = None;
+                            return Command::none();
+                        }
+                        keyboard::Key::Named(keyboard::key::Named::ArrowDown) => Some(1),
+                        keyboard::Key::Named(keyboard::key::Named::ArrowUp) => Some(-1),
+                        _ => None,
+                    };
+                    if let Some(offset) = offset {
+                        let entries_count = palette.entries(&self.notes, &self.settings).len();
+                        palette.move_selection(offset, entries_count);
+                        return Command::none();
+                    }
+                }
+                match self.settings.find_action(&key, modifiers) {
+                    Some(action) => self.update(Message::ShortcutTriggered(action)),
+                    None => Command::none(),
+                }
+            }
+            Message::ShortcutTriggered(action) => {
+                self.run_shortcut(action)
+            }
+            Message::PaletteInputChanged(query) => {
+                if let Some(palette) = &mut self.palette {
+                    palette.query = query;
+                    palette.selected_index = 0;
+                }
+                Command::none()
+            }
+            Message::PaletteSubmitted => {
+                let command = self.palette.as_ref().and_then(|palette| {
+                    palette.entries(&self.notes, &self.settings)
+                        .into_iter()
+                        .nth(palette.selected_index)
+                        .map(|entry| entry.command)
+                });
+                match command {
+                    Some(command) => self.update(Message::PaletteCommandSelected(command)),
+                    None => Command::none(),
+                }
+            }
+            Message::PaletteCommandSelected(command) => {
+                self.palette = None;
+                match command {
+                    palette::PaletteCommand::Action(action) => self.run_shortcut(action),
+                    palette::PaletteCommand::OpenNote(post_id) => self.update(Message::SelectPost(post_id)),
+                }
+            }
+            Message::Undo => {
+                if self.notes.undo() {
+                    self.notes.mark_dirty();
+                }
+                Command::none()
+            }
+            Message::Redo => {
+                if self.notes.redo() {
+                    self.notes.mark_dirty();
+                }
+                Command::none()
+            }
+            Message::ButtonHistoryPressed => {
+                self.notes.toggle_history();
+                Command::none()
+            }
+            Message::HistoryBaseSelected(option) => {
+                self.notes.set_history_base(option.saved_at);
+                Command::none()
+            }
+            Message::HistoryTargetSelected(option) => {
+                self.notes.set_history_target(option.saved_at);
+                Command::none()
+            }
+            Message::ButtonRestoreRevisionPressed(saved_at) => {
+                if self.notes.record(UndoAction::RestoreRevision, |notes| notes.restore_selected_post_revision(saved_at)) {
+                    self.notes.mark_dirty();
+                }
+                Command::none()
+            }
+            Message::ButtonKeepLinksPressed => {
+                self.notes.keep_renamed_links();
+                Command::none()
+            }
+            Message::SearchInputChanged(value) => {
+                self.notes.set_search_input_value(value);
+                self.search()
+            }
+            Message::StorageSearchCompleted(query, result) => {
+                // a newer query may have been typed in the meantime
+                if query == self.notes.search_input_value {
+                    self.notes.search_results = result.ok().flatten();
+                }
+                Command::none()
+            }
+            Message::SelectFavourites => {
+                self.notes.select_favourites();
+                Command::none()
+            }
+            Message::SelectCategory(category_id) => {
+                self.notes.set_selected_category(category_id);
+                Command::none()
+            }
+            Message::ButtonCategoryCreatePressed => {
+                let new_category = self.notes.record(UndoAction::CreateCategory, |notes| notes.add_category("New category"));
+                self.notes.set_selected_category(Some(new_category.id));
+                self.notes.mark_dirty();
+                Command::none()
+            }
+            Message::ButtonCategoryDeletePressed(category_id) => {
+                self.notes.record(UndoAction::DeleteCategory, |notes| notes.remove_category(category_id));
+                self.notes.mark_dirty();
+                Command::none()
+            }
+            Message::CategoryTitleUpdated(category_id, next_title) => {
+                self.notes.record(UndoAction::RenameCategory, |notes| notes.update_category_title(category_id, next_title));
+                self.notes.mark_dirty();
+                Command::none()
+            }
+            Message::PostCategorySelected(option) => {
+                self.notes.record(UndoAction::ChangePostCategory, |notes| notes.update_selected_post_category(option.id));
+                self.notes.mark_dirty();
+                Command::none()
+            }
+            Message::TagFilterToggled(tag) => {
+                self.notes.toggle_selected_tag(tag);
+                Command::none()
+            }
+            Message::PostSortSelected(post_sort) => {
+                self.notes.set_post_sort(post_sort);
+                self.settings.post_sort = post_sort;
+                if let Err(error) = settings::save(&self.settings_path, &self.settings) {
+                    eprintln!("Failed to save settings file {}: {}", self.settings_path.display(), error);
+                }
+                Command::none()
+            }
+            Message::ButtonPinPostPressed(post_id) => {
+                if self.notes.record(UndoAction::PinPost, |notes| notes.toggle_post_pinned(post_id)).is_some() {
+                    self.notes.mark_dirty();
+                }
+                Command::none()
+            }
+            Message::ButtonFavouritePostPressed(post_id) => {
+                if self.notes.record(UndoAction::FavouritePost, |notes| notes.toggle_post_favourite(post_id)).is_some() {
+                    self.notes.mark_dirty();
+                }
+                Command::none()
+            }
+            Message::ButtonMovePostPressed(post_id, offset) => {
+                if self.notes.record(UndoAction::MovePost, |notes| notes.move_post(post_id, offset)) {
+                    self.notes.mark_dirty();
+                }
+                Command::none()
+            }
+            Message::TagMatchSelected(tag_match) => {
+                self.notes.set_tag_match(tag_match);
+                Command::none()
+            }
+            Message::TagFilterCleared => {
+                self.notes.clear_selected_tags();
+                Command::none()
+            }
+            Message::TagInputChanged(value) => {
+                self.notes.set_tag_input_value(value);
+                Command::none()
+            }
+            Message::PostTagSubmitted => {
+                if self.notes.record(UndoAction::EditTags, |notes| notes.add_selected_post_tag()) {
+                    self.notes.mark_dirty();
+                }
+                Command::none()
+            }
+            Message::PostTagRemoved(tag) => {
+                if self.notes.record(UndoAction::EditTags, |notes| notes.remove_selected_post_tag(&tag)) {
+                    self.notes.mark_dirty();
+                }
+                Command::none()
+            }
+            Message::NotesStorageSaved(result) => {
+                self.notes.finish_saving(result.is_ok());
+                // our own write isn't an external change, the time taken right after it is the new baseline
+                self.storage_modified_at = result.as_ref().ok().copied().flatten();
+                let result = result.map(|_| ());
+                self.save_error = result.as_ref().err().cloned();
+                if self.is_lock_pending && !self.is_closing {
+                    self.is_lock_pending = false;
+                    if result.is_ok() {
+                        return self.lock_when_saved();
+                    }
+                }
+                if result.is_ok() && !self.is_closing && self.notes.search_results.is_some() {
+                    // storage search only sees saved notes
+                    return self.search();
+                }
+                if self.is_closing {
+                    return match (result.is_ok(), self.notes.is_dirty) {
+                        (true, false) => self.close(window::Id::MAIN),
+                        (true, true) => self.save(),
+                        (false, _) => {
+                            self.is_closing = false;
+                            Command::none()
+                        }
+                    };
+                }
+                Command::none()
+            }
+            Message::StorageCheckedBeforeSave(result) => {
+                let modified_at = result.ok().flatten();
+                let is_changed = matches!(
+                    (self.storage_modified_at, modified_at),
+                    (Some(previous), Some(modified_at)) if previous != modified_at
+                );
+                if is_changed || self.is_reload_needed {
+                    self.is_reload_needed = false;
+                    return Command::perform(self.storage.load(), Message::NotesStorageLoadedBeforeSave);
+                }
+                self.start_saving()
+            }
+            Message::NotesStorageLoadedBeforeSave(result) => {
+                match result {
+                    // the backup is older than what's open, the save sets the unreadable file aside
+                    Ok(_) if self.storage.take_restored_backup().is_some() => {
+                        eprintln!("Notes file became unreadable, saving over it from the open notes");
+                    }
+                    Ok(data) => self.notes.merge_external(data),
+                    // saved anyway, an unreadable file has nothing to keep
+                    Err(error) => eprintln!("Failed to reload notes before saving: {}", error),
+                }
+                self.start_saving()
+            }
+            Message::StorageWatchTick => {
+                if !self.can_reload() || self.is_saving() {
+                    return Command::none();
+                }
+                Command::perform(self.storage.modified_at(), Message::StorageModifiedChecked)
+            }
+            Message::StorageModifiedChecked(result) => {
+                let Ok(Some(modified_at)) = result else {
+                    return Command::none();
+                };
+                if !self.can_reload() || self.is_saving() {
+                    return Command::none();
+                }
+                let previous = self.storage_modified_at.replace(modified_at);
+                if self.is_reload_needed || previous.is_some_and(|previous| previous != modified_at) {
+                    self.is_reload_needed = false;
+                    return Command::perform(self.storage.load(), Message::NotesStorageReloaded);
+                }
+                Command::none()
+            }
+            Message::NotesStorageReloaded(result) => {
+                match result {
+                    // merged against a save that started meanwhile, it would undo that save
+                    Ok(_) if self.is_saving() || !self.can_reload() => self.is_reload_needed = true,
+                    // the backup is older than what's open, merging it would undo edits
+                    Ok(_) if self.storage.take_restored_backup().is_some() => {
+                        eprintln!("Failed to reload notes: the file is unreadable");
+                    }
+                    Ok(data) => self.notes.merge_external(data),
+                    Err(error) => eprintln!("Failed to reload notes: {}", error),
+                }
+                Command::none()
+            }
+            Message::ButtonKeepLocalPostPressed => {
+                if self.notes.keep_local_post() {
+                    self.notes.mark_dirty();
+                }
+                Command::none()
+            }
+            Message::ButtonUseExternalPostPressed => {
+                if self.notes.record(UndoAction::UseExternalVersion, |notes| notes.use_external_post()) {
+                    self.notes.mark_dirty();
+                }
+                Command::none()
+            }
+            Message::InstanceFocusPollTick => {
+                #[cfg(not(target_arch = "wasm32"))]
+                if instance_lock::take_focus_request() {
+                    return window::gain_focus(window::Id::MAIN);
+                }
+                Command::none()
+            }
+            Message::AutosaveTick => {
+                if self.can_save() && self.notes.is_dirty && !self.is_saving() {
+                    return self.save();
+                }
+                Command::none()
+            }
+            Message::WindowCloseRequested(id) => {
+                self.is_closing = true;
+                if self.is_saving() {
+                    // the running save flushes the rest once it is done
+                    return Command::none();
+                }
+                if self.can_save() && self.notes.is_dirty {
+                    return self.save();
+                }
+                self.close(id)
             }
         }
     }
 
+    fn subscription(&self) -> Subscription<Message> {
+        let autosave = if self.can_save() && self.notes.is_dirty && !self.is_saving() {
+            iced::time::every(AUTOSAVE_INTERVAL).map(|_| Message::AutosaveTick)
+        } else {
+            Subscription::none()
+        };
+        let close_requests = event::listen_with(|event, _status| match event {
+            Event::Window(id, window::Event::CloseRequested) => Some(Message::WindowCloseRequested(id)),
+            _ => None
+        });
+        let undo_toast = match self.undo_post_id {
+            Some(_) => iced::time::every(UNDO_TOAST_CHECK_INTERVAL).map(|_| Message::UndoToastTick),
+            None => Subscription::none(),
+        };
+        // bindings come from the settings, they're resolved in `update`
+        let key_presses = keyboard::on_key_press(|key, modifiers| Some(Message::KeyPressed(key, modifiers)));
+        let storage_watch = match self.can_reload() && !self.is_closing {
+            true => iced::time::every(STORAGE_WATCH_INTERVAL).map(|_| Message::StorageWatchTick),
+            false => Subscription::none(),
+        };
+        // other launches ask the lock owner to come to the front
+        #[cfg(not(target_arch = "wasm32"))]
+        let focus_requests = match self.instance_lock {
+            Some(_) => iced::time::every(INSTANCE_FOCUS_POLL_INTERVAL).map(|_| Message::InstanceFocusPollTick),
+            None => Subscription::none(),
+        };
+        #[cfg(target_arch = "wasm32")]
+        let focus_requests = Subscription::none();
+        let auto_lock = match self.auto_lock_after.is_some() && self.storage.is_encrypted() && self.load_error.is_none() {
+            true => iced::time::every(AUTO_LOCK_CHECK_INTERVAL).map(|_| Message::AutoLockTick),
+            false => Subscription::none(),
+        };
+        Subscription::batch(vec![
+            autosave, close_requests, undo_toast, key_presses, storage_watch, focus_requests, auto_lock,
+        ])
+    }
+
 
     fn theme(&self) -> Self::Theme {
         match self.is_dark_theme {
@@ -109,21 +883,72 @@ impl Application for IcedApplication {
     }
 
     fn view(&self) -> Element<Message> {
-        container(
-            column(vec![
-                common::controls_row(
-                    vec![
-                        buttons::IconButtonVariant::theme_button(
-                            &self.is_dark_theme, buttons::ButtonSize::Small,
-                        ),
+        let mut controls = vec![
+            common::storage_path_caption(self.storage.path()),
+            buttons::IconButtonVariant::button_undo(self.notes.undo_stack.can_undo() && !self.is_read_only),
+            buttons::IconButtonVariant::button_redo(self.notes.undo_stack.can_redo() && !self.is_read_only),
+        ];
+        if self.load_error.is_none() && !self.is_read_only && self.storage.supports_encryption() {
+            controls.push(buttons::IconButtonVariant::button_passphrase(self.passphrase_form.is_some()));
+            if self.storage.is_encrypted() {
+                controls.push(buttons::IconButtonVariant::button_lock());
+            }
+        }
+        controls.push(buttons::IconButtonVariant::theme_button(
+            &self.is_dark_theme, buttons::ButtonSize::Small,
+        ));
+        let mut content = vec![
+            common::controls_row(controls)
+                .into(),
+        ];
+        if self.is_read_only {
+            content.push(error_banner::read_only_banner());
+        }
+        if let Some(error) = &self.save_error {
+            content.push(error_banner::save_error_banner(error));
+        }
+        if let Some(path) = &self.restored_backup_path {
+            content.push(error_banner::restored_backup_banner(path));
+        }
+        if let Some(form) = &self.passphrase_form {
+            content.push(lock_screen::passphrase_form_view(form, self.storage.is_encrypted()));
+        }
+        if let Some(palette) = &self.palette {
+            content.push(
+                container(palette::view(palette, palette.entries(&self.notes, &self.settings)))
+                    .width(Length::Fill)
+                    .center_x()
+                    .into()
+            );
+        }
+        if let Some(trashed_post) = self.undo_post_id.and_then(|post_id| self.notes.data.trash.get(&post_id)) {
+            content.push(toast::undo_delete_toast(trashed_post.post.id, &trashed_post.post.title));
+        }
+        match &self.load_error {
+            Some(error) if error.is_locked() => {
+                content.push(lock_screen::unlock_view(
+                    self.storage.path(),
+                    &self.unlock_passphrase,
+                    self.unlock_error.as_ref(),
+                    self.is_unlocking,
+                ));
+            }
+            Some(error) => {
+                content.push(error_banner::load_error_banner(error, &self.other_file_path));
+            }
+            None => {
+                content.push(
+                    widget::row(vec![
+                        category_list::panel_categories(&self.notes),
+                        post_list::panel_posts_list(&self.notes),
+                        post_list::panel_post_selected(&self.notes, self.is_dark_theme),
                     ])
-                    .into(),
-                widget::row(vec![
-                    post_list::panel_posts_list(&self.notes),
-                    post_list::panel_post_selected(&self.notes),
-                ])
-                    .into(),
-            ])
+                        .into()
+                );
+            }
+        }
+        container(
+            column(content)
                 .width(Length::Fill)
         )
             .width(Length::Fill)
@@ -131,6 +956,62 @@ impl Application for IcedApplication {
     }
 }
 
+/// Messages that come from the user rather than timers and storage, they hold off the auto-lock
+fn is_user_input(message: &Message) -> bool {
+    !matches!(
+        message,
+        Message::NotesStorageLoaded(_)
+            | Message::NotesStorageSaved(_)
+            | Message::NotesStorageSetAside(_)
+            | Message::NotesStorageReloaded(_)
+            | Message::NotesStorageUnlocked(_)
+            | Message::StorageSearchCompleted(..)
+            | Message::StorageWatchTick
+            | Message::StorageModifiedChecked(_)
+            | Message::StorageCheckedBeforeSave(_)
+            | Message::NotesStorageLoadedBeforeSave(_)
+            | Message::PassphraseChanged(_)
+            | Message::AutosaveTick
+            | Message::AutoLockTick
+            | Message::InstanceFocusPollTick
+            | Message::UndoToastTick
+            | Message::UndoToastExpired
+            | Message::WindowCloseRequested(_)
+    )
+}
 
-
-
+/// Messages that change the notes, they're dropped while the notes are read-only.
+/// Shortcuts and palette commands end up as one of these
+fn is_edit(message: &Message) -> bool {
+    match message {
+        Message::OnTextEditorAction(action) => action.is_edit(),
+        message => matches!(
+            message,
+            Message::ButtonCreatePressed
+                | Message::ButtonDeletePressed(_)
+                | Message::PostTitleUpdated(_)
+                | Message::PostContentUpdated(_)
+                | Message::ButtonCategoryCreatePressed
+                | Message::ButtonCategoryDeletePressed(_)
+                | Message::CategoryTitleUpdated(..)
+                | Message::PostCategorySelected(_)
+                | Message::PostTagSubmitted
+                | Message::PostTagRemoved(_)
+                | Message::ButtonRewriteLinksPressed
+                | Message::ButtonRestoreRevisionPressed(_)
+                | Message::ButtonUndoDeletePressed(_)
+                | Message::ButtonRestorePostPressed(_)
+                | Message::ButtonPurgePostPressed(_)
+                | Message::ButtonEmptyTrashPressed
+                | Message::Undo
+                | Message::Redo
+                | Message::ButtonMovePostPressed(..)
+                | Message::ButtonPinPostPressed(_)
+                | Message::ButtonFavouritePostPressed(_)
+                | Message::ButtonKeepLocalPostPressed
+                | Message::ButtonUseExternalPostPressed
+                | Message::ButtonPassphrasePressed
+                | Message::PassphraseFormSubmitted
+        ),
+    }
+}
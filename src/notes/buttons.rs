@@ -1,4 +1,5 @@
 use iced::{alignment, Border, Color, Element, theme, Theme, Vector, widget};
+use uuid::Uuid;
 
 use crate::notes::{FontSize, Message, models};
 use crate::notes::buttons;
@@ -91,13 +92,140 @@ impl IconButtonVariant {
 
     pub fn button_post_delete(post: &models::Post) -> Element<Message> {
         icon_button(
-            Icons::DeleteForever,
-            "Remove post",
+            Icons::Delete,
+            "Move to trash",
             ButtonSize::default(),
             Some(Message::ButtonDeletePressed(post.id)),
         )
             .into()
     }
+    pub fn button_post_pin(post: &models::Post) -> Element<Message> {
+        let (variant, label) = if post.is_pinned {
+            (IconButtonVariant::Filled(ButtonSize::default()), "Unpin")
+        } else {
+            (IconButtonVariant::Outlined(ButtonSize::default()), "Pin to the top")
+        };
+        let btn = variant.render_button(Icons::PushPin, Some(Message::ButtonPinPostPressed(post.id)));
+        IconButtonVariant::render_button_with_tooltip(btn, label)
+    }
+    pub fn button_post_favourite(post: &models::Post) -> Element<Message> {
+        let (icon, label) = if post.is_favourite {
+            (Icons::Star, "Remove from favourites")
+        } else {
+            (Icons::StarBorder, "Add to favourites")
+        };
+        icon_button(
+            icon,
+            label,
+            ButtonSize::default(),
+            Some(Message::ButtonFavouritePostPressed(post.id)),
+        )
+    }
+    pub fn button_trash<'a>(is_trash_open: bool) -> Element<'a, Message> {
+        let variant = if is_trash_open {
+            IconButtonVariant::Filled(ButtonSize::default())
+        } else {
+            IconButtonVariant::Outlined(ButtonSize::default())
+        };
+        let btn = variant.render_button(Icons::Delete, Some(Message::ButtonTrashPressed));
+        IconButtonVariant::render_button_with_tooltip(btn, "Trash")
+    }
+    pub fn button_post_restore<'a>(post_id: Uuid) -> Element<'a, Message> {
+        icon_button(
+            Icons::RestoreFromTrash,
+            "Restore",
+            ButtonSize::Small,
+            Some(Message::ButtonRestorePostPressed(post_id)),
+        )
+    }
+    pub fn button_post_purge<'a>(post_id: Uuid) -> Element<'a, Message> {
+        icon_button(
+            Icons::DeleteForever,
+            "Delete forever",
+            ButtonSize::Small,
+            Some(Message::ButtonPurgePostPressed(post_id)),
+        )
+    }
+    pub fn button_post_move<'a>(post_id: Uuid, offset: isize) -> Element<'a, Message> {
+        let (icon, label) = match offset < 0 {
+            true => (Icons::ArrowUpward, "Move up"),
+            false => (Icons::ArrowDownward, "Move down"),
+        };
+        icon_button(
+            icon,
+            label,
+            ButtonSize::Small,
+            Some(Message::ButtonMovePostPressed(post_id, offset)),
+        )
+    }
+    pub fn button_category_add<'a>() -> Element<'a, Message> {
+        icon_button(
+            Icons::CreateNewFolder,
+            "Create category",
+            ButtonSize::default(),
+            Some(Message::ButtonCategoryCreatePressed),
+        )
+    }
+
+    pub fn button_category_delete(category: &models::Category) -> Element<Message> {
+        icon_button(
+            Icons::DeleteForever,
+            "Remove category",
+            ButtonSize::Small,
+            Some(Message::ButtonCategoryDeletePressed(category.id)),
+        )
+    }
+    pub fn button_editor_mode<'a>(mode: models::EditorMode, selected_mode: models::EditorMode) -> Element<'a, Message> {
+        let (icon, label) = match mode {
+            models::EditorMode::Edit => (Icons::Edit, "Edit"),
+            models::EditorMode::Preview => (Icons::Visibility, "Preview"),
+            models::EditorMode::Split => (Icons::VerticalSplit, "Edit and preview"),
+        };
+        let variant = if mode == selected_mode {
+            IconButtonVariant::Filled(ButtonSize::Small)
+        } else {
+            IconButtonVariant::Outlined(ButtonSize::Small)
+        };
+        let btn = variant.render_button(icon, Some(Message::EditorModeSelected(mode)));
+        IconButtonVariant::render_button_with_tooltip(btn, label)
+    }
+    pub fn button_history<'a>(is_history_open: bool) -> Element<'a, Message> {
+        let variant = if is_history_open {
+            IconButtonVariant::Filled(ButtonSize::Small)
+        } else {
+            IconButtonVariant::Outlined(ButtonSize::Small)
+        };
+        let btn = variant.render_button(Icons::History, Some(Message::ButtonHistoryPressed));
+        IconButtonVariant::render_button_with_tooltip(btn, "History")
+    }
+    pub fn button_passphrase<'a>(is_form_open: bool) -> Element<'a, Message> {
+        let variant = if is_form_open {
+            IconButtonVariant::Filled(ButtonSize::Small)
+        } else {
+            IconButtonVariant::Outlined(ButtonSize::Small)
+        };
+        let btn = variant.render_button(Icons::VpnKey, Some(Message::ButtonPassphrasePressed));
+        IconButtonVariant::render_button_with_tooltip(btn, "Encryption")
+    }
+    pub fn button_lock<'a>() -> Element<'a, Message> {
+        icon_button(Icons::Lock, "Lock now", ButtonSize::Small, Some(Message::ButtonLockPressed))
+    }
+    pub fn button_undo<'a>(can_undo: bool) -> Element<'a, Message> {
+        icon_button(
+            Icons::Undo,
+            "Undo (Ctrl+Z)",
+            ButtonSize::Small,
+            can_undo.then_some(Message::Undo),
+        )
+    }
+    pub fn button_redo<'a>(can_redo: bool) -> Element<'a, Message> {
+        icon_button(
+            Icons::Redo,
+            "Redo (Ctrl+Shift+Z)",
+            ButtonSize::Small,
+            can_redo.then_some(Message::Redo),
+        )
+    }
     pub fn theme_button<'a>(is_theme_dark: &bool, button_size: buttons::ButtonSize) -> Element<'a, Message> {
         icon_button(match is_theme_dark {
             true => Icons::LightMode,
@@ -0,0 +1,100 @@
+use iced::{Alignment, Element, font, Font, Length, theme, widget};
+
+use crate::notes::{buttons, FontSize, Message, models, tag_list};
+use crate::notes::icons::{Icons, material_icon};
+
+pub const PANEL_WIDTH: f32 = (FontSize::Body as i32 * 10) as f32;
+
+fn panel_category_list_item<'a>(icon: Icons, label: &str, count: usize, is_selected: bool, on_press: Message) -> Element<'a, Message> {
+    let btn = widget::button(
+        widget::row(vec![
+            material_icon(icon).size(FontSize::Body).into(),
+            widget::text(label).size(14).width(Length::Fill).into(),
+            widget::text(count).size(FontSize::Caption).into(),
+        ])
+            .spacing(6)
+            .align_items(Alignment::Center)
+    )
+        .width(PANEL_WIDTH)
+        .on_press(on_press);
+    if is_selected {
+        btn.style(theme::Button::Positive).into()
+    } else {
+        btn.style(theme::Button::Text).into()
+    }
+}
+
+fn panel_category_selected(category: &models::Category) -> Element<Message> {
+    let category_id = category.id;
+    widget::column(vec![
+        widget::text("Category").size(FontSize::Caption).into(),
+        widget::row(vec![
+            widget::text_input("Category title", &category.title)
+                .on_input(move |title| Message::CategoryTitleUpdated(category_id, title))
+                .size(14)
+                .into(),
+            buttons::IconButtonVariant::button_category_delete(category),
+        ])
+            .spacing(4)
+            .align_items(Alignment::Center)
+            .into(),
+    ])
+        .spacing(4)
+        .width(PANEL_WIDTH)
+        .into()
+}
+
+
+pub fn panel_categories(state: &models::NotesState) -> Element<Message> {
+    let all_posts = panel_category_list_item(
+        Icons::Folder,
+        "All notes",
+        state.data.posts.len(),
+        state.selected_category_id.is_none() && !state.is_favourites_selected,
+        Message::SelectCategory(None),
+    );
+    let favourites = panel_category_list_item(
+        Icons::Star,
+        "Favourites",
+        state.data.posts.values().filter(|post| post.is_favourite).count(),
+        state.is_favourites_selected,
+        Message::SelectFavourites,
+    );
+    let categories: Element<Message> = state.get_ordered_categories_list()
+        .into_iter()
+        .fold(
+            widget::Column::new().spacing(4).push(all_posts).push(favourites),
+            |col, category| {
+                col.push(
+                    panel_category_list_item(
+                        Icons::Folder,
+                        &category.title,
+                        state.data.posts.values()
+                            .filter(|post| post.category_id == Some(category.id))
+                            .count(),
+                        category.is_selected(&state.selected_category_id),
+                        Message::SelectCategory(Some(category.id)),
+                    ))
+            }).into();
+
+    let mut panel = widget::Column::new()
+        .spacing(8)
+        .push(buttons::IconButtonVariant::button_category_add())
+        .push(widget::text("Categories").size(FontSize::Caption).font(Font {
+            weight: font::Weight::Bold,
+            ..Font::default()
+        }))
+        .push(widget::scrollable(categories).height(Length::FillPortion(1)));
+
+    if let Some(category) = state.get_selected_category() {
+        panel = panel.push(panel_category_selected(category));
+    }
+    if !state.tag_counts.is_empty() {
+        panel = panel.push(tag_list::panel_tags(state));
+    }
+
+    widget::container(panel)
+        .padding([0, 8])
+        .height(Length::Fill)
+        .into()
+}
@@ -0,0 +1,181 @@
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use iced::{Color, Font};
+use iced::advanced::text::highlighter::{self, Format};
+use syntect::highlighting::{self, HighlightState, RangedHighlightIterator, Theme, ThemeSet};
+use syntect::parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet};
+
+const LIGHT_THEME: &str = "InspiredGitHub";
+const DARK_THEME: &str = "base16-ocean.dark";
+const FENCE_COLOR: Color = Color { r: 0.5, g: 0.5, b: 0.5, a: 1.0 };
+
+fn syntaxes() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn theme(is_dark_theme: bool) -> &'static Theme {
+    static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+    let themes = THEMES.get_or_init(ThemeSet::load_defaults);
+    &themes.themes[if is_dark_theme { DARK_THEME } else { LIGHT_THEME }]
+}
+
+/// Syntax for the info string of a fence, e.g. `rust` in ```` ```rust ````
+fn find_syntax(language: &str) -> Option<&'static SyntaxReference> {
+    let token = language.split_whitespace().next()?;
+    syntaxes().find_syntax_by_token(token)
+}
+
+fn to_color(color: highlighting::Color) -> Color {
+    Color::from_rgba8(color.r, color.g, color.b, color.a as f32 / 255.0)
+}
+
+/// Opening fence of a code block, returns the fence and the info string after it
+fn parse_fence(line: &str) -> Option<(String, &str)> {
+    let trimmed = line.trim_start();
+    let marker = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let length = trimmed.chars().take_while(|c| *c == marker).count();
+    if length < 3 {
+        return None;
+    }
+    let fence = marker.to_string().repeat(length);
+    Some((fence, trimmed[length..].trim()))
+}
+
+fn is_closing_fence(line: &str, fence: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.starts_with(fence) && trimmed.chars().all(|c| Some(c) == fence.chars().next())
+}
+
+/// Splits a code snippet into lines of `(fragment, color)` for the rendered markdown view
+pub fn highlight_code(code: &str, language: &str, is_dark_theme: bool) -> Vec<Vec<(String, Option<Color>)>> {
+    let syntax = match find_syntax(language) {
+        Some(syntax) => syntax,
+        None => return code.lines().map(|line| vec![(line.to_string(), None)]).collect(),
+    };
+    let theme = theme(is_dark_theme);
+    let mut lines = syntect::easy::HighlightLines::new(syntax, theme);
+    syntect::util::LinesWithEndings::from(code)
+        .map(|line| match lines.highlight_line(line, syntaxes()) {
+            Ok(ranges) => ranges.into_iter()
+                .map(|(style, text)| (text.trim_end_matches('\n').to_string(), Some(to_color(style.foreground))))
+                .collect(),
+            Err(_) => vec![(line.trim_end_matches('\n').to_string(), None)],
+        })
+        .collect()
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub is_dark_theme: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Highlight {
+    color: Option<Color>,
+    font: Option<Font>,
+}
+
+impl Highlight {
+    pub fn to_format(&self) -> Format<Font> {
+        Format {
+            color: self.color,
+            font: self.font,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum LineState {
+    Text,
+    Code {
+        fence: String,
+        parser: Option<(ParseState, HighlightState)>,
+    },
+}
+
+/// Editor highlighter for markdown notes, leaves prose alone and highlights fenced code blocks
+/// with the syntax named after the opening fence
+pub struct CodeHighlighter {
+    theme: &'static Theme,
+    /// `states[n]` is the state at the start of line `n`
+    states: Vec<LineState>,
+}
+
+impl CodeHighlighter {
+    fn highlight(&self, state: LineState, line: &str) -> (LineState, Vec<(Range<usize>, Highlight)>) {
+        let fence_highlight = vec![(0..line.len(), Highlight {
+            color: Some(FENCE_COLOR),
+            font: Some(Font::MONOSPACE),
+        })];
+        match state {
+            LineState::Text => match parse_fence(line) {
+                Some((fence, language)) => {
+                    let parser = find_syntax(language).map(|syntax| (
+                        ParseState::new(syntax),
+                        HighlightState::new(&highlighting::Highlighter::new(self.theme), ScopeStack::new()),
+                    ));
+                    (LineState::Code { fence, parser }, fence_highlight)
+                }
+                None => (LineState::Text, Vec::new()),
+            },
+            LineState::Code { fence, .. } if is_closing_fence(line, &fence) => (LineState::Text, fence_highlight),
+            LineState::Code { fence, parser: None } => {
+                let highlights = vec![(0..line.len(), Highlight {
+                    color: None,
+                    font: Some(Font::MONOSPACE),
+                })];
+                (LineState::Code { fence, parser: None }, highlights)
+            }
+            LineState::Code { fence, parser: Some((mut parse_state, mut highlight_state)) } => {
+                // the bundled syntaxes expect lines with their newline
+                let line_with_ending = format!("{}\n", line);
+                let ops = parse_state.parse_line(&line_with_ending, syntaxes()).unwrap_or_default();
+                let highlighter = highlighting::Highlighter::new(self.theme);
+                let highlights = RangedHighlightIterator::new(&mut highlight_state, &ops, &line_with_ending, &highlighter)
+                    .filter(|(_, _, range)| range.start < line.len())
+                    .map(|(style, _, range)| (range.start..range.end.min(line.len()), Highlight {
+                        color: Some(to_color(style.foreground)),
+                        font: Some(Font::MONOSPACE),
+                    }))
+                    .collect();
+                (LineState::Code { fence, parser: Some((parse_state, highlight_state)) }, highlights)
+            }
+        }
+    }
+}
+
+impl highlighter::Highlighter for CodeHighlighter {
+    type Settings = Settings;
+    type Highlight = Highlight;
+    type Iterator<'a> = std::vec::IntoIter<(Range<usize>, Highlight)>;
+
+    fn new(settings: &Settings) -> Self {
+        Self {
+            theme: theme(settings.is_dark_theme),
+            states: vec![LineState::Text],
+        }
+    }
+
+    fn update(&mut self, new_settings: &Settings) {
+        self.theme = theme(new_settings.is_dark_theme);
+        self.change_line(0);
+    }
+
+    fn change_line(&mut self, line: usize) {
+        self.states.truncate(line + 1);
+    }
+
+    fn highlight_line(&mut self, line: &str) -> Self::Iterator<'_> {
+        let state = self.states.last().cloned().unwrap_or(LineState::Text);
+        let (next_state, highlights) = self.highlight(state, line);
+        self.states.push(next_state);
+        highlights.into_iter()
+    }
+
+    fn current_line(&self) -> usize {
+        self.states.len() - 1
+    }
+}
@@ -1,13 +1,19 @@
+use std::path::Path;
+
 use iced::{Alignment, alignment, Element, Length, widget};
 use iced::widget::{row, Text};
 
-use crate::notes::{DEFAULT_DATE_FORMAT, Message};
+use crate::notes::{DEFAULT_DATE_FORMAT, FontSize, Message};
 use crate::notes::models::Post;
 
 pub fn post_date_created(post: &Post) -> Text {
     Text::new(format!("{}", post.created_at.format(DEFAULT_DATE_FORMAT)))
 }
 
+pub fn post_date_updated(post: &Post) -> Text {
+    Text::new(format!("Edited {}", post.updated_at.format(DEFAULT_DATE_FORMAT)))
+}
+
 
 pub fn controls_row(content: Vec<Element<Message>>) -> Element<Message> {
     widget::Container::new(
@@ -19,4 +25,60 @@ pub fn controls_row(content: Vec<Element<Message>>) -> Element<Message> {
         .width(Length::Fill)
         .align_x(alignment::Horizontal::Right)
         .into()
-}
\ No newline at end of file
+}
+
+pub fn storage_path_caption<'a>(path: &Path) -> Element<'a, Message> {
+    Text::new(format!("{}", path.display()))
+        .size(FontSize::Caption)
+        .into()
+}
+
+
+/// Schemes a link in a note may open, anything else could start a local program or script
+const ALLOWED_URL_SCHEMES: [&str; 3] = ["http", "https", "mailto"];
+
+fn is_allowed_url(url: &str) -> bool {
+    url.split_once(':').is_some_and(|(scheme, _)| {
+        ALLOWED_URL_SCHEMES.iter().any(|allowed| scheme.eq_ignore_ascii_case(allowed))
+    })
+}
+
+/// Opens an external link from rendered markdown in the system browser
+#[cfg(not(target_arch = "wasm32"))]
+pub fn open_url(url: &str) {
+    if !is_allowed_url(url) {
+        eprintln!("Not opening {}: only http, https and mailto links are opened", url);
+        return;
+    }
+    if let Err(error) = open::that_detached(url) {
+        eprintln!("Failed to open {}: {}", url, error);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn open_url(url: &str) {
+    if !is_allowed_url(url) {
+        return;
+    }
+    if let Some(window) = web_sys::window() {
+        let _ = window.open_with_url_and_target(url, "_blank");
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_web_and_mail_links_are_allowed() {
+        assert!(is_allowed_url("https://example.com/page"));
+        assert!(is_allowed_url("HTTP://example.com"));
+        assert!(is_allowed_url("mailto:someone@example.com"));
+        assert!(!is_allowed_url("file:///etc/passwd"));
+        assert!(!is_allowed_url("javascript:alert(1)"));
+        assert!(!is_allowed_url("/usr/bin/calculator"));
+        assert!(!is_allowed_url("C:\\Windows\\System32\\calc.exe"));
+        assert!(!is_allowed_url("smb://host/share"));
+    }
+}
@@ -0,0 +1,108 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffKind {
+    Same,
+    Added,
+    Removed,
+}
+
+/// Line diff of two texts based on their longest common subsequence of lines
+pub fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<(DiffKind, &'a str)> {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+
+    // common head and tail are cut off first, edits usually touch a small part of a note
+    let prefix = old_lines.iter().zip(&new_lines).take_while(|(a, b)| a == b).count();
+    let suffix = old_lines[prefix..].iter().rev()
+        .zip(new_lines[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old_lines[prefix..old_lines.len() - suffix];
+    let new_middle = &new_lines[prefix..new_lines.len() - suffix];
+
+    // lengths[i][j] is the common subsequence length of old_middle[i..] and new_middle[j..]
+    let mut lengths = vec![vec![0u32; new_middle.len() + 1]; old_middle.len() + 1];
+    for i in (0..old_middle.len()).rev() {
+        for j in (0..new_middle.len()).rev() {
+            lengths[i][j] = if old_middle[i] == new_middle[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff: Vec<(DiffKind, &str)> = old_lines[..prefix].iter()
+        .map(|line| (DiffKind::Same, *line))
+        .collect();
+    let (mut i, mut j) = (0, 0);
+    while i < old_middle.len() || j < new_middle.len() {
+        if i < old_middle.len() && j < new_middle.len() && old_middle[i] == new_middle[j] {
+            diff.push((DiffKind::Same, old_middle[i]));
+            i += 1;
+            j += 1;
+        } else if i < old_middle.len() && (j == new_middle.len() || lengths[i + 1][j] >= lengths[i][j + 1]) {
+            diff.push((DiffKind::Removed, old_middle[i]));
+            i += 1;
+        } else {
+            diff.push((DiffKind::Added, new_middle[j]));
+            j += 1;
+        }
+    }
+    diff.extend(old_lines[old_lines.len() - suffix..].iter().map(|line| (DiffKind::Same, *line)));
+    diff
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DiffKind::{Added, Removed, Same};
+
+    #[test]
+    fn equal_texts_are_all_same() {
+        assert_eq!(diff_lines("a\nb", "a\nb"), vec![(Same, "a"), (Same, "b")]);
+        assert!(diff_lines("", "").is_empty());
+    }
+
+    #[test]
+    fn lines_added_to_or_removed_from_empty_text() {
+        assert_eq!(diff_lines("", "a\nb"), vec![(Added, "a"), (Added, "b")]);
+        assert_eq!(diff_lines("a\nb", ""), vec![(Removed, "a"), (Removed, "b")]);
+    }
+
+    #[test]
+    fn changed_line_is_removed_then_added_between_common_lines() {
+        assert_eq!(
+            diff_lines("head\nold\ntail", "head\nnew\ntail"),
+            vec![(Same, "head"), (Removed, "old"), (Added, "new"), (Same, "tail")],
+        );
+    }
+
+    #[test]
+    fn common_lines_in_the_middle_are_kept() {
+        assert_eq!(
+            diff_lines("a\nx\nb\nc", "a\nb\ny\nc"),
+            vec![(Same, "a"), (Removed, "x"), (Same, "b"), (Added, "y"), (Same, "c")],
+        );
+    }
+
+    #[test]
+    fn repeated_lines_do_not_overlap_prefix_and_suffix() {
+        assert_eq!(diff_lines("a\na", "a"), vec![(Same, "a"), (Removed, "a")]);
+        assert_eq!(diff_lines("a", "a\na"), vec![(Same, "a"), (Added, "a")]);
+    }
+
+    #[test]
+    fn both_sides_can_be_rebuilt_from_the_diff() {
+        let (old, new) = ("one\ntwo\nthree\nfour\nfive", "zero\none\nthree\n3.5\nfive\nsix");
+        let diff = diff_lines(old, new);
+        let side = |skipped: DiffKind| diff.iter()
+            .filter(|(kind, _)| *kind != skipped)
+            .map(|(_, line)| *line)
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(side(Added), old);
+        assert_eq!(side(Removed), new);
+        assert_eq!(diff.iter().filter(|(kind, _)| *kind == Same).count(), 3);
+    }
+}
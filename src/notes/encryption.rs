@@ -0,0 +1,237 @@
+use std::fmt;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::aead::rand_core::RngCore;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
+
+const ENVELOPE_VERSION: u32 = 1;
+const KDF_ALGORITHM: &str = "argon2id";
+const KEY_LENGTH: usize = 32;
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 24;
+
+/// An encrypted notes file, still a JSON document so it's told apart from a plain one
+/// and fits into `localStorage`
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    encrypted: EncryptedDocument,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptedDocument {
+    version: u32,
+    kdf: KdfParams,
+    /// Base64 of the XChaCha20-Poly1305 nonce, a new one for every save
+    nonce: String,
+    /// Base64 of the sealed notes document
+    ciphertext: String,
+}
+
+/// How the key is derived from the passphrase, kept in the file so the parameters can change later
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    algorithm: String,
+    /// Base64 of the random salt
+    salt: String,
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+}
+
+/// Key derived from the passphrase, together with the parameters it was derived with
+#[derive(Clone, PartialEq, Eq)]
+pub struct SealingKey {
+    key: [u8; KEY_LENGTH],
+    params: KdfParams,
+}
+
+
+impl KdfParams {
+    fn generate() -> Self {
+        let mut salt = [0; SALT_LENGTH];
+        OsRng.fill_bytes(&mut salt);
+        Self {
+            algorithm: String::from(KDF_ALGORITHM),
+            salt: BASE64.encode(salt),
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl SealingKey {
+    /// Derives a key with a new random salt, used when the passphrase is set
+    pub fn generate(passphrase: &str) -> Result<Self, String> {
+        Self::derive(passphrase, KdfParams::generate())
+    }
+
+    /// Slow on purpose, callers run it off the UI thread
+    pub fn derive(passphrase: &str, params: KdfParams) -> Result<Self, String> {
+        if params.algorithm != KDF_ALGORITHM {
+            return Err(format!("unknown key derivation \"{}\"", params.algorithm));
+        }
+        let salt = BASE64.decode(&params.salt).map_err(|error| error.to_string())?;
+        let argon2_params = Params::new(params.memory_kib, params.iterations, params.parallelism, Some(KEY_LENGTH))
+            .map_err(|error| error.to_string())?;
+        let mut key = [0; KEY_LENGTH];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, argon2_params)
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|error| error.to_string())?;
+        Ok(Self { key, params })
+    }
+
+    /// Whether `passphrase` is the one this key was derived from
+    pub fn matches(&self, passphrase: &str) -> bool {
+        Self::derive(passphrase, self.params.clone()).is_ok_and(|key| key == *self)
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(Key::from_slice(&self.key))
+    }
+}
+
+/// The key bytes never end up in logs
+impl fmt::Debug for SealingKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SealingKey").finish_non_exhaustive()
+    }
+}
+
+impl Drop for SealingKey {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+
+/// Whether the contents of a notes file are sealed with `seal`
+pub fn is_encrypted(contents: &str) -> bool {
+    serde_json::from_str::<Envelope>(contents).is_ok()
+}
+
+/// Encrypts a notes document into an encrypted file
+pub fn seal(key: &SealingKey, plaintext: &str) -> Result<String, String> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = key.cipher()
+        .encrypt(&nonce, plaintext.as_bytes())
+        .map_err(|_| String::from("encryption failed"))?;
+    let envelope = Envelope {
+        encrypted: EncryptedDocument {
+            version: ENVELOPE_VERSION,
+            kdf: key.params.clone(),
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        },
+    };
+    serde_json::to_string(&envelope).map_err(|error| error.to_string())
+}
+
+/// Decrypts an encrypted file with a key derived earlier,
+/// fails when the file was sealed with another passphrase or was tampered with
+pub fn open(key: &SealingKey, contents: &str) -> Result<String, String> {
+    let document = read_envelope(contents)?;
+    if document.kdf != key.params {
+        return Err(String::from("the passphrase was changed, unlock again"));
+    }
+    decrypt(key, &document)
+}
+
+/// Derives the key from the passphrase and the parameters in the file and decrypts it
+pub fn unlock(passphrase: &str, contents: &str) -> Result<(SealingKey, String), String> {
+    let document = read_envelope(contents)?;
+    let key = SealingKey::derive(passphrase, document.kdf.clone())?;
+    let plaintext = decrypt(&key, &document)?;
+    Ok((key, plaintext))
+}
+
+fn read_envelope(contents: &str) -> Result<EncryptedDocument, String> {
+    let envelope: Envelope = serde_json::from_str(contents).map_err(|error| error.to_string())?;
+    if envelope.encrypted.version > ENVELOPE_VERSION {
+        return Err(format!("unsupported encryption version {}", envelope.encrypted.version));
+    }
+    Ok(envelope.encrypted)
+}
+
+fn decrypt(key: &SealingKey, document: &EncryptedDocument) -> Result<String, String> {
+    let nonce = BASE64.decode(&document.nonce).map_err(|error| error.to_string())?;
+    if nonce.len() != NONCE_LENGTH {
+        return Err(String::from("invalid nonce"));
+    }
+    let ciphertext = BASE64.decode(&document.ciphertext).map_err(|error| error.to_string())?;
+    let plaintext = key.cipher()
+        .decrypt(XNonce::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| String::from("wrong passphrase"))?;
+    String::from_utf8(plaintext).map_err(|error| error.to_string())
+}
+
+
+/// A key derived with the cheapest parameters, so tests don't wait for the real derivation
+#[cfg(test)]
+pub fn test_key(passphrase: &str) -> SealingKey {
+    let params = KdfParams {
+        memory_kib: Params::MIN_M_COST,
+        iterations: Params::MIN_T_COST,
+        parallelism: Params::MIN_P_COST,
+        ..KdfParams::generate()
+    };
+    SealingKey::derive(passphrase, params).unwrap()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAINTEXT: &str = r#"{"version": 6, "posts": {}}"#;
+
+    #[test]
+    fn sealed_notes_open_with_the_same_key_and_passphrase() {
+        let key = test_key("passphrase");
+        let sealed = seal(&key, PLAINTEXT).unwrap();
+
+        assert!(!sealed.contains("posts"));
+        assert_eq!(open(&key, &sealed).unwrap(), PLAINTEXT);
+        let (unlocked_key, plaintext) = unlock("passphrase", &sealed).unwrap();
+        assert_eq!(plaintext, PLAINTEXT);
+        assert_eq!(unlocked_key, key);
+    }
+
+    #[test]
+    fn wrong_passphrase_is_refused() {
+        let key = test_key("passphrase");
+        let sealed = seal(&key, PLAINTEXT).unwrap();
+
+        assert_eq!(unlock("wrong", &sealed).err(), Some(String::from("wrong passphrase")));
+        assert!(!key.matches("wrong"));
+        assert!(key.matches("passphrase"));
+    }
+
+    #[test]
+    fn tampered_ciphertext_is_refused() {
+        let key = test_key("passphrase");
+        let mut envelope: Envelope = serde_json::from_str(&seal(&key, PLAINTEXT).unwrap()).unwrap();
+        let mut ciphertext = BASE64.decode(&envelope.encrypted.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        envelope.encrypted.ciphertext = BASE64.encode(ciphertext);
+        let tampered = serde_json::to_string(&envelope).unwrap();
+
+        assert!(open(&key, &tampered).is_err());
+        assert!(unlock("passphrase", &tampered).is_err());
+    }
+
+    #[test]
+    fn only_sealed_notes_are_encrypted() {
+        let sealed = seal(&test_key("passphrase"), PLAINTEXT).unwrap();
+
+        assert!(is_encrypted(&sealed));
+        assert!(!is_encrypted(PLAINTEXT));
+        assert!(!is_encrypted(r#"{"encrypted": true}"#));
+        assert!(!is_encrypted("not json"));
+    }
+}
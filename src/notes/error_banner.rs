@@ -0,0 +1,141 @@
+use std::path::Path;
+
+use iced::{Alignment, Color, Element, Length, theme, widget};
+
+use crate::notes::{FontSize, Message};
+use crate::notes::buttons::{ButtonSize, icon_button};
+use crate::notes::icons::{Icons, material_icon};
+use crate::notes::storage::StorageError;
+
+const ERROR_COLOR: Color = Color { r: 0.85, g: 0.2, b: 0.2, a: 1.0 };
+
+fn error_details(error: &StorageError) -> Option<String> {
+    let mut details = Vec::new();
+    if let Some((line, column)) = error.line_column() {
+        details.push(format!("line {}, column {}", line, column));
+    }
+    if let Some(os_error) = error.os_error() {
+        details.push(format!("os error {}", os_error));
+    }
+    if details.is_empty() {
+        None
+    } else {
+        Some(details.join(", "))
+    }
+}
+
+fn error_text<'a>(title: &str, error: &StorageError) -> Element<'a, Message> {
+    let mut text = widget::Column::new()
+        .spacing(2)
+        .width(Length::Fill)
+        .push(widget::text(title).size(FontSize::Body))
+        .push(widget::text(error).size(FontSize::Tooltip));
+    if let Some(details) = error_details(error) {
+        text = text.push(widget::text(details).size(FontSize::Caption));
+    }
+    widget::row(vec![
+        material_icon(Icons::Error)
+            .size(FontSize::Header1)
+            .style(theme::Text::Color(ERROR_COLOR))
+            .into(),
+        text.into(),
+    ])
+        .spacing(8)
+        .align_items(Alignment::Center)
+        .into()
+}
+
+fn banner_container(content: Element<Message>) -> Element<Message> {
+    widget::container(content)
+        .width(Length::Fill)
+        .padding(8)
+        .style(theme::Container::Box)
+        .into()
+}
+
+/// Shown while another instance owns the storage
+pub fn read_only_banner<'a>() -> Element<'a, Message> {
+    banner_container(
+        widget::text("These notes are open in another window, they can only be read here")
+            .size(FontSize::Tooltip)
+            .into()
+    )
+}
+
+pub fn save_error_banner(error: &StorageError) -> Element<Message> {
+    banner_container(
+        widget::row(vec![
+            error_text("Notes are not saved", error),
+            icon_button(Icons::Close, "Dismiss", ButtonSize::Small, Some(Message::SaveErrorDismissed)),
+        ])
+            .spacing(8)
+            .align_items(Alignment::Center)
+            .into()
+    )
+}
+
+/// Shown after the notes were loaded from the backup because the storage file couldn't be read
+pub fn restored_backup_banner(path: &Path) -> Element<'_, Message> {
+    let text = widget::column(vec![
+        widget::text("Notes were restored from the backup").size(FontSize::Body).into(),
+        widget::text(format!(
+            "The notes file could not be read, recent changes may be missing. \
+            Loaded {}, the unreadable file is kept next to it on the next save.",
+            path.display(),
+        ))
+            .size(FontSize::Tooltip)
+            .into(),
+    ])
+        .spacing(2)
+        .width(Length::Fill);
+    banner_container(
+        widget::row(vec![
+            material_icon(Icons::Error)
+                .size(FontSize::Header1)
+                .style(theme::Text::Color(ERROR_COLOR))
+                .into(),
+            text.into(),
+            icon_button(Icons::Close, "Dismiss", ButtonSize::Small, Some(Message::RestoredBackupDismissed)),
+        ])
+            .spacing(8)
+            .align_items(Alignment::Center)
+            .into()
+    )
+}
+
+/// Shown instead of the notes while the storage file can not be loaded
+pub fn load_error_banner<'a>(error: &StorageError, other_file_path: &str) -> Element<'a, Message> {
+    let actions = widget::row(vec![
+        widget::button(widget::text("Retry").size(FontSize::Tooltip))
+            .on_press(Message::ButtonRetryLoadPressed)
+            .into(),
+        widget::button(widget::text("Start fresh").size(FontSize::Tooltip))
+            .style(theme::Button::Destructive)
+            .on_press(Message::ButtonStartFreshPressed)
+            .into(),
+        widget::text_input("Path to another notes file", other_file_path)
+            .on_input(Message::OtherFilePathChanged)
+            .on_submit(Message::ButtonOpenOtherFilePressed)
+            .size(FontSize::Tooltip)
+            .into(),
+        widget::button(widget::text("Open").size(FontSize::Tooltip))
+            .on_press_maybe(
+                (!other_file_path.trim().is_empty()).then_some(Message::ButtonOpenOtherFilePressed)
+            )
+            .into(),
+    ])
+        .spacing(8)
+        .align_items(Alignment::Center);
+
+    banner_container(
+        widget::column(vec![
+            error_text("Notes could not be loaded", error),
+            widget::text("Start fresh keeps the unreadable file next to the new one.")
+                .size(FontSize::Caption)
+                .into(),
+            actions.into(),
+        ])
+            .spacing(8)
+            .into()
+    )
+}
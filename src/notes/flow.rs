@@ -0,0 +1,160 @@
+use iced::{Element, Event, event, Length, mouse, Point, Rectangle, Size};
+use iced::advanced::{Clipboard, layout, Layout, renderer, Shell, widget};
+use iced::advanced::widget::{Tree, Widget};
+
+/// Lays children out left to right and wraps them onto the next line when the width runs out,
+/// used for rich text made of separately styled `text` fragments
+pub struct Flow<'a, Message, Theme = iced::Theme, Renderer = iced::Renderer> {
+    spacing: f32,
+    line_spacing: f32,
+    children: Vec<Element<'a, Message, Theme, Renderer>>,
+}
+
+impl<'a, Message, Theme, Renderer> Flow<'a, Message, Theme, Renderer> {
+    pub fn with_children(children: Vec<Element<'a, Message, Theme, Renderer>>) -> Self {
+        Self {
+            spacing: 0.0,
+            line_spacing: 2.0,
+            children,
+        }
+    }
+    pub fn spacing(mut self, spacing: f32) -> Self {
+        self.spacing = spacing;
+        self
+    }
+    pub fn line_spacing(mut self, line_spacing: f32) -> Self {
+        self.line_spacing = line_spacing;
+        self
+    }
+}
+
+impl<'a, Message, Theme, Renderer> Widget<Message, Theme, Renderer> for Flow<'a, Message, Theme, Renderer>
+    where
+        Renderer: iced::advanced::Renderer,
+{
+    fn size(&self) -> Size<Length> {
+        Size::new(Length::Fill, Length::Shrink)
+    }
+
+    fn children(&self) -> Vec<Tree> {
+        self.children.iter().map(Tree::new).collect()
+    }
+
+    fn diff(&self, tree: &mut Tree) {
+        tree.diff_children(&self.children);
+    }
+
+    fn layout(&self, tree: &mut Tree, renderer: &Renderer, limits: &layout::Limits) -> layout::Node {
+        let max_width = limits.max().width;
+        let child_limits = layout::Limits::new(Size::ZERO, Size::new(max_width, f32::INFINITY));
+
+        let mut x = 0.0_f32;
+        let mut y = 0.0_f32;
+        let mut line_height = 0.0_f32;
+        let mut content_width = 0.0_f32;
+
+        let nodes = self.children.iter()
+            .zip(tree.children.iter_mut())
+            .map(|(child, tree)| {
+                let node = child.as_widget().layout(tree, renderer, &child_limits);
+                let size = node.size();
+                if x > 0.0 && x + size.width > max_width {
+                    x = 0.0;
+                    y += line_height + self.line_spacing;
+                    line_height = 0.0;
+                }
+                let node = node.move_to(Point::new(x, y));
+                content_width = content_width.max(x + size.width);
+                x += size.width + self.spacing;
+                line_height = line_height.max(size.height);
+                node
+            })
+            .collect();
+
+        let width = if max_width.is_finite() { max_width } else { content_width };
+        layout::Node::with_children(Size::new(width, y + line_height), nodes)
+    }
+
+    fn draw(
+        &self,
+        tree: &Tree,
+        renderer: &mut Renderer,
+        theme: &Theme,
+        style: &renderer::Style,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        viewport: &Rectangle,
+    ) {
+        for ((child, state), layout) in self.children.iter().zip(&tree.children).zip(layout.children()) {
+            child.as_widget().draw(state, renderer, theme, style, layout, cursor, viewport);
+        }
+    }
+
+    fn on_event(
+        &mut self,
+        tree: &mut Tree,
+        event: Event,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        renderer: &Renderer,
+        clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+        viewport: &Rectangle,
+    ) -> event::Status {
+        self.children.iter_mut()
+            .zip(&mut tree.children)
+            .zip(layout.children())
+            .map(|((child, state), layout)| {
+                child.as_widget_mut().on_event(
+                    state, event.clone(), layout, cursor, renderer, clipboard, shell, viewport,
+                )
+            })
+            .fold(event::Status::Ignored, event::Status::merge)
+    }
+
+    fn mouse_interaction(
+        &self,
+        tree: &Tree,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        viewport: &Rectangle,
+        renderer: &Renderer,
+    ) -> mouse::Interaction {
+        self.children.iter()
+            .zip(&tree.children)
+            .zip(layout.children())
+            .map(|((child, state), layout)| {
+                child.as_widget().mouse_interaction(state, layout, cursor, viewport, renderer)
+            })
+            .max()
+            .unwrap_or_default()
+    }
+
+    fn operate(
+        &self,
+        tree: &mut Tree,
+        layout: Layout<'_>,
+        renderer: &Renderer,
+        operation: &mut dyn widget::Operation<Message>,
+    ) {
+        operation.container(None, layout.bounds(), &mut |operation| {
+            self.children.iter()
+                .zip(&mut tree.children)
+                .zip(layout.children())
+                .for_each(|((child, state), layout)| {
+                    child.as_widget().operate(state, layout, renderer, operation);
+                });
+        });
+    }
+}
+
+impl<'a, Message, Theme, Renderer> From<Flow<'a, Message, Theme, Renderer>> for Element<'a, Message, Theme, Renderer>
+    where
+        Message: 'a,
+        Theme: 'a,
+        Renderer: iced::advanced::Renderer + 'a,
+{
+    fn from(flow: Flow<'a, Message, Theme, Renderer>) -> Self {
+        Element::new(flow)
+    }
+}
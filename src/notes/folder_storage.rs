@@ -0,0 +1,708 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::notes::{front_matter, schema};
+use crate::notes::models::{Category, Changes, Data, Post, Revision, TrashedPost};
+use crate::notes::storage::{self, Storage, StorageError, StorageFuture};
+
+/// Categories and the manual order, hidden so it's never taken for a category folder
+static META_FILE_NAME: &str = ".iced_notes.json";
+/// History and trash, a file per post, kept out of git by the `.gitignore` inside
+static LOCAL_DIR_NAME: &str = ".iced_notes";
+const EXTENSION: &str = "md";
+/// Namespace of the ids derived from paths, for notes and category folders that have none stored yet
+const PATH_ID_NAMESPACE: Uuid = Uuid::from_u128(0x6f1e_2c3a_9b7d_4e58_a1c4_d2f0_8b93_5e17);
+
+/// Every post is a Markdown file with front matter, in the folder of its category
+/// or in the root one when it has none. Categories and the manual order are kept in the meta file,
+/// the history and the trashed version of each post in its own files of the local folder.
+/// Files edited, moved or added by hand are picked up on the next load.
+/// Loading never writes, ids missing from the files are derived from their paths and stored by saves
+#[derive(Debug, Clone)]
+pub struct FolderStorage {
+    path: PathBuf,
+    /// File each post was last read from or written to, so renames and moves remove the old one
+    files: Arc<Mutex<HashMap<Uuid, PathBuf>>>,
+    /// The meta file last read still holds the history and trash, the next save moves them out
+    has_legacy_meta: Arc<AtomicBool>,
+}
+
+/// File operation prepared on the UI thread and applied in a blocking task
+enum Write {
+    /// Written only when it differs from the file, so saving notes leaves it untouched
+    /// A meta file that can't be read is set aside first
+    Meta(String),
+    Post { id: Uuid, folder: Option<String>, slug: String, document: String },
+    DeletePost(Uuid),
+    /// Removes the files of every known post missing from the set
+    Retain(HashSet<Uuid>),
+    /// File of a post in the local folder, `None` removes it
+    Local { kind: LocalFile, post_id: Uuid, document: Option<String> },
+    /// Removes the local files of the kind for every post missing from the set
+    RetainLocal(LocalFile, HashSet<Uuid>),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum LocalFile {
+    History,
+    Trash,
+}
+
+
+impl LocalFile {
+    fn dir(&self, root: &Path) -> PathBuf {
+        root.join(LOCAL_DIR_NAME).join(match self {
+            Self::History => "history",
+            Self::Trash => "trash",
+        })
+    }
+    fn path(&self, root: &Path, post_id: Uuid) -> PathBuf {
+        self.dir(root).join(format!("{}.json", post_id))
+    }
+    /// Documents of every post found in the folder, unreadable ones are skipped
+    fn read_all<T: serde::de::DeserializeOwned>(&self, root: &Path) -> HashMap<Uuid, T> {
+        let Ok(entries) = std::fs::read_dir(self.dir(root)) else {
+            return HashMap::new();
+        };
+        entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter_map(|path| {
+                let post_id = Uuid::parse_str(&path.file_stem()?.to_string_lossy()).ok()?;
+                let document = std::fs::read_to_string(&path)
+                    .map_err(|error| StorageError::load_file(&path, error))
+                    .and_then(|contents| serde_json::from_str(&contents).map_err(|error| StorageError::load_format(&path, error)));
+                match document {
+                    Ok(document) => Some((post_id, document)),
+                    Err(error) => {
+                        eprintln!("Skipping {}", error);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+
+/// Same id for the same path on every load, until a save writes it into the file or the meta file
+fn path_id(root: &Path, path: &Path) -> Uuid {
+    let relative_path = path.strip_prefix(root).unwrap_or(path).to_string_lossy().replace('\\', "/");
+    Uuid::new_v5(&PATH_ID_NAMESPACE, relative_path.to_lowercase().as_bytes())
+}
+
+/// Folder names of the categories, made unique with the start of the id when titles clash
+fn category_folders(data: &Data) -> HashMap<Uuid, String> {
+    let mut categories: Vec<&Category> = data.categories.values().collect();
+    categories.sort_by(|a, b| a.created_at.cmp(&b.created_at).then(a.id.cmp(&b.id)));
+    let mut used_names = HashSet::new();
+    categories.into_iter()
+        .map(|category| {
+            let mut name = folder_name(&category.title);
+            if !used_names.insert(name.to_lowercase()) {
+                name = format!("{}-{}", name, short_id(category.id));
+                used_names.insert(name.to_lowercase());
+            }
+            (category.id, name)
+        })
+        .collect()
+}
+
+/// Category title with the characters file systems don't allow replaced
+fn folder_name(title: &str) -> String {
+    let name: String = title.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '-',
+            c if c.is_control() => '-',
+            c => c,
+        })
+        .collect();
+    let name = name.trim().trim_matches('.').trim();
+    match name.is_empty() {
+        true => String::from("untitled"),
+        false => String::from(name),
+    }
+}
+
+fn short_id(id: Uuid) -> String {
+    id.simple().to_string()[..8].to_string()
+}
+
+fn is_note_file(path: &Path) -> bool {
+    path.is_file() && path.extension().is_some_and(|extension| extension == EXTENSION)
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+fn sorted_entries(dir: &Path) -> Result<Vec<PathBuf>, StorageError> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map_err(|error| StorageError::load_file(dir, error))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| !is_hidden(path))
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+/// Writes next to the target first and renames it over, so a crash never leaves a truncated note
+fn write_file(path: &Path, contents: &str) -> Result<(), StorageError> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|error| StorageError::save_file(dir, error))?;
+    }
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(path.file_name().unwrap_or_default());
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+    std::fs::write(&temp_path, contents).map_err(|error| StorageError::write(&temp_path, error))?;
+    std::fs::rename(&temp_path, path).map_err(|error| StorageError::save_file(path, error))
+}
+
+fn remove_file(root: &Path, path: &Path) -> Result<(), StorageError> {
+    match std::fs::remove_file(path) {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(StorageError::save_file(path, error)),
+        _ => {}
+    }
+    // the folder of a renamed or emptied category goes away with its last note
+    if let Some(dir) = path.parent().filter(|dir| *dir != root) {
+        let _ = std::fs::remove_dir(dir);
+    }
+    Ok(())
+}
+
+
+impl FolderStorage {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            files: Default::default(),
+            has_legacy_meta: Default::default(),
+        }
+    }
+
+    fn meta_path(root: &Path) -> PathBuf {
+        root.join(META_FILE_NAME)
+    }
+
+    fn set_meta_aside(root: &Path) -> Result<PathBuf, StorageError> {
+        let broken_path = root.join(format!("{}.broken-{}", META_FILE_NAME, chrono::Utc::now().format("%Y%m%d%H%M%S")));
+        std::fs::rename(Self::meta_path(root), &broken_path)
+            .map_err(|error| StorageError::save_file(&broken_path, error))?;
+        Ok(broken_path)
+    }
+
+    fn run<T: Send + 'static>(
+        &self,
+        task: impl FnOnce(&Path, &mut HashMap<Uuid, PathBuf>) -> Result<T, StorageError> + Send + 'static,
+    ) -> StorageFuture<T> {
+        let path = self.path.clone();
+        let files = self.files.clone();
+        Box::pin(async_std::task::spawn_blocking(move || {
+            let mut files = files.lock().unwrap_or_else(|error| error.into_inner());
+            task(&path, &mut files)
+        }))
+    }
+
+    /// Meta file with the schema version, without the posts, history and trash
+    fn meta_write(data: &Data) -> Result<Write, StorageError> {
+        let mut document = schema::encode_value(data)?;
+        if let Some(document) = document.as_object_mut() {
+            document.remove("posts");
+            document.remove("history");
+            document.remove("trash");
+        }
+        serde_json::to_string_pretty(&document)
+            .map(Write::Meta)
+            .map_err(StorageError::save_format)
+    }
+
+    /// Revisions of a post, or their removal once the post is purged
+    fn history_write(data: &Data, post_id: Uuid) -> Result<Write, StorageError> {
+        let is_kept = data.posts.contains_key(&post_id) || data.trash.contains_key(&post_id);
+        let document = data.history.get(&post_id)
+            .filter(|_| is_kept)
+            .map(serde_json::to_string_pretty)
+            .transpose()
+            .map_err(StorageError::save_format)?;
+        Ok(Write::Local { kind: LocalFile::History, post_id, document })
+    }
+
+    /// The post in the trash, or its removal once it's restored or purged
+    fn trash_write(data: &Data, post_id: Uuid) -> Result<Write, StorageError> {
+        let document = data.trash.get(&post_id)
+            .map(serde_json::to_string_pretty)
+            .transpose()
+            .map_err(StorageError::save_format)?;
+        Ok(Write::Local { kind: LocalFile::Trash, post_id, document })
+    }
+
+    fn post_write(root: &Path, post: &Post, data: &Data, folders: &HashMap<Uuid, String>) -> Result<Write, StorageError> {
+        let folder = post.category_id.and_then(|category_id| folders.get(&category_id)).cloned();
+        let category = post.category_id
+            .and_then(|category_id| data.categories.get(&category_id))
+            .map(|category| category.title.as_str());
+        let slug = front_matter::slug(&post.title);
+        let document = front_matter::write(post, category).map_err(|reason| StorageError::FrontMatterError {
+            path: root.join(folder.as_deref().unwrap_or_default()).join(&slug),
+            reason,
+        })?;
+        Ok(Write::Post {
+            id: post.id,
+            folder,
+            slug,
+            document,
+        })
+    }
+
+    /// Keeps the file a post already has when it still fits its title and category,
+    /// otherwise picks `<slug>.md`, or `<slug>-<id>.md` when that one belongs to another note
+    fn post_path(root: &Path, files: &HashMap<Uuid, PathBuf>, id: Uuid, folder: Option<&str>, slug: &str) -> PathBuf {
+        let dir = match folder {
+            Some(folder) => root.join(folder),
+            None => root.to_path_buf(),
+        };
+        let plain = dir.join(format!("{}.{}", slug, EXTENSION));
+        let suffixed = dir.join(format!("{}-{}.{}", slug, short_id(id), EXTENSION));
+        let current = files.get(&id);
+        if current == Some(&plain) || current == Some(&suffixed) {
+            return current.cloned().unwrap_or(plain);
+        }
+        let is_taken = plain.exists() || files.values().any(|path| *path == plain);
+        match is_taken {
+            true => suffixed,
+            false => plain,
+        }
+    }
+
+    fn apply(root: &Path, files: &mut HashMap<Uuid, PathBuf>, writes: Vec<Write>) -> Result<(), StorageError> {
+        std::fs::create_dir_all(root).map_err(|error| StorageError::save_file(root, error))?;
+        for write in writes {
+            match write {
+                Write::Meta(document) => {
+                    let meta_path = Self::meta_path(root);
+                    let current = std::fs::read_to_string(&meta_path).ok();
+                    if current.as_ref() == Some(&document) {
+                        continue;
+                    }
+                    let is_broken = current.is_some_and(|contents| {
+                        serde_json::from_str(&contents)
+                            .map_err(|error| StorageError::load_format(&meta_path, error))
+                            .and_then(|document| Self::decode_meta(&meta_path, document))
+                            .is_err()
+                    });
+                    if is_broken {
+                        Self::set_meta_aside(root)?;
+                    }
+                    write_file(&meta_path, &document)?;
+                }
+                Write::Post { id, folder, slug, document } => {
+                    let path = Self::post_path(root, files, id, folder.as_deref(), &slug);
+                    write_file(&path, &document)?;
+                    if let Some(previous_path) = files.insert(id, path.clone()).filter(|previous| *previous != path) {
+                        remove_file(root, &previous_path)?;
+                    }
+                }
+                Write::DeletePost(id) => {
+                    if let Some(path) = files.remove(&id) {
+                        remove_file(root, &path)?;
+                    }
+                }
+                Write::Retain(ids) => {
+                    let removed: Vec<Uuid> = files.keys().filter(|id| !ids.contains(id)).copied().collect();
+                    for id in removed {
+                        if let Some(path) = files.remove(&id) {
+                            remove_file(root, &path)?;
+                        }
+                    }
+                }
+                Write::Local { kind, post_id, document } => {
+                    let path = kind.path(root, post_id);
+                    match document {
+                        Some(document) => {
+                            Self::create_local_dir(root)?;
+                            write_file(&path, &document)?;
+                        }
+                        None => match std::fs::remove_file(&path) {
+                            Err(error) if error.kind() != io::ErrorKind::NotFound => {
+                                return Err(StorageError::save_file(&path, error));
+                            }
+                            _ => {}
+                        },
+                    }
+                }
+                Write::RetainLocal(kind, post_ids) => {
+                    let Ok(entries) = std::fs::read_dir(kind.dir(root)) else {
+                        continue;
+                    };
+                    for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+                        let post_id = path.file_stem().and_then(|stem| Uuid::parse_str(&stem.to_string_lossy()).ok());
+                        if post_id.is_some_and(|post_id| !post_ids.contains(&post_id)) {
+                            std::fs::remove_file(&path).map_err(|error| StorageError::save_file(&path, error))?;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The local folder is made with a `.gitignore` ignoring all of it,
+    /// so a notes folder kept in git tracks only the notes and the meta file
+    fn create_local_dir(root: &Path) -> Result<(), StorageError> {
+        let dir = root.join(LOCAL_DIR_NAME);
+        if dir.is_dir() {
+            return Ok(());
+        }
+        std::fs::create_dir_all(&dir).map_err(|error| StorageError::save_file(&dir, error))?;
+        write_file(&dir.join(".gitignore"), "*\n")
+    }
+
+    fn read_modified_at(root: &Path) -> Option<std::time::SystemTime> {
+        let mut paths = vec![root.to_path_buf(), Self::meta_path(root)];
+        for path in sorted_entries(root).unwrap_or_default() {
+            if path.is_dir() {
+                paths.extend(sorted_entries(&path).unwrap_or_default());
+            }
+            paths.push(path);
+        }
+        storage::latest_modified_at(paths.iter().map(PathBuf::as_path))
+    }
+
+    fn write(&self, writes: Result<Vec<Write>, StorageError>) -> StorageFuture<()> {
+        match writes {
+            Ok(writes) => self.run(move |root, files| Self::apply(root, files, writes)),
+            Err(error) => Box::pin(async { Err(error) }),
+        }
+    }
+
+    /// Reads a note of the given category, one without an id or with the id of a note read before
+    /// gets one derived from its path, which is written into it by the next save of the note
+    fn read_post(root: &Path, path: &Path, category_id: Option<Uuid>, data: &Data) -> Result<Post, StorageError> {
+        let document = std::fs::read_to_string(path).map_err(|error| StorageError::load_file(path, error))?;
+        let (front_matter, content) = front_matter::parse(&document)
+            .map_err(|reason| StorageError::FrontMatterError { path: path.to_path_buf(), reason })?;
+        let is_missing_id = front_matter.id.map_or(true, |id| data.posts.contains_key(&id));
+        let modified_at = std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .map(chrono::DateTime::<chrono::Utc>::from)
+            .unwrap_or_else(|_| chrono::Utc::now());
+        let file_stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let base = Post {
+            id: path_id(root, path),
+            category_id,
+            created_at: modified_at,
+            updated_at: modified_at,
+            ..Post::new("", "")
+        };
+        Ok(match is_missing_id {
+            true => front_matter::FrontMatter { id: None, ..front_matter }.to_post(content, &file_stem, base),
+            false => front_matter.to_post(content, &file_stem, base),
+        })
+    }
+
+    /// Meta document without the posts, and whether it still holds the history and trash
+    fn decode_meta(meta_path: &Path, mut document: Value) -> Result<(Data, bool), StorageError> {
+        if let Some(root) = document.as_object_mut() {
+            root.insert("posts".to_string(), Value::Object(Default::default()));
+        }
+        let has_local_data = ["history", "trash"].iter().any(|key| document.get(key).is_some());
+        Ok((schema::decode_value(meta_path, document)?, has_local_data))
+    }
+
+    /// Returns whether the meta file still holds the history and trash too
+    fn read_data(root: &Path, files: &mut HashMap<Uuid, PathBuf>) -> Result<(Data, bool), StorageError> {
+        let meta_path = Self::meta_path(root);
+        let default_meta = || {
+            json!({ schema::VERSION_FIELD: schema::SCHEMA_VERSION, "categories": {} })
+        };
+        let document = match std::fs::read_to_string(&meta_path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|error| StorageError::load_format(&meta_path, error)),
+            // a folder of notes made by hand, or a missing folder, the normal first run
+            Err(error) if error.kind() == io::ErrorKind::NotFound && root.is_dir() => Ok(default_meta()),
+            Err(error) => return Err(StorageError::load_file(root, error)),
+        };
+        let (mut data, has_local_data) = match document.and_then(|document| Self::decode_meta(&meta_path, document)) {
+            // written by a newer version of the app, it must not be replaced
+            Err(error @ StorageError::UnsupportedVersion { .. }) => return Err(error),
+            // the notes are what matters, categories come back from their folders,
+            // the broken file is set aside by the next save
+            Err(error) => {
+                eprintln!("Reading the notes without {}", error);
+                Self::decode_meta(&meta_path, default_meta())?
+            }
+            Ok(decoded) => decoded,
+        };
+
+        let folder_categories: HashMap<String, Uuid> = category_folders(&data)
+            .into_iter()
+            .map(|(category_id, name)| (name.to_lowercase(), category_id))
+            .collect();
+        let mut note_paths: Vec<(PathBuf, Option<Uuid>)> = Vec::new();
+        for path in sorted_entries(root)? {
+            if is_note_file(&path) {
+                note_paths.push((path, None));
+            } else if path.is_dir() {
+                let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+                // a folder made by hand is a new category, the same one on every load until it's saved
+                let category_id = match folder_categories.get(&name.to_lowercase()) {
+                    Some(category_id) => *category_id,
+                    None => {
+                        let created_at = std::fs::metadata(&path)
+                            .and_then(|metadata| metadata.created())
+                            .map(chrono::DateTime::<chrono::Utc>::from)
+                            .unwrap_or_default();
+                        let category = Category {
+                            id: path_id(root, &path),
+                            created_at,
+                            updated_at: created_at,
+                            ..Category::new(&name)
+                        };
+                        let category_id = category.id;
+                        data.categories.insert(category_id, category);
+                        category_id
+                    }
+                };
+                for note_path in sorted_entries(&path)?.into_iter().filter(|path| is_note_file(path)) {
+                    note_paths.push((note_path, Some(category_id)));
+                }
+            }
+        }
+
+        files.clear();
+        for (path, category_id) in note_paths {
+            // a broken note is left alone rather than failing the whole load
+            match Self::read_post(root, &path, category_id, &data) {
+                Ok(post) => {
+                    files.insert(post.id, path);
+                    data.posts.insert(post.id, post);
+                }
+                Err(error) => eprintln!("Skipping {}", error),
+            }
+        }
+
+        data.history.extend(LocalFile::History.read_all::<Vec<Revision>>(root));
+        data.trash.extend(LocalFile::Trash.read_all::<TrashedPost>(root));
+        Ok((data, has_local_data))
+    }
+}
+
+
+impl Storage for FolderStorage {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn load(&self) -> StorageFuture<Data> {
+        let has_legacy_meta = self.has_legacy_meta.clone();
+        self.run(move |root, files| {
+            let (data, has_local_data) = Self::read_data(root, files)?;
+            has_legacy_meta.store(has_local_data, Ordering::Relaxed);
+            Ok(data)
+        })
+    }
+
+    fn save(&self, data: &Data) -> StorageFuture<()> {
+        let folders = category_folders(data);
+        // the meta file last, an old one keeps the history and trash until they're written out
+        let writes = data.posts.values().map(|post| Self::post_write(&self.path, post, data, &folders))
+            .chain(std::iter::once(Ok(Write::Retain(data.posts.keys().copied().collect()))))
+            .chain(data.history.keys().map(|post_id| Self::history_write(data, *post_id)))
+            .chain(data.trash.keys().map(|post_id| Self::trash_write(data, *post_id)))
+            .chain(std::iter::once(Ok(Write::RetainLocal(LocalFile::History, data.history.keys().copied().collect()))))
+            .chain(std::iter::once(Ok(Write::RetainLocal(LocalFile::Trash, data.trash.keys().copied().collect()))))
+            .chain(std::iter::once(Self::meta_write(data)))
+            .collect();
+        self.has_legacy_meta.store(false, Ordering::Relaxed);
+        self.write(writes)
+    }
+
+    /// Only the meta file is moved, the notes stay where they are and are read again without it
+    fn set_aside(&self) -> StorageFuture<Option<PathBuf>> {
+        self.run(|root, _files| match Self::meta_path(root).exists() {
+            true => Self::set_meta_aside(root).map(Some),
+            false => Ok(None),
+        })
+    }
+
+    /// Any note or folder changed, added or removed, folders change when their entries do
+    fn modified_at(&self) -> StorageFuture<Option<std::time::SystemTime>> {
+        self.run(|root, _files| Ok(Self::read_modified_at(root)))
+    }
+
+    /// Writes the notes that changed with their history and trash files, and the meta file if it changed.
+    /// A renamed category moves all of its notes, a meta file still holding the history and trash
+    /// has all of them written out first
+    fn save_changes(&self, data: &Data, changes: &Changes) -> StorageFuture<Option<std::time::SystemTime>> {
+        let folders = category_folders(data);
+        let moved_post_ids = data.posts.values()
+            .filter(|post| post.category_id.is_some_and(|category_id| changes.categories.contains(&category_id)))
+            .map(|post| post.id);
+        let post_ids: HashSet<Uuid> = changes.posts.iter().copied().chain(moved_post_ids).collect();
+        let has_legacy_meta = self.has_legacy_meta.swap(false, Ordering::Relaxed);
+        let local_post_ids: HashSet<Uuid> = match has_legacy_meta {
+            true => data.history.keys().chain(data.trash.keys()).chain(&changes.posts).copied().collect(),
+            false => changes.posts.clone(),
+        };
+        let writes = local_post_ids.iter().map(|post_id| Self::history_write(data, *post_id))
+            .chain(local_post_ids.iter().map(|post_id| Self::trash_write(data, *post_id)))
+            .chain(std::iter::once(Self::meta_write(data)))
+            .chain(post_ids.into_iter().map(|post_id| match data.posts.get(&post_id) {
+                Some(post) => Self::post_write(&self.path, post, data, &folders),
+                None => Ok(Write::DeletePost(post_id)),
+            }))
+            .collect::<Result<Vec<Write>, StorageError>>();
+        let legacy_meta_flag = self.has_legacy_meta.clone();
+        match writes {
+            // taken while the files are still held, so no other save can slip in between
+            Ok(writes) => self.run(move |root, files| {
+                let result = Self::apply(root, files, writes);
+                if result.is_err() && has_legacy_meta {
+                    legacy_meta_flag.store(true, Ordering::Relaxed);
+                }
+                result?;
+                Ok(Self::read_modified_at(root))
+            }),
+            Err(error) => {
+                self.has_legacy_meta.store(has_legacy_meta, Ordering::Relaxed);
+                Box::pin(async { Err(error) })
+            }
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("iced_notes_test_{}", Uuid::new_v4().simple()))
+    }
+
+    fn data_with_trash() -> (Data, Post, Post) {
+        let post = Post::new("Kept", "Content");
+        let trashed = Post::new("Trashed", "Content");
+        let mut data = Data::default();
+        data.posts.insert(post.id, post.clone());
+        data.history.insert(post.id, vec![Revision::from(&post)]);
+        data.trash.insert(trashed.id, TrashedPost { post: trashed.clone(), deleted_at: chrono::Utc::now() });
+        (data, post, trashed)
+    }
+
+    #[test]
+    fn history_and_trash_live_in_ignored_files_per_post() {
+        let dir = temp_dir();
+        let storage = FolderStorage::new(dir.clone());
+        let (data, post, trashed) = data_with_trash();
+        async_std::task::block_on(storage.save(&data)).unwrap();
+
+        let meta = std::fs::read_to_string(FolderStorage::meta_path(&dir)).unwrap();
+        assert!(!meta.contains("\"history\"") && !meta.contains("\"trash\""));
+        assert!(LocalFile::History.path(&dir, post.id).is_file());
+        assert!(LocalFile::Trash.path(&dir, trashed.id).is_file());
+        assert_eq!(std::fs::read_to_string(dir.join(LOCAL_DIR_NAME).join(".gitignore")).unwrap(), "*\n");
+
+        let loaded = async_std::task::block_on(FolderStorage::new(dir.clone()).load()).unwrap();
+        assert_eq!(loaded.history, data.history);
+        assert_eq!(loaded.trash, data.trash);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn saving_a_note_leaves_an_unchanged_meta_file_alone() {
+        let dir = temp_dir();
+        let storage = FolderStorage::new(dir.clone());
+        let (mut data, post, _) = data_with_trash();
+        async_std::task::block_on(storage.save(&data)).unwrap();
+        let modified_at = || std::fs::metadata(FolderStorage::meta_path(&dir)).unwrap().modified().unwrap();
+        let before = modified_at();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+
+        data.posts.insert(post.id, Post { content: String::from("Edited"), ..post.clone() });
+        let changes = Changes { posts: HashSet::from([post.id]), ..Default::default() };
+        async_std::task::block_on(storage.save_changes(&data, &changes)).unwrap();
+        assert_eq!(modified_at(), before);
+
+        data.post_order = vec![post.id];
+        async_std::task::block_on(storage.save_changes(&data, &Changes::default())).unwrap();
+        assert_ne!(modified_at(), before);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn history_and_trash_of_an_old_meta_file_are_moved_out_by_the_next_save() {
+        let dir = temp_dir();
+        let (data, post, trashed) = data_with_trash();
+        async_std::task::block_on(FolderStorage::new(dir.clone()).save(&data)).unwrap();
+        std::fs::remove_dir_all(dir.join(LOCAL_DIR_NAME)).unwrap();
+        let mut meta = schema::encode_value(&data).unwrap();
+        meta.as_object_mut().unwrap().remove("posts");
+        std::fs::write(FolderStorage::meta_path(&dir), meta.to_string()).unwrap();
+
+        let storage = FolderStorage::new(dir.clone());
+        let loaded = async_std::task::block_on(storage.load()).unwrap();
+        assert_eq!(loaded.history, data.history);
+        assert_eq!(loaded.trash, data.trash);
+        assert!(!dir.join(LOCAL_DIR_NAME).exists());
+
+        async_std::task::block_on(storage.save_changes(&loaded, &Changes::default())).unwrap();
+        let meta = std::fs::read_to_string(FolderStorage::meta_path(&dir)).unwrap();
+        assert!(!meta.contains("\"history\"") && !meta.contains("\"trash\""));
+        assert!(LocalFile::History.path(&dir, post.id).is_file());
+        assert!(LocalFile::Trash.path(&dir, trashed.id).is_file());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn notes_are_read_without_a_broken_meta_file_which_the_next_save_sets_aside() {
+        let dir = temp_dir();
+        let storage = FolderStorage::new(dir.clone());
+        let (data, post, _) = data_with_trash();
+        async_std::task::block_on(storage.save(&data)).unwrap();
+        std::fs::write(FolderStorage::meta_path(&dir), "{ not json").unwrap();
+
+        let storage = FolderStorage::new(dir.clone());
+        let loaded = async_std::task::block_on(storage.load()).unwrap();
+        assert_eq!(loaded.posts[&post.id].content, post.content);
+        assert_eq!(std::fs::read_to_string(FolderStorage::meta_path(&dir)).unwrap(), "{ not json");
+
+        async_std::task::block_on(storage.save_changes(&loaded, &Changes::default())).unwrap();
+        let is_set_aside = std::fs::read_dir(&dir).unwrap()
+            .any(|entry| entry.unwrap().file_name().to_string_lossy().starts_with(".iced_notes.json.broken-"));
+        assert!(is_set_aside);
+        assert!(async_std::task::block_on(storage.load()).is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn loading_a_folder_made_by_hand_writes_nothing_and_keeps_the_same_ids() {
+        let dir = temp_dir();
+        std::fs::create_dir_all(dir.join("Work")).unwrap();
+        std::fs::write(dir.join("Plain.md"), "Written by hand").unwrap();
+        std::fs::write(dir.join("Work").join("Plan.md"), "# Plan").unwrap();
+        let storage = FolderStorage::new(dir.clone());
+
+        let first = async_std::task::block_on(storage.load()).unwrap();
+        let second = async_std::task::block_on(storage.load()).unwrap();
+        assert_eq!(first.posts, second.posts);
+        assert_eq!(first.categories, second.categories);
+        assert_eq!(first.posts.len(), 2);
+        assert_eq!(first.categories.len(), 1);
+        assert_eq!(std::fs::read_to_string(dir.join("Plain.md")).unwrap(), "Written by hand");
+        assert!(!FolderStorage::meta_path(&dir).exists());
+
+        async_std::task::block_on(storage.save(&first)).unwrap();
+        let saved = async_std::task::block_on(FolderStorage::new(dir.clone()).load()).unwrap();
+        assert_eq!(saved.posts.keys().collect::<HashSet<_>>(), first.posts.keys().collect::<HashSet<_>>());
+        assert_eq!(saved.categories.keys().collect::<HashSet<_>>(), first.categories.keys().collect::<HashSet<_>>());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::BTreeSet;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::notes::models::Post;
+
+const FENCE: &str = "---";
+
+/// YAML header of a note written as a Markdown file, everything but the id is optional
+/// so files written by hand can be read too
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FrontMatter {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Category title, categories are matched by it case-insensitively
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub pinned: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub favourite: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+
+impl FrontMatter {
+    pub fn from_post(post: &Post, category: Option<&str>) -> Self {
+        Self {
+            id: Some(post.id),
+            title: Some(post.title.clone()),
+            category: category.map(String::from),
+            tags: post.tags.clone(),
+            pinned: post.is_pinned,
+            favourite: post.is_favourite,
+            created_at: Some(post.created_at),
+            updated_at: Some(post.updated_at),
+        }
+    }
+    /// The post these fields describe, the ones missing and the category are taken from `base`
+    pub fn to_post(self, content: String, file_stem: &str, base: Post) -> Post {
+        Post {
+            id: self.id.unwrap_or(base.id),
+            title: self.title.unwrap_or_else(|| fallback_title(&content, file_stem)),
+            content,
+            tags: self.tags,
+            is_pinned: self.pinned,
+            is_favourite: self.favourite,
+            created_at: self.created_at.unwrap_or(base.created_at),
+            updated_at: self.updated_at.unwrap_or(base.updated_at),
+            ..base
+        }
+    }
+}
+
+
+/// The post as a Markdown document with its front matter
+pub fn write(post: &Post, category: Option<&str>) -> Result<String, String> {
+    let yaml = serde_yaml::to_string(&FrontMatter::from_post(post, category))
+        .map_err(|error| error.to_string())?;
+    Ok(format!("{FENCE}\n{yaml}{FENCE}\n\n{}", post.content))
+}
+
+/// Splits a Markdown document into its front matter and content,
+/// a document without front matter gets the default one
+pub fn parse(document: &str) -> Result<(FrontMatter, String), String> {
+    let document = document.strip_prefix('\u{feff}').unwrap_or(document);
+    let Some(rest) = strip_fence_line(document) else {
+        return Ok((FrontMatter::default(), String::from(document)));
+    };
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == FENCE {
+            let front_matter = match rest[..offset].trim().is_empty() {
+                true => FrontMatter::default(),
+                false => serde_yaml::from_str(&rest[..offset]).map_err(|error| error.to_string())?,
+            };
+            let content = &rest[offset + line.len()..];
+            // the blank line `write` puts after the front matter isn't part of the content
+            let content = content.strip_prefix("\r\n").or_else(|| content.strip_prefix('\n')).unwrap_or(content);
+            return Ok((front_matter, String::from(content)));
+        }
+        offset += line.len();
+    }
+    Err(String::from("front matter is not closed with \"---\""))
+}
+
+fn strip_fence_line(document: &str) -> Option<&str> {
+    let rest = document.strip_prefix(FENCE)?;
+    rest.strip_prefix("\r\n").or_else(|| rest.strip_prefix('\n'))
+}
+
+/// Title of a note without one in its front matter, its first heading or else the file name
+pub fn fallback_title(content: &str, file_stem: &str) -> String {
+    content.lines()
+        .find_map(|line| line.strip_prefix("# "))
+        .map(|heading| String::from(heading.trim()))
+        .filter(|heading| !heading.is_empty())
+        .unwrap_or_else(|| String::from(file_stem))
+}
+
+/// File name friendly version of a title, lowercase words joined by dashes
+pub fn slug(title: &str) -> String {
+    let slug = title.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    match slug.is_empty() {
+        true => String::from("untitled"),
+        false => slug.chars().take(60).collect::<String>().trim_end_matches('-').to_string(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post() -> Post {
+        Post {
+            tags: BTreeSet::from([String::from("rust")]),
+            is_pinned: true,
+            ..Post::new("Title: with colon", "# Heading\n\nBody\n")
+        }
+    }
+
+    #[test]
+    fn written_post_parses_back() {
+        let post = post();
+        let (front_matter, content) = parse(&write(&post, Some("Work")).unwrap()).unwrap();
+        assert_eq!(front_matter, FrontMatter::from_post(&post, Some("Work")));
+        assert_eq!(content, post.content);
+    }
+
+    #[test]
+    fn crlf_document_parses_like_lf() {
+        let post = post();
+        let document = write(&post, None).unwrap().replace('\n', "\r\n");
+        let (front_matter, content) = parse(&document).unwrap();
+        assert_eq!(front_matter, FrontMatter::from_post(&post, None));
+        assert_eq!(content, post.content.replace('\n', "\r\n"));
+    }
+
+    #[test]
+    fn byte_order_mark_is_skipped() {
+        let post = post();
+        let document = format!("\u{feff}{}", write(&post, None).unwrap());
+        let (front_matter, content) = parse(&document).unwrap();
+        assert_eq!(front_matter.id, Some(post.id));
+        assert_eq!(content, post.content);
+    }
+
+    #[test]
+    fn document_without_front_matter_is_all_content() {
+        let (front_matter, content) = parse("# Just notes\n---\nmore").unwrap();
+        assert_eq!(front_matter, FrontMatter::default());
+        assert_eq!(content, "# Just notes\n---\nmore");
+        assert_eq!(front_matter.to_post(content, "file", Post::new("", "")).title, "Just notes");
+    }
+
+    #[test]
+    fn unclosed_fence_is_an_error() {
+        assert!(parse("---\ntitle: Open\n\nContent").is_err());
+        assert!(parse("---\r\ntitle: Open\r\n--").is_err());
+    }
+
+    #[test]
+    fn empty_front_matter_is_the_default() {
+        let (front_matter, content) = parse("---\n---\n\nContent").unwrap();
+        assert_eq!(front_matter, FrontMatter::default());
+        assert_eq!(content, "Content");
+    }
+}
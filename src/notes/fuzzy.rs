@@ -0,0 +1,31 @@
+/// Scores `text` against `query` typed as a subsequence of its characters, ignoring case,
+/// consecutive characters and word starts score higher, `None` if it doesn't match
+pub fn fuzzy_score(query: &str, text: &str) -> Option<i64> {
+    let query: Vec<char> = query.to_lowercase().chars().filter(|c| !c.is_whitespace()).collect();
+    if query.is_empty() {
+        return Some(0);
+    }
+    let mut score = 0;
+    let mut query_index = 0;
+    let mut previous_match: Option<usize> = None;
+    let mut previous_char: Option<char> = None;
+    for (index, c) in text.to_lowercase().chars().enumerate() {
+        if query_index < query.len() && c == query[query_index] {
+            score += 1;
+            if previous_match.is_some_and(|previous| previous + 1 == index) {
+                score += 5;
+            }
+            if previous_char.map_or(true, |previous| !previous.is_alphanumeric()) {
+                score += 8;
+            }
+            previous_match = Some(index);
+            query_index += 1;
+        }
+        previous_char = Some(c);
+    }
+    if query_index < query.len() {
+        return None;
+    }
+    // shorter texts are closer to what was typed
+    Some(score * 100 - text.chars().count() as i64)
+}
@@ -0,0 +1,85 @@
+use iced::{Alignment, Color, Element, Font, Length, theme, widget};
+
+use crate::notes::{FontSize, Message, models};
+use crate::notes::diff::{diff_lines, DiffKind};
+
+const ADDED_COLOR: Color = Color { r: 0.2, g: 0.6, b: 0.3, a: 1.0 };
+const REMOVED_COLOR: Color = Color { r: 0.85, g: 0.2, b: 0.2, a: 1.0 };
+
+fn diff_line<'a>(kind: DiffKind, line: &str) -> Element<'a, Message> {
+    let (marker, color) = match kind {
+        DiffKind::Same => (' ', None),
+        DiffKind::Added => ('+', Some(ADDED_COLOR)),
+        DiffKind::Removed => ('-', Some(REMOVED_COLOR)),
+    };
+    let text = widget::text(format!("{} {}", marker, line))
+        .font(Font::MONOSPACE)
+        .size(FontSize::Tooltip);
+    match color {
+        Some(color) => text.style(theme::Text::Color(color)).into(),
+        None => text.into(),
+    }
+}
+
+fn diff_view<'a>(base: (&str, &str), target: (&str, &str)) -> Element<'a, Message> {
+    let mut lines = widget::Column::new().spacing(2);
+    if base.0 != target.0 {
+        lines = lines
+            .push(diff_line(DiffKind::Removed, &format!("Title: {}", base.0)))
+            .push(diff_line(DiffKind::Added, &format!("Title: {}", target.0)));
+    }
+    let diff = diff_lines(base.1, target.1);
+    if diff.iter().all(|(kind, _)| *kind == DiffKind::Same) && base.0 == target.0 {
+        lines = lines.push(widget::text("No differences").size(FontSize::Tooltip));
+    }
+    for (kind, line) in diff {
+        lines = lines.push(diff_line(kind, line));
+    }
+    widget::scrollable(lines.width(Length::Fill))
+        .height(Length::Fill)
+        .into()
+}
+
+
+/// Picks two versions of the selected post, shows their line diff and restores the older one
+pub fn panel_history<'a>(state: &'a models::NotesState, selection: &models::HistorySelection) -> Element<'a, Message> {
+    let options = state.get_revision_options();
+    if options.len() < 2 {
+        return widget::text("No saved revisions yet").size(FontSize::Tooltip).into();
+    }
+    let selected_option = |saved_at| options.iter().find(|option| option.saved_at == saved_at).cloned();
+
+    let mut controls = widget::Row::new()
+        .spacing(8)
+        .align_items(Alignment::Center)
+        .push(widget::text("Compare").size(FontSize::Caption))
+        .push(
+            widget::pick_list(options.clone(), selected_option(selection.base), Message::HistoryBaseSelected)
+                .text_size(FontSize::Caption)
+        )
+        .push(widget::text("with").size(FontSize::Caption))
+        .push(
+            widget::pick_list(options.clone(), selected_option(selection.target), Message::HistoryTargetSelected)
+                .text_size(FontSize::Caption)
+        )
+        .push(widget::horizontal_space(Length::Fill));
+    if let Some(saved_at) = selection.base {
+        controls = controls.push(
+            widget::button(widget::text("Restore").size(FontSize::Caption))
+                .on_press(Message::ButtonRestoreRevisionPressed(saved_at))
+        );
+    }
+
+    let diff = match (
+        state.get_selected_post_version(selection.base),
+        state.get_selected_post_version(selection.target),
+    ) {
+        (Some(base), Some(target)) => diff_view(base, target),
+        _ => widget::text("Revision not found").size(FontSize::Tooltip).into(),
+    };
+
+    widget::column(vec![controls.into(), diff])
+        .spacing(8)
+        .height(Length::Fill)
+        .into()
+}
@@ -9,6 +9,25 @@ pub enum Icons {
     LightMode,
     DeleteForever,
     PostAdd,
+    Folder,
+    CreateNewFolder,
+    Close,
+    Error,
+    Edit,
+    Visibility,
+    VerticalSplit,
+    History,
+    Delete,
+    RestoreFromTrash,
+    Undo,
+    Redo,
+    ArrowUpward,
+    ArrowDownward,
+    PushPin,
+    Star,
+    StarBorder,
+    Lock,
+    VpnKey,
 }
 
 pub fn get_codepoint(icon: &Icons) -> char {
@@ -17,6 +36,25 @@ pub fn get_codepoint(icon: &Icons) -> char {
         Icons::LightMode => '\u{e518}',
         Icons::DeleteForever => '\u{e92b}',
         Icons::PostAdd => '\u{ea20}',
+        Icons::Folder => '\u{e2c7}',
+        Icons::CreateNewFolder => '\u{e2cc}',
+        Icons::Close => '\u{e5cd}',
+        Icons::Error => '\u{e000}',
+        Icons::Edit => '\u{e3c9}',
+        Icons::Visibility => '\u{e8f4}',
+        Icons::VerticalSplit => '\u{e949}',
+        Icons::History => '\u{e889}',
+        Icons::Delete => '\u{e872}',
+        Icons::RestoreFromTrash => '\u{e938}',
+        Icons::Undo => '\u{e166}',
+        Icons::Redo => '\u{e15a}',
+        Icons::ArrowUpward => '\u{e5d8}',
+        Icons::ArrowDownward => '\u{e5db}',
+        Icons::PushPin => '\u{f10d}',
+        Icons::Star => '\u{e838}',
+        Icons::StarBorder => '\u{e83a}',
+        Icons::Lock => '\u{e897}',
+        Icons::VpnKey => '\u{e0da}',
     }
 }
 
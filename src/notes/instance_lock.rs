@@ -0,0 +1,234 @@
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use fs2::FileExt;
+use serde::{Deserialize, Serialize};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(500);
+/// Owner info that can't be read may still be being written by an instance starting right now
+const PARTIAL_LOCK_WAIT: Duration = Duration::from_millis(200);
+
+/// Set by the listener thread when a second launch asks this instance to come to the front
+static FOCUS_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// What a second launch against the same storage does
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SecondInstanceMode {
+    Refuse,
+    ReadOnly,
+    #[default]
+    Focus,
+}
+
+/// Owner of the storage written next to the lock file, which can't be read while it's locked on Windows
+#[derive(Debug, Serialize, Deserialize)]
+struct LockInfo {
+    pid: u32,
+    /// Localhost port the owner listens on for focus requests
+    port: u16,
+    started_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Advisory lock held while the app owns the storage. The operating system releases it when
+/// the process ends, so a lock is never left behind by a crash
+#[derive(Debug)]
+pub struct InstanceLock {
+    /// Locked for as long as it's open. The file itself stays, removing it would let a launch
+    /// that opened it just before lock a file no longer found at the lock path
+    _file: File,
+    info_path: PathBuf,
+}
+
+pub enum Acquired {
+    Owner(InstanceLock),
+    /// Another instance owns the storage, this one must not write to it
+    ReadOnly,
+    /// Another instance owns the storage and was brought to the front
+    Focused,
+}
+
+#[derive(Debug)]
+pub enum LockError {
+    /// `pid` is `None` when the owner hasn't written it yet
+    AlreadyRunning { path: PathBuf, pid: Option<u32> },
+    Io { path: PathBuf, source: io::Error },
+}
+
+
+impl SecondInstanceMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "refuse" => Some(Self::Refuse),
+            "read-only" | "readonly" => Some(Self::ReadOnly),
+            "focus" => Some(Self::Focus),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AlreadyRunning { path, pid: Some(pid) } => write!(
+                f,
+                "{} is already open in another instance (pid {})",
+                path.display(), pid,
+            ),
+            Self::AlreadyRunning { path, pid: None } => {
+                write!(f, "{} is already open in another instance", path.display())
+            }
+            Self::Io { path, source } => write!(f, "Failed to lock {}: {}", path.display(), source),
+        }
+    }
+}
+
+impl Drop for InstanceLock {
+    /// The file lock goes with the file, which is closed after this
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.info_path);
+    }
+}
+
+
+/// `<storage file>.lock` next to the storage file or folder
+pub fn lock_path(storage_path: &Path) -> PathBuf {
+    let mut file_name = storage_path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".lock");
+    storage_path.with_file_name(file_name)
+}
+
+/// `<storage file>.lock.json` with the owner of the lock
+fn info_path(lock_path: &Path) -> PathBuf {
+    let mut file_name = lock_path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".json");
+    lock_path.with_file_name(file_name)
+}
+
+/// Whether another launch asked to be focused since the last call
+pub fn take_focus_request() -> bool {
+    FOCUS_REQUESTED.swap(false, Ordering::Relaxed)
+}
+
+fn read_info(path: &Path) -> Option<LockInfo> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+/// Sends a one line request to the owner, `None` when nobody answers
+fn send_request(info: &LockInfo, request: &str) -> Option<String> {
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, info.port));
+    let mut stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT).ok()?;
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT)).ok()?;
+    writeln!(stream, "{}", request).ok()?;
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response).ok()?;
+    Some(String::from(response.trim()))
+}
+
+fn listen(listener: TcpListener) {
+    for stream in listener.incoming().flatten() {
+        let _ = stream.set_read_timeout(Some(CONNECT_TIMEOUT));
+        let mut request = String::new();
+        if BufReader::new(&stream).read_line(&mut request).is_err() {
+            continue;
+        }
+        let response = match request.trim() {
+            "focus" => {
+                FOCUS_REQUESTED.store(true, Ordering::Relaxed);
+                "ok"
+            }
+            _ => continue,
+        };
+        let _ = writeln!(&stream, "{}", response);
+    }
+}
+
+fn create(path: &Path) -> io::Result<Option<InstanceLock>> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+    match file.try_lock_exclusive() {
+        Ok(()) => {}
+        Err(error) if error.raw_os_error() == fs2::lock_contended_error().raw_os_error() => return Ok(None),
+        Err(error) => return Err(error),
+    }
+    // from here on the lock is ours, dropping it on an error below releases it again
+    let lock = InstanceLock { _file: file, info_path: info_path(path) };
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    let info = LockInfo {
+        pid: std::process::id(),
+        port: listener.local_addr()?.port(),
+        started_at: chrono::Utc::now(),
+    };
+    // written aside and renamed over the info of a crashed owner, so it's never read half written
+    let mut temp_file_name = lock.info_path.file_name().unwrap_or_default().to_os_string();
+    temp_file_name.push(".tmp");
+    let temp_path = lock.info_path.with_file_name(temp_file_name);
+    {
+        let mut temp_file = File::create(&temp_path)?;
+        temp_file.write_all(serde_json::to_string(&info)?.as_bytes())?;
+        temp_file.sync_all()?;
+    }
+    std::fs::rename(&temp_path, &lock.info_path)?;
+    std::thread::spawn(move || listen(listener));
+    Ok(Some(lock))
+}
+
+/// Takes the lock of the storage, or decides what to do about the instance holding it
+pub fn acquire(storage_path: &Path, mode: SecondInstanceMode) -> Result<Acquired, LockError> {
+    let path = lock_path(storage_path);
+    if let Some(lock) = create(&path).map_err(|source| LockError::Io { path: path.clone(), source })? {
+        return Ok(Acquired::Owner(lock));
+    }
+    let info_path = info_path(&path);
+    let info = read_info(&info_path).or_else(|| {
+        std::thread::sleep(PARTIAL_LOCK_WAIT);
+        read_info(&info_path)
+    });
+    match mode {
+        SecondInstanceMode::Refuse => Err(LockError::AlreadyRunning {
+            path: storage_path.to_path_buf(),
+            pid: info.map(|info| info.pid),
+        }),
+        SecondInstanceMode::ReadOnly => Ok(Acquired::ReadOnly),
+        // the owner is alive but didn't come to the front, so don't write under it
+        SecondInstanceMode::Focus => match info.and_then(|info| send_request(&info, "focus")) {
+            Some(response) if response == "ok" => Ok(Acquired::Focused),
+            _ => Ok(Acquired::ReadOnly),
+        },
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn lock_is_refused_while_held_and_taken_once_released() {
+        let storage_path = std::env::temp_dir()
+            .join(format!("iced_notes_test_{}", Uuid::new_v4().simple()))
+            .join("notes.json");
+        let Ok(Acquired::Owner(lock)) = acquire(&storage_path, SecondInstanceMode::Refuse) else {
+            panic!("the first lock wasn't taken");
+        };
+        assert!(matches!(
+            acquire(&storage_path, SecondInstanceMode::Refuse),
+            Err(LockError::AlreadyRunning { pid: Some(pid), .. }) if pid == std::process::id()
+        ));
+        assert!(matches!(acquire(&storage_path, SecondInstanceMode::ReadOnly), Ok(Acquired::ReadOnly)));
+
+        drop(lock);
+        assert!(lock_path(&storage_path).exists());
+        assert!(matches!(acquire(&storage_path, SecondInstanceMode::Refuse), Ok(Acquired::Owner(_))));
+        let _ = std::fs::remove_dir_all(storage_path.parent().unwrap());
+    }
+}
@@ -0,0 +1,143 @@
+use std::path::Path;
+
+use iced::{Alignment, Element, Length, theme, widget};
+use iced::widget::text_input;
+use zeroize::Zeroizing;
+
+use crate::notes::{FontSize, Message};
+use crate::notes::icons::{Icons, material_icon};
+use crate::notes::storage::StorageError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassphraseField {
+    Current,
+    New,
+    Repeat,
+}
+
+/// Form that turns encryption on, changes the passphrase, or turns encryption off with an empty one.
+/// The passphrases are wiped from memory when they're replaced and when the form is closed
+#[derive(Debug, Default)]
+pub struct PassphraseForm {
+    pub current: Zeroizing<String>,
+    pub new: Zeroizing<String>,
+    pub repeat: Zeroizing<String>,
+    pub error: Option<String>,
+    pub is_saving: bool,
+}
+
+pub fn passphrase_input_id() -> text_input::Id {
+    text_input::Id::new("unlock_passphrase")
+}
+
+
+impl PassphraseForm {
+    pub fn set(&mut self, field: PassphraseField, value: String) {
+        match field {
+            PassphraseField::Current => self.current = Zeroizing::new(value),
+            PassphraseField::New => self.new = Zeroizing::new(value),
+            PassphraseField::Repeat => self.repeat = Zeroizing::new(value),
+        }
+        self.error = None;
+    }
+    /// The passphrase to encrypt with, `None` saves the notes unencrypted
+    pub fn validate(&self, is_encrypted: bool) -> Result<Option<Zeroizing<String>>, String> {
+        if self.new != self.repeat {
+            return Err(String::from("The new passphrases don't match"));
+        }
+        if self.new.is_empty() && !is_encrypted {
+            return Err(String::from("Enter a passphrase"));
+        }
+        Ok(Some(self.new.clone()).filter(|passphrase| !passphrase.is_empty()))
+    }
+}
+
+
+/// Shown instead of the notes while they're encrypted and locked
+pub fn unlock_view<'a>(path: &Path, passphrase: &str, error: Option<&StorageError>, is_unlocking: bool) -> Element<'a, Message> {
+    let input = widget::text_input("Passphrase", passphrase)
+        .id(passphrase_input_id())
+        .secure(true)
+        .on_input(Message::UnlockPassphraseChanged)
+        .on_submit(Message::UnlockSubmitted)
+        .size(FontSize::Body);
+    let unlock_button = widget::button(widget::text("Unlock").size(FontSize::Tooltip))
+        .on_press_maybe((!is_unlocking && !passphrase.is_empty()).then_some(Message::UnlockSubmitted));
+    let mut content = widget::Column::new()
+        .spacing(8)
+        .align_items(Alignment::Center)
+        .push(material_icon(Icons::Lock).size(FontSize::Header1))
+        .push(widget::text(format!("{} is encrypted", path.display())).size(FontSize::Body))
+        .push(
+            widget::row(vec![input.into(), unlock_button.into()])
+                .spacing(8)
+                .align_items(Alignment::Center)
+        );
+    if is_unlocking {
+        content = content.push(widget::text("Unlocking…").size(FontSize::Tooltip));
+    }
+    if let Some(error) = error {
+        content = content.push(widget::text(error).size(FontSize::Tooltip));
+    }
+    widget::container(
+        widget::container(content)
+            .max_width(400)
+            .padding(16)
+            .style(theme::Container::Box)
+    )
+        .width(Length::Fill)
+        .padding(32)
+        .center_x()
+        .into()
+}
+
+fn passphrase_input<'a>(placeholder: &str, value: &str, field: PassphraseField) -> Element<'a, Message> {
+    widget::text_input(placeholder, value)
+        .secure(true)
+        .on_input(move |value| Message::PassphraseFormChanged(field, value))
+        .on_submit(Message::PassphraseFormSubmitted)
+        .size(FontSize::Tooltip)
+        .into()
+}
+
+/// Encryption settings under the controls row
+pub fn passphrase_form_view<'a>(form: &PassphraseForm, is_encrypted: bool) -> Element<'a, Message> {
+    let (title, hint) = match is_encrypted {
+        true => ("Change passphrase", "Leave the new passphrase empty to stop encrypting these notes."),
+        false => ("Encrypt notes", "The passphrase can't be recovered, notes are lost without it."),
+    };
+    let mut content = widget::Column::new()
+        .spacing(8)
+        .push(widget::text(title).size(FontSize::Body))
+        .push(widget::text(hint).size(FontSize::Caption));
+    if is_encrypted {
+        content = content.push(passphrase_input("Current passphrase", &form.current, PassphraseField::Current));
+    }
+    content = content
+        .push(passphrase_input("New passphrase", &form.new, PassphraseField::New))
+        .push(passphrase_input("Repeat new passphrase", &form.repeat, PassphraseField::Repeat));
+    if let Some(error) = &form.error {
+        content = content.push(widget::text(error).size(FontSize::Tooltip));
+    }
+    content = content.push(
+        widget::row(vec![
+            widget::button(widget::text("Save").size(FontSize::Tooltip))
+                .on_press_maybe((!form.is_saving).then_some(Message::PassphraseFormSubmitted))
+                .into(),
+            widget::button(widget::text("Cancel").size(FontSize::Tooltip))
+                .on_press(Message::ButtonPassphrasePressed)
+                .style(theme::Button::Text)
+                .into(),
+        ])
+            .spacing(8)
+    );
+    widget::container(
+        widget::container(content)
+            .max_width(400)
+            .padding(8)
+            .style(theme::Container::Box)
+    )
+        .width(Length::Fill)
+        .center_x()
+        .into()
+}
@@ -0,0 +1,391 @@
+use iced::{alignment, Color, Element, font, Font, Length, theme, widget};
+use pulldown_cmark::{Alignment as ColumnAlignment, CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag};
+
+use crate::notes::{code_highlight, FontSize, Message, wiki_links};
+use crate::notes::flow::Flow;
+
+const LINK_COLOR: Color = Color { r: 0.2, g: 0.45, b: 0.9, a: 1.0 };
+const MUTED_COLOR: Color = Color { r: 0.5, g: 0.5, b: 0.5, a: 1.0 };
+const BLOCK_SPACING: u16 = 12;
+
+/// Styled run of text inside a paragraph, heading or table cell
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub is_strong: bool,
+    pub is_emphasis: bool,
+    pub is_strikethrough: bool,
+    pub is_code: bool,
+    pub link: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Heading(u8, Vec<Span>),
+    Paragraph(Vec<Span>),
+    CodeBlock { language: Option<String>, code: String },
+    Quote(Vec<Block>),
+    List { start: Option<u64>, items: Vec<Vec<Block>> },
+    Table { alignments: Vec<ColumnAlignment>, header: Vec<Vec<Span>>, rows: Vec<Vec<Vec<Span>>> },
+    Rule,
+}
+
+#[derive(Default)]
+struct InlineStyle {
+    strong: usize,
+    emphasis: usize,
+    strikethrough: usize,
+    link: Option<String>,
+}
+
+/// Containers still being filled while walking the parser events
+enum Frame {
+    Root(Vec<Block>),
+    Quote(Vec<Block>),
+    List { start: Option<u64>, items: Vec<Vec<Block>> },
+    Item(Vec<Block>),
+    Inline { kind: InlineKind, spans: Vec<Span> },
+    CodeBlock { language: Option<String>, code: String },
+    Table { alignments: Vec<ColumnAlignment>, header: Vec<Vec<Span>>, rows: Vec<Vec<Vec<Span>>>, row: Vec<Vec<Span>>, is_header: bool },
+}
+
+#[derive(Clone, Copy)]
+enum InlineKind {
+    Heading(u8),
+    Paragraph,
+    /// Text of a tight list item, which comes without a paragraph around it
+    Loose,
+    Cell,
+}
+
+
+pub fn parse(markdown: &str) -> Vec<Block> {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let mut stack = vec![Frame::Root(Vec::new())];
+    let mut style = InlineStyle::default();
+
+    for event in Parser::new_ext(markdown, options) {
+        if let Event::Start(Tag::Heading(..) | Tag::Paragraph | Tag::BlockQuote | Tag::CodeBlock(_) | Tag::List(_) | Tag::Table(_))
+        | Event::End(Tag::Item | Tag::BlockQuote | Tag::List(_)) = &event {
+            close_loose_text(&mut stack);
+        }
+        match event {
+            Event::Start(tag) => match tag {
+                Tag::Heading(level, _, _) => stack.push(Frame::Inline { kind: InlineKind::Heading(heading_level(level)), spans: Vec::new() }),
+                Tag::Paragraph => stack.push(Frame::Inline { kind: InlineKind::Paragraph, spans: Vec::new() }),
+                Tag::BlockQuote => stack.push(Frame::Quote(Vec::new())),
+                Tag::CodeBlock(kind) => stack.push(Frame::CodeBlock {
+                    language: match kind {
+                        CodeBlockKind::Fenced(language) if !language.is_empty() => {
+                            language.split_whitespace().next().map(String::from)
+                        }
+                        _ => None
+                    },
+                    code: String::new(),
+                }),
+                Tag::List(start) => stack.push(Frame::List { start, items: Vec::new() }),
+                Tag::Item => stack.push(Frame::Item(Vec::new())),
+                Tag::Table(alignments) => stack.push(Frame::Table {
+                    alignments,
+                    header: Vec::new(),
+                    rows: Vec::new(),
+                    row: Vec::new(),
+                    is_header: false,
+                }),
+                Tag::TableHead => {
+                    if let Some(Frame::Table { is_header, .. }) = stack.last_mut() {
+                        *is_header = true;
+                    }
+                }
+                Tag::TableCell => stack.push(Frame::Inline { kind: InlineKind::Cell, spans: Vec::new() }),
+                Tag::Emphasis => style.emphasis += 1,
+                Tag::Strong => style.strong += 1,
+                Tag::Strikethrough => style.strikethrough += 1,
+                Tag::Link(_, url, _) => style.link = Some(url.to_string()),
+                _ => {}
+            },
+            Event::End(tag) => match tag {
+                Tag::Emphasis => style.emphasis = style.emphasis.saturating_sub(1),
+                Tag::Strong => style.strong = style.strong.saturating_sub(1),
+                Tag::Strikethrough => style.strikethrough = style.strikethrough.saturating_sub(1),
+                Tag::Link(..) => style.link = None,
+                Tag::TableHead | Tag::TableRow => {
+                    if let Some(Frame::Table { header, rows, row, is_header, .. }) = stack.last_mut() {
+                        let row = std::mem::take(row);
+                        if *is_header {
+                            *header = row;
+                            *is_header = false;
+                        } else {
+                            rows.push(row);
+                        }
+                    }
+                }
+                Tag::Heading(..) | Tag::Paragraph | Tag::BlockQuote | Tag::CodeBlock(_)
+                | Tag::List(_) | Tag::Item | Tag::Table(_) | Tag::TableCell => {
+                    if let Some(frame) = stack.pop() {
+                        close_frame(&mut stack, frame);
+                    }
+                }
+                _ => {}
+            },
+            Event::Text(text) => push_text(&mut stack, &style, &text, false),
+            Event::Code(code) => push_text(&mut stack, &style, &code, true),
+            Event::SoftBreak => push_text(&mut stack, &style, " ", false),
+            Event::HardBreak => push_text(&mut stack, &style, "\n", false),
+            Event::TaskListMarker(is_checked) => {
+                push_text(&mut stack, &style, if is_checked { "☑ " } else { "☐ " }, false)
+            }
+            Event::Rule => push_block(&mut stack, Block::Rule),
+            _ => {}
+        }
+    }
+
+    while stack.len() > 1 {
+        let frame = stack.pop().unwrap();
+        close_frame(&mut stack, frame);
+    }
+    match stack.pop() {
+        Some(Frame::Root(blocks)) => blocks,
+        _ => Vec::new()
+    }
+}
+
+fn heading_level(level: HeadingLevel) -> u8 {
+    match level {
+        HeadingLevel::H1 => 1,
+        HeadingLevel::H2 => 2,
+        HeadingLevel::H3 => 3,
+        HeadingLevel::H4 => 4,
+        HeadingLevel::H5 => 5,
+        HeadingLevel::H6 => 6,
+    }
+}
+
+fn push_text(stack: &mut Vec<Frame>, style: &InlineStyle, text: &str, is_code: bool) {
+    match stack.last_mut() {
+        Some(Frame::CodeBlock { code, .. }) => code.push_str(text),
+        Some(Frame::Inline { spans, .. }) => spans.push(Span {
+            text: String::from(text),
+            is_strong: style.strong > 0,
+            is_emphasis: style.emphasis > 0,
+            is_strikethrough: style.strikethrough > 0,
+            is_code,
+            link: style.link.clone(),
+        }),
+        _ => {
+            stack.push(Frame::Inline { kind: InlineKind::Loose, spans: Vec::new() });
+            push_text(stack, style, text, is_code);
+        }
+    }
+}
+
+fn close_loose_text(stack: &mut Vec<Frame>) {
+    if let Some(Frame::Inline { kind: InlineKind::Loose, .. }) = stack.last() {
+        let frame = stack.pop().unwrap();
+        close_frame(stack, frame);
+    }
+}
+
+fn push_block(stack: &mut Vec<Frame>, block: Block) {
+    close_loose_text(stack);
+    match stack.last_mut() {
+        Some(Frame::Root(blocks)) | Some(Frame::Quote(blocks)) | Some(Frame::Item(blocks)) => blocks.push(block),
+        _ => {}
+    }
+}
+
+fn close_frame(stack: &mut Vec<Frame>, frame: Frame) {
+    match frame {
+        Frame::Inline { kind: InlineKind::Cell, spans } => {
+            if let Some(Frame::Table { row, .. }) = stack.last_mut() {
+                row.push(spans);
+            }
+        }
+        Frame::Inline { kind: InlineKind::Heading(level), spans } => push_block(stack, Block::Heading(level, spans)),
+        Frame::Inline { kind: InlineKind::Paragraph | InlineKind::Loose, spans } => push_block(stack, Block::Paragraph(spans)),
+        Frame::CodeBlock { language, code } => push_block(stack, Block::CodeBlock { language, code }),
+        Frame::Quote(blocks) => push_block(stack, Block::Quote(blocks)),
+        Frame::List { start, items } => push_block(stack, Block::List { start, items }),
+        Frame::Item(blocks) => {
+            if let Some(Frame::List { items, .. }) = stack.last_mut() {
+                items.push(blocks);
+            }
+        }
+        Frame::Table { alignments, header, rows, .. } => push_block(stack, Block::Table { alignments, header, rows }),
+        Frame::Root(blocks) => stack.push(Frame::Root(blocks)),
+    }
+}
+
+
+fn span_font(span: &Span, base: Font) -> Font {
+    let mut font = if span.is_code { Font::MONOSPACE } else { base };
+    if span.is_strong {
+        font.weight = font::Weight::Bold;
+    }
+    if span.is_emphasis {
+        font.style = font::Style::Italic;
+    }
+    font
+}
+
+/// Every word becomes its own text so `Flow` can wrap lines between words
+fn rich_text<'a>(spans: &[Span], size: u16, base: Font) -> Element<'a, Message> {
+    let mut fragments: Vec<Element<'a, Message>> = Vec::new();
+    for span in spans {
+        let font = span_font(span, base);
+        for (index, line) in span.text.split('\n').enumerate() {
+            if index > 0 {
+                // a hard break takes the rest of the line
+                fragments.push(widget::horizontal_space(Length::Fill).into());
+            }
+            for word in line.split_inclusive(' ') {
+                let text = widget::text(word).size(size).font(font);
+                let text = if span.link.is_some() {
+                    text.style(theme::Text::Color(LINK_COLOR))
+                } else if span.is_strikethrough {
+                    text.style(theme::Text::Color(MUTED_COLOR))
+                } else {
+                    text
+                };
+                fragments.push(match &span.link {
+                    Some(url) => widget::button(text)
+                        .padding(0)
+                        .style(theme::Button::Text)
+                        .on_press(Message::MarkdownLinkPressed(url.clone()))
+                        .into(),
+                    None => text.into(),
+                });
+            }
+        }
+    }
+    Flow::with_children(fragments).into()
+}
+
+fn heading_size(level: u8) -> u16 {
+    match level {
+        1 => 28,
+        2 => 24,
+        3 => 20,
+        4 => 18,
+        _ => FontSize::Body as u16,
+    }
+}
+
+fn code_block<'a>(language: &str, code: &str, is_dark_theme: bool) -> Element<'a, Message> {
+    let lines = code_highlight::highlight_code(code.trim_end_matches('\n'), language, is_dark_theme)
+        .into_iter()
+        .map(|fragments| {
+            widget::Row::with_children(
+                fragments.into_iter().map(|(fragment, color)| {
+                    let text = widget::text(fragment)
+                        .font(Font::MONOSPACE)
+                        .size(FontSize::Tooltip);
+                    match color {
+                        Some(color) => text.style(theme::Text::Color(color)),
+                        None => text,
+                    }
+                        .into()
+                }).collect::<Vec<_>>()
+            )
+                .into()
+        })
+        .collect::<Vec<_>>();
+    widget::container(widget::Column::with_children(lines))
+        .width(Length::Fill)
+        .padding(8)
+        .style(theme::Container::Box)
+        .into()
+}
+
+fn list<'a>(start: Option<u64>, items: &[Vec<Block>], is_dark_theme: bool) -> Element<'a, Message> {
+    widget::Column::with_children(
+        items.iter().enumerate().map(|(index, item)| {
+            let marker = match start {
+                Some(start) => format!("{}.", start + index as u64),
+                None => String::from("•"),
+            };
+            widget::row(vec![
+                widget::text(marker)
+                    .size(FontSize::Body)
+                    .width(24)
+                    .horizontal_alignment(alignment::Horizontal::Right)
+                    .into(),
+                blocks(item, is_dark_theme),
+            ])
+                .spacing(6)
+                .into()
+        }).collect::<Vec<_>>()
+    )
+        .spacing(4)
+        .into()
+}
+
+fn table<'a>(alignments: &[ColumnAlignment], header: &[Vec<Span>], rows: &[Vec<Vec<Span>>]) -> Element<'a, Message> {
+    let row = |cells: &[Vec<Span>], base: Font| -> Element<'a, Message> {
+        widget::Row::with_children(
+            cells.iter().enumerate().map(|(index, cell)| {
+                let align_x = match alignments.get(index) {
+                    Some(ColumnAlignment::Center) => alignment::Horizontal::Center,
+                    Some(ColumnAlignment::Right) => alignment::Horizontal::Right,
+                    _ => alignment::Horizontal::Left,
+                };
+                widget::container(rich_text(cell, FontSize::Tooltip as u16, base))
+                    .width(Length::FillPortion(1))
+                    .align_x(align_x)
+                    .padding([2, 4])
+                    .into()
+            }).collect::<Vec<_>>()
+        )
+            .into()
+    };
+    let bold = Font {
+        weight: font::Weight::Bold,
+        ..Font::default()
+    };
+    let mut table = widget::Column::new()
+        .push(row(header, bold))
+        .push(widget::horizontal_rule(1));
+    for cells in rows {
+        table = table.push(row(cells, Font::default()));
+    }
+    widget::container(table)
+        .width(Length::Fill)
+        .padding(4)
+        .style(theme::Container::Box)
+        .into()
+}
+
+fn block<'a>(block: &Block, is_dark_theme: bool) -> Element<'a, Message> {
+    match block {
+        Block::Heading(level, spans) => rich_text(spans, heading_size(*level), Font {
+            weight: font::Weight::Bold,
+            ..Font::default()
+        }),
+        Block::Paragraph(spans) => rich_text(spans, FontSize::Body as u16, Font::default()),
+        Block::CodeBlock { language, code } => {
+            code_block(language.as_deref().unwrap_or_default(), code, is_dark_theme)
+        }
+        Block::Quote(quoted) => widget::row(vec![
+            widget::vertical_rule(3).into(),
+            widget::container(blocks(quoted, is_dark_theme)).padding([0, 8]).into(),
+        ])
+            .height(Length::Shrink)
+            .into(),
+        Block::List { start, items } => list(*start, items, is_dark_theme),
+        Block::Table { alignments, header, rows } => table(alignments, header, rows),
+        Block::Rule => widget::horizontal_rule(1).into(),
+    }
+}
+
+pub fn blocks<'a>(blocks: &[Block], is_dark_theme: bool) -> Element<'a, Message> {
+    widget::Column::with_children(blocks.iter().map(|item| block(item, is_dark_theme)).collect::<Vec<_>>())
+        .spacing(BLOCK_SPACING)
+        .width(Length::Fill)
+        .into()
+}
+
+/// Rendered preview of a markdown note, code blocks are highlighted to match the app theme
+/// and `[[links]]` to other notes become clickable
+pub fn view<'a>(markdown: &str, is_dark_theme: bool) -> Element<'a, Message> {
+    blocks(&parse(&wiki_links::to_markdown_links(markdown)), is_dark_theme)
+}
@@ -0,0 +1,255 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::notes::front_matter;
+use crate::notes::models::{Category, Data, Post};
+use crate::notes::storage::{Storage, StorageError};
+
+const EXTENSION: &str = "md";
+
+/// What an import did, conflicting files are left out and the stored posts kept
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub added: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub conflicts: Vec<ImportConflict>,
+}
+
+#[derive(Debug)]
+pub struct ImportConflict {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+
+impl fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} updated, {} unchanged, {} conflicts",
+            self.added, self.updated, self.unchanged, self.conflicts.len(),
+        )
+    }
+}
+
+impl fmt::Display for ImportConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.reason)
+    }
+}
+
+
+/// File name for the post, taken from its title and made unique with the start of its id
+pub fn file_name(post: &Post, used_names: &mut HashSet<String>) -> String {
+    let slug = front_matter::slug(&post.title);
+    let mut name = format!("{}.{}", slug, EXTENSION);
+    if !used_names.insert(name.clone()) {
+        name = format!("{}-{}.{}", slug, &post.id.simple().to_string()[..8], EXTENSION);
+        used_names.insert(name.clone());
+    }
+    name
+}
+
+fn markdown_paths(dir: &Path) -> Result<Vec<PathBuf>, StorageError> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map_err(|error| StorageError::load_file(dir, error))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|extension| extension == EXTENSION))
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+/// Writes every post into `dir` as a Markdown file, files already there are overwritten.
+/// Files left from an earlier export of a note that was renamed, deleted or purged since are removed,
+/// they're the ones with an id in their front matter. Files without one are left alone
+pub fn export_data(data: &Data, dir: &Path) -> Result<usize, StorageError> {
+    std::fs::create_dir_all(dir).map_err(|error| StorageError::save_file(dir, error))?;
+    let previous_paths: Vec<PathBuf> = markdown_paths(dir)?
+        .into_iter()
+        .filter(|path| {
+            let id = std::fs::read_to_string(path).ok()
+                .and_then(|document| front_matter::parse(&document).ok())
+                .and_then(|(front_matter, _)| front_matter.id);
+            id.is_some()
+        })
+        .collect();
+    let mut posts: Vec<&Post> = data.posts.values().collect();
+    posts.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+    let mut used_names = HashSet::new();
+    let mut written_paths = HashSet::new();
+    for post in &posts {
+        let path = dir.join(file_name(post, &mut used_names));
+        let category = post.category_id
+            .and_then(|category_id| data.categories.get(&category_id))
+            .map(|category| category.title.as_str());
+        let document = front_matter::write(post, category)
+            .map_err(|reason| StorageError::FrontMatterError { path: path.clone(), reason })?;
+        std::fs::write(&path, document).map_err(|error| StorageError::write(&path, error))?;
+        written_paths.insert(path);
+    }
+    // only once every note is written, so a failed export loses nothing
+    for path in previous_paths.iter().filter(|path| !written_paths.contains(*path)) {
+        std::fs::remove_file(path).map_err(|error| StorageError::write(path, error))?;
+    }
+    Ok(posts.len())
+}
+
+/// Id of the category with the given title, created when there's none yet
+pub fn find_or_add_category(data: &mut Data, title: &str) -> Uuid {
+    let existing = data.categories.values().find(|category| category.title.to_lowercase() == title.to_lowercase());
+    match existing {
+        Some(category) => category.id,
+        None => {
+            let category = Category::new(title);
+            let category_id = category.id;
+            data.categories.insert(category_id, category);
+            category_id
+        }
+    }
+}
+
+/// Reads a Markdown note, missing front matter fields are filled in from the file
+/// and the stored post with the same id. Returns the `updated_at` of the front matter too
+pub fn read_post(path: &Path, data: &mut Data) -> Result<(Post, Option<chrono::DateTime<Utc>>), StorageError> {
+    let document = std::fs::read_to_string(path).map_err(|error| StorageError::load_file(path, error))?;
+    let (front_matter, content) = front_matter::parse(&document)
+        .map_err(|reason| StorageError::FrontMatterError { path: path.to_path_buf(), reason })?;
+    let file_stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let base = front_matter.id
+        .and_then(|id| data.posts.get(&id))
+        .cloned()
+        .unwrap_or_else(|| Post {
+            id: front_matter.id.unwrap_or_else(Uuid::new_v4),
+            ..Post::new("", "")
+        });
+    let category_id = front_matter.category
+        .as_deref()
+        .map(str::trim)
+        .filter(|title| !title.is_empty())
+        .map(|title| find_or_add_category(data, title));
+    let updated_at = front_matter.updated_at;
+    // the stored `updated_at` is kept here, the caller decides which one wins
+    let post = Post {
+        category_id,
+        updated_at: base.updated_at,
+        ..front_matter.to_post(content, &file_stem, base)
+    };
+    Ok((post, updated_at))
+}
+
+/// Merges a folder of Markdown notes into `data`, matching posts by the id in their front matter.
+/// A file is a conflict when its post changed in the app after the file was written,
+/// or when it can't be read, both are reported and skipped
+pub fn import_data(data: &mut Data, dir: &Path) -> Result<ImportReport, StorageError> {
+    let paths = markdown_paths(dir)?;
+
+    let mut report = ImportReport::default();
+    let mut seen_ids = HashSet::new();
+    for path in paths {
+        let conflict = |reason: String| ImportConflict { path: path.clone(), reason };
+        let (post, updated_at) = match read_post(&path, data) {
+            Ok(result) => result,
+            Err(error) => {
+                report.conflicts.push(conflict(error.to_string()));
+                continue;
+            }
+        };
+        if !seen_ids.insert(post.id) {
+            report.conflicts.push(conflict(format!("another file has the same id {}", post.id)));
+            continue;
+        }
+        if data.trash.contains_key(&post.id) {
+            report.conflicts.push(conflict(String::from("the note is in the trash")));
+            continue;
+        }
+        match data.posts.get(&post.id).cloned() {
+            None => {
+                let post = Post {
+                    updated_at: updated_at.unwrap_or(post.updated_at),
+                    ..post
+                };
+                data.posts.insert(post.id, post);
+                report.added += 1;
+            }
+            Some(existing) if existing == post => report.unchanged += 1,
+            Some(existing) if updated_at.is_some_and(|updated_at| existing.updated_at > updated_at) => {
+                report.conflicts.push(conflict(format!(
+                    "\"{}\" was edited in the app after the file was written, keeping the app version",
+                    existing.title,
+                )));
+            }
+            Some(existing) => {
+                // a file edited by hand keeps its old timestamp, so it's newer than both
+                let updated_at = updated_at
+                    .filter(|updated_at| *updated_at > existing.updated_at)
+                    .unwrap_or_else(Utc::now);
+                data.posts.insert(post.id, Post { updated_at, ..post });
+                report.updated += 1;
+            }
+        }
+    }
+    Ok(report)
+}
+
+
+/// Exports the stored notes into `dir`, returns how many were written
+pub async fn export(storage: &dyn Storage, dir: PathBuf) -> Result<usize, StorageError> {
+    let data = storage.load().await?;
+    async_std::task::spawn_blocking(move || export_data(&data, &dir)).await
+}
+
+/// Imports `dir` into the stored notes, they're saved only when something changed
+pub async fn import(storage: &dyn Storage, dir: PathBuf) -> Result<ImportReport, StorageError> {
+    let data = match storage.load().await {
+        Err(error) if error.is_not_found() => Data::default(),
+        result => result?,
+    };
+    let (data, report) = async_std::task::spawn_blocking(move || {
+        let mut data = data;
+        let report = import_data(&mut data, &dir)?;
+        Ok::<_, StorageError>((data, report))
+    }).await?;
+    if report.added + report.updated > 0 {
+        storage.save(&data).await?;
+    }
+    Ok(report)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notes::models::TrashedPost;
+
+    #[test]
+    fn export_removes_files_of_renamed_deleted_and_purged_notes() {
+        let dir = std::env::temp_dir().join(format!("iced_notes_test_{}", Uuid::new_v4().simple()));
+        let renamed = Post::new("Old title", "Content");
+        let deleted = Post::new("Deleted", "Content");
+        let purged = Post::new("Purged", "Content");
+        let mut data = Data::default();
+        data.posts.insert(renamed.id, renamed.clone());
+        data.posts.insert(deleted.id, deleted.clone());
+        data.posts.insert(purged.id, purged.clone());
+        export_data(&data, &dir).unwrap();
+        std::fs::write(dir.join("by-hand.md"), "# Written by hand").unwrap();
+
+        data.posts.insert(renamed.id, Post { title: String::from("New title"), ..renamed });
+        data.posts.remove(&deleted.id);
+        data.trash.insert(deleted.id, TrashedPost { post: deleted, deleted_at: Utc::now() });
+        data.posts.remove(&purged.id);
+        export_data(&data, &dir).unwrap();
+
+        let names: Vec<String> = markdown_paths(&dir).unwrap().iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, vec!["by-hand.md", "new-title.md"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,25 +1,84 @@
 use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
 
-use iced::Pixels;
+use iced::{keyboard, Pixels, window};
 use iced::widget::text_editor;
 use uuid::Uuid;
 
-use crate::notes::storage::StorageError;
+use crate::notes::storage::{Storage, StorageError, StorageKind};
 
 pub mod app;
 pub mod buttons;
+pub mod category_list;
+pub mod code_highlight;
 pub mod common;
 pub mod constants;
+pub mod diff;
+pub mod encryption;
+pub mod error_banner;
+pub mod flow;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod folder_storage;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod front_matter;
+pub mod fuzzy;
 pub mod icons;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod instance_lock;
+pub mod lock_screen;
+pub mod markdown;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod markdown_folder;
 pub mod models;
+pub mod history_panel;
+pub mod palette;
 pub mod post_list;
 
 pub mod post_details;
+pub mod schema;
+pub mod search;
+pub mod settings;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_storage;
 pub mod storage;
 pub mod styles;
+pub mod tag_list;
+pub mod tags;
+pub mod toast;
+pub mod undo;
+pub mod wiki_links;
 
 
 pub static DEFAULT_DATE_FORMAT: &str = "%H:%M %d.%m.%Y";
+pub static REVISION_DATE_FORMAT: &str = "%H:%M:%S %d.%m.%Y";
+/// Snapshots kept per post, older ones are dropped
+pub const MAX_POST_REVISIONS: usize = 50;
+/// Snapshots older than this are dropped whatever their number
+pub const MAX_REVISION_AGE_DAYS: i64 = 90;
+/// Editing a post again after this long snapshots the version it had before
+pub const REVISION_IDLE_GAP: Duration = Duration::from_secs(5 * 60);
+/// A post edited without a pause is snapshotted at most this often
+pub const MIN_REVISION_INTERVAL: Duration = Duration::from_secs(15 * 60);
+pub const DEFAULT_TRASH_RETENTION_DAYS: i64 = 30;
+/// How long the "Undo" toast stays after a post is deleted
+pub const UNDO_TOAST_DURATION: Duration = Duration::from_secs(8);
+/// How often the undo toast checks whether its time is up
+pub const UNDO_TOAST_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// Steps kept on the undo stack
+pub const UNDO_LIMIT: usize = 100;
+/// Edits of the same field closer together than this are undone as one step
+pub const UNDO_COALESCE_WINDOW: Duration = Duration::from_secs(2);
+
+/// Pending changes are written to disk at most once per this interval
+pub const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(2);
+/// How often the lock owner checks whether another launch asked to be focused
+pub const INSTANCE_FOCUS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// How often the storage is checked for changes made outside the app
+pub const STORAGE_WATCH_INTERVAL: Duration = Duration::from_secs(2);
+/// Encrypted notes lock after this long without input
+pub const DEFAULT_AUTO_LOCK_MINUTES: i64 = 10;
+/// How often the time since the last input is checked against the auto-lock timeout
+pub const AUTO_LOCK_CHECK_INTERVAL: Duration = Duration::from_secs(15);
 
 
 #[repr(i32)]
@@ -38,9 +97,177 @@ impl From<FontSize> for Pixels {
     }
 }
 
-#[warn(dead_code)]
-fn get_storage_file() -> PathBuf {
-    PathBuf::from("../../notes_data.json")
+pub static STORAGE_FILE_NAME: &str = "notes_data.json";
+#[cfg(feature = "sqlite")]
+pub static SQLITE_STORAGE_FILE_NAME: &str = "notes_data.sqlite3";
+#[cfg(not(target_arch = "wasm32"))]
+pub static FOLDER_STORAGE_NAME: &str = "notes";
+pub static STORAGE_FILE_ARG: &str = "--data-file";
+pub static STORAGE_FILE_ENV: &str = "ICED_NOTES_DATA_FILE";
+pub static STORAGE_KIND_ARG: &str = "--storage";
+pub static STORAGE_KIND_ENV: &str = "ICED_NOTES_STORAGE";
+pub static MIGRATE_TO_ARG: &str = "--migrate-to";
+pub static EXPORT_MARKDOWN_ARG: &str = "--export-markdown";
+pub static IMPORT_MARKDOWN_ARG: &str = "--import-markdown";
+pub static SECOND_INSTANCE_ARG: &str = "--second-instance";
+pub static SECOND_INSTANCE_ENV: &str = "ICED_NOTES_SECOND_INSTANCE";
+pub static TRASH_RETENTION_ARG: &str = "--trash-days";
+pub static TRASH_RETENTION_ENV: &str = "ICED_NOTES_TRASH_DAYS";
+pub static AUTO_LOCK_ARG: &str = "--lock-after";
+pub static AUTO_LOCK_ENV: &str = "ICED_NOTES_LOCK_AFTER";
+pub static SETTINGS_FILE_NAME: &str = "settings.json";
+pub static SETTINGS_FILE_ARG: &str = "--settings";
+pub static SETTINGS_FILE_ENV: &str = "ICED_NOTES_SETTINGS";
+
+
+/// Resolves the storage backend and where notes are stored, first match wins:
+/// - file: `--data-file <path>` argument, `ICED_NOTES_DATA_FILE` environment variable,
+///   a file in the platform data directory
+/// - backend: `--storage <json|sqlite|folder>` argument, `ICED_NOTES_STORAGE` environment variable,
+///   guessed from the file extension
+#[cfg(not(target_arch = "wasm32"))]
+pub fn get_storage() -> Box<dyn Storage> {
+    let path = get_setting(STORAGE_FILE_ARG, STORAGE_FILE_ENV).map(PathBuf::from);
+    let kind = match get_setting(STORAGE_KIND_ARG, STORAGE_KIND_ENV) {
+        Some(name) => StorageKind::from_name(&name).unwrap_or_else(|| {
+            eprintln!("Unknown storage \"{}\", using json", name);
+            StorageKind::Json
+        }),
+        None => path.as_deref().map(StorageKind::from_path).unwrap_or(StorageKind::Json),
+    };
+    let path = path.unwrap_or_else(|| get_default_storage_file(kind));
+    storage::open(kind, path)
+}
+
+/// Target of `--migrate-to <path>`, the backend is guessed from the file extension
+#[cfg(not(target_arch = "wasm32"))]
+pub fn get_migration_target() -> Option<Box<dyn Storage>> {
+    let path = PathBuf::from(get_arg_value(std::env::args().skip(1), MIGRATE_TO_ARG)?);
+    Some(storage::open(StorageKind::from_path(&path), path))
+}
+
+/// What a launch does when the storage is already open in another instance:
+/// `--second-instance <refuse|read-only|focus>` argument, `ICED_NOTES_SECOND_INSTANCE` environment variable,
+/// or focusing the other instance
+#[cfg(not(target_arch = "wasm32"))]
+pub fn get_second_instance_mode() -> instance_lock::SecondInstanceMode {
+    match get_setting(SECOND_INSTANCE_ARG, SECOND_INSTANCE_ENV) {
+        Some(name) => instance_lock::SecondInstanceMode::from_name(&name).unwrap_or_else(|| {
+            eprintln!("Unknown second instance mode \"{}\", using focus", name);
+            instance_lock::SecondInstanceMode::default()
+        }),
+        None => instance_lock::SecondInstanceMode::default(),
+    }
+}
+
+/// Folder of `--export-markdown <dir>`
+#[cfg(not(target_arch = "wasm32"))]
+pub fn get_markdown_export_dir() -> Option<PathBuf> {
+    get_arg_value(std::env::args().skip(1), EXPORT_MARKDOWN_ARG).map(PathBuf::from)
+}
+
+/// Folder of `--import-markdown <dir>`
+#[cfg(not(target_arch = "wasm32"))]
+pub fn get_markdown_import_dir() -> Option<PathBuf> {
+    get_arg_value(std::env::args().skip(1), IMPORT_MARKDOWN_ARG).map(PathBuf::from)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn get_setting(arg_name: &str, env_name: &str) -> Option<String> {
+    get_arg_value(std::env::args().skip(1), arg_name)
+        .or_else(|| {
+            std::env::var(env_name)
+                .ok()
+                .filter(|value| !value.is_empty())
+        })
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn get_arg_value(mut args: impl Iterator<Item=String>, name: &str) -> Option<String> {
+    while let Some(arg) = args.next() {
+        if arg == name {
+            return args.next();
+        }
+        if let Some(value) = arg.strip_prefix(name).and_then(|rest| rest.strip_prefix('=')) {
+            return Some(String::from(value));
+        }
+    }
+    None
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn get_default_storage_file(kind: StorageKind) -> PathBuf {
+    let file_name = match kind {
+        StorageKind::Json => STORAGE_FILE_NAME,
+        #[cfg(feature = "sqlite")]
+        StorageKind::Sqlite => SQLITE_STORAGE_FILE_NAME,
+        StorageKind::Folder => FOLDER_STORAGE_NAME,
+    };
+    match directories_next::ProjectDirs::from("", "", "iced_notes") {
+        Some(project_dirs) => project_dirs.data_dir().join(file_name),
+        None => PathBuf::from(file_name),
+    }
+}
+
+/// Settings file: `--settings <path>` argument, `ICED_NOTES_SETTINGS` environment variable,
+/// or a file in the platform config directory
+#[cfg(not(target_arch = "wasm32"))]
+pub fn get_settings_file() -> PathBuf {
+    get_setting(SETTINGS_FILE_ARG, SETTINGS_FILE_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| match directories_next::ProjectDirs::from("", "", "iced_notes") {
+            Some(project_dirs) => project_dirs.config_dir().join(SETTINGS_FILE_NAME),
+            None => PathBuf::from(SETTINGS_FILE_NAME),
+        })
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn get_settings_file() -> PathBuf {
+    PathBuf::from(SETTINGS_FILE_NAME)
+}
+
+/// Days deleted posts stay in the trash: `--trash-days <days>` argument,
+/// `ICED_NOTES_TRASH_DAYS` environment variable, or `DEFAULT_TRASH_RETENTION_DAYS`
+#[cfg(not(target_arch = "wasm32"))]
+pub fn get_trash_retention() -> chrono::Duration {
+    let days = match get_setting(TRASH_RETENTION_ARG, TRASH_RETENTION_ENV) {
+        Some(value) => value.parse().unwrap_or_else(|_| {
+            eprintln!("Invalid trash retention \"{}\", using {} days", value, DEFAULT_TRASH_RETENTION_DAYS);
+            DEFAULT_TRASH_RETENTION_DAYS
+        }),
+        None => DEFAULT_TRASH_RETENTION_DAYS,
+    };
+    chrono::Duration::days(days)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn get_trash_retention() -> chrono::Duration {
+    chrono::Duration::days(DEFAULT_TRASH_RETENTION_DAYS)
+}
+
+/// Minutes without input before encrypted notes lock again: `--lock-after <minutes>` argument,
+/// `ICED_NOTES_LOCK_AFTER` environment variable, or `DEFAULT_AUTO_LOCK_MINUTES`. `None` when set to 0
+#[cfg(not(target_arch = "wasm32"))]
+pub fn get_auto_lock_after() -> Option<chrono::Duration> {
+    let minutes = match get_setting(AUTO_LOCK_ARG, AUTO_LOCK_ENV) {
+        Some(value) => value.parse().unwrap_or_else(|_| {
+            eprintln!("Invalid auto-lock timeout \"{}\", using {} minutes", value, DEFAULT_AUTO_LOCK_MINUTES);
+            DEFAULT_AUTO_LOCK_MINUTES
+        }),
+        None => DEFAULT_AUTO_LOCK_MINUTES,
+    };
+    Some(chrono::Duration::minutes(minutes)).filter(|timeout| *timeout > chrono::Duration::zero())
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn get_auto_lock_after() -> Option<chrono::Duration> {
+    Some(chrono::Duration::minutes(DEFAULT_AUTO_LOCK_MINUTES))
+}
+
+/// The browser build keeps notes in `localStorage` under this key
+#[cfg(target_arch = "wasm32")]
+pub fn get_storage() -> Box<dyn Storage> {
+    storage::open(StorageKind::Json, PathBuf::from(STORAGE_FILE_NAME))
 }
 
 pub async fn type_to_async<T>(t: T) -> T {
@@ -50,7 +277,7 @@ pub async fn type_to_async<T>(t: T) -> T {
 #[derive(Debug, Clone)]
 pub enum Message {
     NotesStorageLoaded(Result<models::Data, StorageError>),
-    NotesStorageSaved(Result<(), StorageError>),
+    NotesStorageSaved(Result<Option<SystemTime>, StorageError>),
     SelectPost(Uuid),
     ToggleIsDarkMode(bool),
     ButtonCreatePressed,
@@ -58,4 +285,70 @@ pub enum Message {
     PostTitleUpdated(String),
     PostContentUpdated(String),
     OnTextEditorAction(text_editor::Action),
+    SelectCategory(Option<Uuid>),
+    ButtonCategoryCreatePressed,
+    ButtonCategoryDeletePressed(Uuid),
+    CategoryTitleUpdated(Uuid, String),
+    PostCategorySelected(models::CategoryOption),
+    SearchInputChanged(String),
+    AutosaveTick,
+    WindowCloseRequested(window::Id),
+    SaveErrorDismissed,
+    RestoredBackupDismissed,
+    ButtonRetryLoadPressed,
+    OtherFilePathChanged(String),
+    ButtonOpenOtherFilePressed,
+    ButtonStartFreshPressed,
+    NotesStorageSetAside(Result<Option<PathBuf>, StorageError>),
+    StorageSearchCompleted(String, Result<Option<Vec<Uuid>>, StorageError>),
+    EditorModeSelected(models::EditorMode),
+    MarkdownLinkPressed(String),
+    TagFilterToggled(String),
+    TagMatchSelected(models::TagMatch),
+    TagFilterCleared,
+    TagInputChanged(String),
+    PostTagSubmitted,
+    PostTagRemoved(String),
+    ButtonRewriteLinksPressed,
+    ButtonKeepLinksPressed,
+    ButtonHistoryPressed,
+    HistoryBaseSelected(models::RevisionOption),
+    HistoryTargetSelected(models::RevisionOption),
+    ButtonRestoreRevisionPressed(chrono::DateTime<chrono::Utc>),
+    ButtonUndoDeletePressed(Uuid),
+    UndoToastTick,
+    UndoToastExpired,
+    ButtonTrashPressed,
+    ButtonRestorePostPressed(Uuid),
+    ButtonPurgePostPressed(Uuid),
+    ButtonEmptyTrashPressed,
+    Undo,
+    Redo,
+    KeyPressed(keyboard::Key, keyboard::Modifiers),
+    ShortcutTriggered(settings::ShortcutAction),
+    PaletteInputChanged(String),
+    PaletteSubmitted,
+    PaletteCommandSelected(palette::PaletteCommand),
+    PostSortSelected(models::PostSort),
+    ButtonMovePostPressed(Uuid, isize),
+    ButtonPinPostPressed(Uuid),
+    ButtonFavouritePostPressed(Uuid),
+    SelectFavourites,
+    InstanceFocusPollTick,
+    StorageWatchTick,
+    StorageModifiedChecked(Result<Option<SystemTime>, StorageError>),
+    NotesStorageReloaded(Result<models::Data, StorageError>),
+    StorageCheckedBeforeSave(Result<Option<SystemTime>, StorageError>),
+    NotesStorageLoadedBeforeSave(Result<models::Data, StorageError>),
+    ButtonKeepLocalPostPressed,
+    ButtonUseExternalPostPressed,
+    UnlockPassphraseChanged(String),
+    UnlockSubmitted,
+    NotesStorageUnlocked(Result<models::Data, StorageError>),
+    ButtonLockPressed,
+    AutoLockTick,
+    ButtonPassphrasePressed,
+    PassphraseFormChanged(lock_screen::PassphraseField, String),
+    PassphraseFormSubmitted,
+    PassphraseChanged(Result<(), StorageError>),
 }
\ No newline at end of file
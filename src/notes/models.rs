@@ -1,36 +1,143 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
 use chrono::{DateTime, Utc};
 use iced::widget::text_editor;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::notes::{MAX_POST_REVISIONS, MAX_REVISION_AGE_DAYS, MIN_REVISION_INTERVAL, REVISION_DATE_FORMAT, REVISION_IDLE_GAP, search, tags, undo, wiki_links};
+use crate::notes::undo::{PostSnapshot, UndoAction};
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Data {
     pub categories: HashMap<Uuid, Category>,
     pub posts: HashMap<Uuid, Post>,
+    /// Saved snapshots of every post, oldest first
+    #[serde(default)]
+    pub history: HashMap<Uuid, Vec<Revision>>,
+    /// Deleted posts, kept until they're purged by hand or get older than the retention period
+    #[serde(default)]
+    pub trash: HashMap<Uuid, TrashedPost>,
+    /// Post ids in the order arranged by hand, newest posts go first
+    #[serde(default)]
+    pub post_order: Vec<Uuid>,
 }
 
 #[derive(Debug)]
 pub struct NotesState {
     pub data: Data,
     pub selected_post_id: Option<Uuid>,
+    pub selected_category_id: Option<Uuid>,
+    /// The posts list shows only the favourites, in place of a category
+    pub is_favourites_selected: bool,
     pub text_editor_state: text_editor::Content,
+    pub editor_mode: EditorMode,
+    /// Order of the posts list while nothing is searched
+    pub post_sort: PostSort,
     pub search_input_value: String,
+    pub search_index: search::SearchIndex,
+    /// Posts linking to every `[[link]]` target, for backlinks and renames
+    pub link_index: wiki_links::LinkIndex,
+    /// Ranked results of the storage backend search, `None` falls back to `search_index`
+    pub search_results: Option<Vec<Uuid>>,
+    /// Number of posts per tag, kept in step with every post change
+    pub tag_counts: BTreeMap<String, usize>,
+    pub selected_tags: BTreeSet<String>,
+    pub tag_match: TagMatch,
+    pub tag_input_value: String,
+    /// Title the selected post had before it was renamed, until the links to it are rewritten or kept
+    pub pending_rename: Option<PendingRename>,
+    /// Revisions compared in the history panel of the selected post, `None` while it's closed
+    pub history_selection: Option<HistorySelection>,
+    /// When each post was last edited in this session, a post missing here starts a new one
+    pub last_edited_at: HashMap<Uuid, DateTime<Utc>>,
+    /// The posts panel lists the trash instead of the posts
+    pub is_trash_open: bool,
+    pub undo_stack: undo::UndoStack,
     pub is_loading: bool,
     pub is_dirty: bool,
+    /// Bumped on every change, lets a finished save know whether newer changes are still pending
+    pub revision: u64,
+    pub saving_revision: Option<u64>,
+    /// Posts and categories changed since the last save, for backends writing per row
+    pub pending_changes: Changes,
+    pub saving_changes: Changes,
+    /// Data as it was last read from or written to the storage, the base of merging external changes
+    pub synced_data: Data,
+    pub saving_data: Option<Data>,
+    /// The selected post was changed both here and outside the app, until one version is picked
+    pub external_conflict: Option<ExternalConflict>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrashedPost {
+    pub post: Post,
+    pub deleted_at: DateTime<Utc>,
+}
+
+/// Snapshot of a post as it was saved
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Revision {
+    pub title: String,
+    pub content: String,
+    pub saved_at: DateTime<Utc>,
+}
+
+/// Pair of versions diffed in the history panel, `None` stands for the current post
+#[derive(Debug, Clone, Default)]
+pub struct HistorySelection {
+    pub base: Option<DateTime<Utc>>,
+    pub target: Option<DateTime<Utc>>,
+}
+
+/// Entry of the revision pickers in the history panel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RevisionOption {
+    pub saved_at: Option<DateTime<Utc>>,
+    pub title: String,
+}
+
+/// Version of a post found in the storage that clashes with the edited one, `None` if it was removed there
+#[derive(Debug, Clone)]
+pub struct ExternalConflict {
+    pub post_id: Uuid,
+    pub external_post: Option<Post>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone)]
+pub struct PendingRename {
+    pub post_id: Uuid,
+    pub previous_title: String,
+}
+
+/// Ids of posts and categories touched since the last save,
+/// an id missing from `Data` at save time means the item was removed
+#[derive(Debug, Clone, Default)]
+pub struct Changes {
+    pub posts: HashSet<Uuid>,
+    pub categories: HashSet<Uuid>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Post {
     pub id: Uuid,
     pub title: String,
     pub content: String,
+    #[serde(default)]
+    pub category_id: Option<Uuid>,
+    /// Tags entered in the details view, `#hashtags` in the content are added by `Post::get_tags`
+    #[serde(default)]
+    pub tags: BTreeSet<String>,
+    /// Listed above the other posts whatever the sort order
+    #[serde(default)]
+    pub is_pinned: bool,
+    #[serde(default)]
+    pub is_favourite: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Category {
     pub id: Uuid,
     pub title: String,
@@ -38,29 +145,616 @@ pub struct Category {
     pub updated_at: DateTime<Utc>,
 }
 
+/// How the details view shows the post content
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EditorMode {
+    #[default]
+    Edit,
+    Preview,
+    Split,
+}
+
+/// Order of the posts list, persisted in the settings file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PostSort {
+    #[default]
+    Updated,
+    Created,
+    TitleAsc,
+    TitleDesc,
+    Manual,
+}
+
+/// Whether filtered posts need all of the selected tags or any of them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TagMatch {
+    #[default]
+    All,
+    Any,
+}
+
+/// Entry of the category picker in the details view, `id: None` stands for "no category"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CategoryOption {
+    pub id: Option<Uuid>,
+    pub title: String,
+}
+
 
 impl NotesState {
+    pub fn set_data(&mut self, data: Data) {
+        self.synced_data = data.clone();
+        self.data = data;
+        self.rebuild_indexes();
+        self.pending_changes = Changes::default();
+        self.undo_stack.clear();
+        self.external_conflict = None;
+    }
+    /// Search, link and tag indexes of all posts, after `data` was replaced as a whole
+    fn rebuild_indexes(&mut self) {
+        self.search_index = search::SearchIndex::build(self.data.posts.values());
+        self.link_index = wiki_links::LinkIndex::build(self.data.posts.values());
+        let data = std::mem::take(&mut self.data);
+        self.tag_counts.clear();
+        for post in data.posts.values() {
+            self.count_tags(post, true);
+        }
+        self.data = data;
+        self.selected_tags.retain(|tag| self.tag_counts.contains_key(tag));
+    }
+    pub fn mark_dirty(&mut self) {
+        self.revision += 1;
+        self.is_dirty = true;
+    }
+    pub fn is_saving(&self) -> bool {
+        self.saving_revision.is_some()
+    }
+    /// Returns the changes the save has to write
+    pub fn start_saving(&mut self) -> Changes {
+        self.saving_revision = Some(self.revision);
+        self.saving_changes = std::mem::take(&mut self.pending_changes);
+        // posts edited for a long time without a pause still get a revision now and then
+        let post_ids: Vec<Uuid> = self.saving_changes.posts.iter()
+            .filter(|post_id| !self.is_revised_within(**post_id, MIN_REVISION_INTERVAL))
+            .copied()
+            .collect();
+        for post_id in post_ids {
+            self.record_revision(post_id);
+        }
+        self.saving_data = Some(self.data.clone());
+        self.saving_changes.clone()
+    }
+    /// Clears `is_dirty` if the save succeeded and nothing changed while it was running,
+    /// a failed save puts its changes back to be written by the next one
+    pub fn finish_saving(&mut self, is_saved: bool) {
+        let saving_changes = std::mem::take(&mut self.saving_changes);
+        let saving_data = self.saving_data.take();
+        if is_saved {
+            if let Some(saving_data) = saving_data {
+                self.synced_data = saving_data;
+            }
+            if self.saving_revision == Some(self.revision) {
+                self.is_dirty = false;
+            }
+        } else {
+            self.pending_changes.merge(saving_changes);
+        }
+        self.saving_revision = None;
+    }
+    /// Merges data changed outside the app, every post, category and trashed post comes from
+    /// the side that changed it since the last sync. One changed on both sides keeps its newer version,
+    /// except the selected post which stays as it is until the user picks one in `external_conflict`.
+    /// Its external version goes into its history, so saves of the local one never lose it
+    pub fn merge_external(&mut self, external: Data) {
+        let base = std::mem::take(&mut self.synced_data);
+        let mut merged = self.data.clone();
+        let selected_post_id = self.selected_post_id;
+        let mut conflict = None;
+        merge_map(&base.posts, &mut merged.posts, &external.posts, |post_id, local, external| {
+            if Some(*post_id) == selected_post_id {
+                conflict = Some(ExternalConflict {
+                    post_id: *post_id,
+                    external_post: external.cloned(),
+                });
+                return local.cloned();
+            }
+            match (local, external) {
+                (Some(local), Some(external)) if external.updated_at > local.updated_at => Some(external.clone()),
+                // an edit wins over a removal
+                (local, external) => local.or(external).cloned(),
+            }
+        });
+        merge_map(&base.categories, &mut merged.categories, &external.categories, |_, local, external| {
+            match (local, external) {
+                (Some(local), Some(external)) if external.updated_at > local.updated_at => Some(external.clone()),
+                (local, external) => local.or(external).cloned(),
+            }
+        });
+        merge_map(&base.trash, &mut merged.trash, &external.trash, |_, local, _| local.cloned());
+        merge_map(&base.history, &mut merged.history, &external.history, |_, local, _| local.cloned());
+        if merged.post_order == base.post_order {
+            merged.post_order = external.post_order.clone();
+        }
+        // a post edited here and trashed there stays a post
+        merged.trash.retain(|post_id, _| !merged.posts.contains_key(post_id));
+
+        let selected_content = self.get_selected_post().map(|post| post.content.clone());
+        // pending changes and the undo stack stay, they're about local edits
+        self.data = merged;
+        if let Some(ExternalConflict { post_id, external_post: Some(external_post) }) = &conflict {
+            self.push_revision(*post_id, Revision::from(external_post));
+            self.pending_changes.posts.insert(*post_id);
+        }
+        self.rebuild_indexes();
+        self.synced_data = external;
+        self.external_conflict = conflict;
+        match self.get_selected_post().map(|post| post.content.clone()) {
+            None => self.selected_post_id = None,
+            Some(content) if Some(&content) != selected_content.as_ref() => {
+                self.text_editor_state = text_editor::Content::with_text(&content);
+            }
+            Some(_) => {}
+        }
+    }
+    /// Keeps the edited version of the conflicting post, the next save writes it over the external one
+    pub fn keep_local_post(&mut self) -> bool {
+        let Some(conflict) = self.external_conflict.take() else {
+            return false;
+        };
+        self.pending_changes.posts.insert(conflict.post_id);
+        true
+    }
+    /// Replaces the conflicting post with its external version, or removes it if it's gone there
+    pub fn use_external_post(&mut self) -> bool {
+        let Some(conflict) = self.external_conflict.take() else {
+            return false;
+        };
+        match conflict.external_post {
+            Some(post) => {
+                if self.selected_post_id == Some(post.id) {
+                    self.text_editor_state = text_editor::Content::with_text(&post.content);
+                }
+                self.update_post(post);
+            }
+            None => {
+                self.take_post(conflict.post_id);
+            }
+        }
+        true
+    }
+    /// Snapshots the post unless it's unchanged since its last revision
+    fn record_revision(&mut self, post_id: Uuid) {
+        if let Some(post) = self.data.posts.get(&post_id) {
+            let revision = Revision::from(post);
+            self.push_revision(post_id, revision);
+        }
+    }
+    /// Adds a revision unless the last one has the same title and content, dropping the ones
+    /// older than `MAX_REVISION_AGE_DAYS` and the oldest ones beyond `MAX_POST_REVISIONS`
+    fn push_revision(&mut self, post_id: Uuid, revision: Revision) {
+        let history = self.data.history.entry(post_id).or_default();
+        if history.last().is_some_and(|last| last.title == revision.title && last.content == revision.content) {
+            return;
+        }
+        let oldest_kept = revision.saved_at - chrono::Duration::days(MAX_REVISION_AGE_DAYS);
+        history.retain(|revision| revision.saved_at > oldest_kept);
+        history.push(revision);
+        if history.len() > MAX_POST_REVISIONS {
+            history.drain(..history.len() - MAX_POST_REVISIONS);
+        }
+    }
+    fn is_revised_within(&self, post_id: Uuid, interval: std::time::Duration) -> bool {
+        self.get_post_history(post_id)
+            .last()
+            .is_some_and(|revision| (Utc::now() - revision.saved_at).to_std().is_ok_and(|elapsed| elapsed < interval))
+    }
+    /// Snapshots the post as it was before the first edit after a pause, so every editing session
+    /// can be gone back to without a revision for each autosave
+    fn start_edit_session(&mut self, post_id: Uuid) {
+        let now = Utc::now();
+        let is_session_over = self.last_edited_at.get(&post_id)
+            .map_or(true, |edited_at| (now - *edited_at).to_std().map_or(true, |elapsed| elapsed >= REVISION_IDLE_GAP));
+        if is_session_over {
+            self.record_revision(post_id);
+        }
+        self.last_edited_at.insert(post_id, now);
+    }
+    /// Snapshots the post edited until now, switching to another post ends its editing session
+    fn end_edit_session(&mut self, post_id: Uuid) {
+        if self.last_edited_at.remove(&post_id).is_some() {
+            self.record_revision(post_id);
+            self.pending_changes.posts.insert(post_id);
+            self.mark_dirty();
+        }
+    }
+    pub fn get_post_history(&self, post_id: Uuid) -> &[Revision] {
+        self.data.history.get(&post_id).map(Vec::as_slice).unwrap_or_default()
+    }
+    pub fn toggle_history(&mut self) {
+        self.history_selection = match self.history_selection {
+            Some(_) => None,
+            // compare the last saved revision with the current post
+            None => Some(HistorySelection {
+                base: self.selected_post_id
+                    .and_then(|post_id| self.get_post_history(post_id).last())
+                    .map(|revision| revision.saved_at),
+                target: None,
+            }),
+        };
+    }
+    pub fn set_history_base(&mut self, saved_at: Option<DateTime<Utc>>) {
+        if let Some(selection) = &mut self.history_selection {
+            selection.base = saved_at;
+        }
+    }
+    pub fn set_history_target(&mut self, saved_at: Option<DateTime<Utc>>) {
+        if let Some(selection) = &mut self.history_selection {
+            selection.target = saved_at;
+        }
+    }
+    /// Title and content of a revision of the selected post, `None` picks the current post
+    pub fn get_selected_post_version(&self, saved_at: Option<DateTime<Utc>>) -> Option<(&str, &str)> {
+        let post = self.get_selected_post()?;
+        match saved_at {
+            None => Some((&post.title, &post.content)),
+            Some(saved_at) => self.get_post_history(post.id)
+                .iter()
+                .find(|revision| revision.saved_at == saved_at)
+                .map(|revision| (revision.title.as_str(), revision.content.as_str())),
+        }
+    }
+    pub fn get_revision_options(&self) -> Vec<RevisionOption> {
+        let mut options = vec![RevisionOption::current()];
+        if let Some(post_id) = self.selected_post_id {
+            options.extend(self.get_post_history(post_id).iter().rev().map(RevisionOption::from));
+        }
+        options
+    }
+    /// Brings back the title and content of a revision, the replaced version is kept in the history
+    pub fn restore_selected_post_revision(&mut self, saved_at: DateTime<Utc>) -> bool {
+        let Some(post) = self.get_selected_post() else {
+            return false;
+        };
+        let post_id = post.id;
+        let Some(revision) = self.get_post_history(post_id).iter().find(|revision| revision.saved_at == saved_at) else {
+            return false;
+        };
+        let restored_post = Post {
+            title: revision.title.clone(),
+            content: revision.content.clone(),
+            ..post.clone()
+        };
+        self.record_revision(post_id);
+        self.text_editor_state = text_editor::Content::with_text(&restored_post.content);
+        self.edit_post(restored_post);
+        self.history_selection = None;
+        true
+    }
     pub fn get_ordered_posts_list(&self) -> Vec<&Post> {
-        let mut posts_list: Vec<&Post> = self.data.posts.iter().map(|(_id, post)| post).collect();
-        posts_list.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        let query = self.get_search_query();
+        let is_in_selected_category = |post: &&Post| {
+            let is_in_category = match self.selected_category_id {
+                Some(category_id) => post.category_id == Some(category_id),
+                None => !self.is_favourites_selected || post.is_favourite
+            };
+            is_in_category && self.is_matching_selected_tags(post)
+        };
+        if !query.is_empty() {
+            // search results are already ranked by relevance and recency
+            let post_ids = match &self.search_results {
+                Some(post_ids) => post_ids.clone(),
+                None => self.search_index.search(&query),
+            };
+            return post_ids
+                .into_iter()
+                .filter_map(|post_id| self.data.posts.get(&post_id))
+                .filter(is_in_selected_category)
+                .collect();
+        }
+        let mut posts_list: Vec<&Post> = self.data.posts.iter()
+            .map(|(_id, post)| post)
+            .filter(is_in_selected_category)
+            .collect();
+        self.sort_posts(&mut posts_list);
+        // stable, so pinned posts keep the chosen order among themselves
+        posts_list.sort_by_key(|post| !post.is_pinned);
         posts_list
     }
+    fn sort_posts(&self, posts_list: &mut [&Post]) {
+        match self.post_sort {
+            PostSort::Updated => posts_list.sort_by(|a, b| b.updated_at.cmp(&a.updated_at)),
+            PostSort::Created => posts_list.sort_by(|a, b| b.created_at.cmp(&a.created_at)),
+            PostSort::TitleAsc => posts_list.sort_by_cached_key(|post| post.title.to_lowercase()),
+            PostSort::TitleDesc => {
+                posts_list.sort_by_cached_key(|post| post.title.to_lowercase());
+                posts_list.reverse();
+            }
+            PostSort::Manual => {
+                // posts missing from the arranged order, like imported ones, go last, newest first
+                let positions: HashMap<Uuid, usize> = self.data.post_order.iter()
+                    .enumerate()
+                    .map(|(position, post_id)| (*post_id, position))
+                    .collect();
+                posts_list.sort_by(|a, b| {
+                    let position = |post: &Post| positions.get(&post.id).copied().unwrap_or(usize::MAX);
+                    position(a).cmp(&position(b)).then(b.created_at.cmp(&a.created_at))
+                });
+            }
+        }
+    }
+    pub fn set_post_sort(&mut self, post_sort: PostSort) {
+        self.post_sort = post_sort;
+    }
+    /// Swaps the post with the one `offset` places away in the listed posts, for the manual order.
+    /// The arranged order is rebuilt from every post first, dropping the ids of removed ones
+    pub fn move_post(&mut self, post_id: Uuid, offset: isize) -> bool {
+        let listed_ids: Vec<Uuid> = self.get_ordered_posts_list().iter().map(|post| post.id).collect();
+        let Some(index) = listed_ids.iter().position(|id| *id == post_id) else {
+            return false;
+        };
+        let Some(other_id) = index.checked_add_signed(offset).and_then(|index| listed_ids.get(index)).copied() else {
+            return false;
+        };
+        let mut all_posts: Vec<&Post> = self.data.posts.values().collect();
+        let post_sort = std::mem::replace(&mut self.post_sort, PostSort::Manual);
+        self.sort_posts(&mut all_posts);
+        self.post_sort = post_sort;
+        let mut post_order: Vec<Uuid> = all_posts.iter().map(|post| post.id).collect();
+        let position = |id: Uuid| post_order.iter().position(|order_id| *order_id == id);
+        let positions = (position(post_id), position(other_id));
+        if let (Some(a), Some(b)) = positions {
+            post_order.swap(a, b);
+        }
+        self.data.post_order = post_order;
+        true
+    }
+    /// Selects the post `offset` places away in the posts list, the first one if none is selected
+    pub fn select_adjacent_post(&mut self, offset: isize) {
+        let post_ids: Vec<Uuid> = self.get_ordered_posts_list().iter().map(|post| post.id).collect();
+        if post_ids.is_empty() {
+            return;
+        }
+        let index = match self.selected_post_id.and_then(|post_id| post_ids.iter().position(|id| *id == post_id)) {
+            Some(index) => (index as isize + offset).clamp(0, post_ids.len() as isize - 1) as usize,
+            None => 0,
+        };
+        self.set_selected_post(Some(post_ids[index]));
+    }
+    pub fn get_search_query(&self) -> search::Query {
+        search::Query::parse(&self.search_input_value)
+    }
+    fn is_matching_selected_tags(&self, post: &Post) -> bool {
+        if self.selected_tags.is_empty() {
+            return true;
+        }
+        let tags = post.get_tags();
+        match self.tag_match {
+            TagMatch::All => self.selected_tags.iter().all(|tag| tags.contains(tag)),
+            TagMatch::Any => self.selected_tags.iter().any(|tag| tags.contains(tag)),
+        }
+    }
+    fn count_tags(&mut self, post: &Post, is_added: bool) {
+        for tag in post.get_tags() {
+            if is_added {
+                *self.tag_counts.entry(tag).or_insert(0) += 1;
+            } else if let Some(count) = self.tag_counts.get_mut(&tag) {
+                *count -= 1;
+                if *count == 0 {
+                    self.tag_counts.remove(&tag);
+                    self.selected_tags.remove(&tag);
+                }
+            }
+        }
+    }
+    /// Tags with their post counts, most used first
+    pub fn get_ordered_tags_list(&self) -> Vec<(&String, usize)> {
+        let mut tags_list: Vec<(&String, usize)> = self.tag_counts.iter()
+            .map(|(tag, count)| (tag, *count))
+            .collect();
+        tags_list.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        tags_list
+    }
+    pub fn toggle_selected_tag(&mut self, tag: String) {
+        if !self.selected_tags.remove(&tag) {
+            self.selected_tags.insert(tag);
+        }
+    }
+    pub fn set_tag_match(&mut self, tag_match: TagMatch) {
+        self.tag_match = tag_match;
+    }
+    pub fn clear_selected_tags(&mut self) {
+        self.selected_tags.clear();
+    }
+    pub fn set_tag_input_value(&mut self, value: String) {
+        self.tag_input_value = value;
+    }
+    /// Adds the tag typed in the details view to the selected post, returns whether anything changed
+    pub fn add_selected_post_tag(&mut self) -> bool {
+        let tag = tags::normalize(&self.tag_input_value);
+        let post = match (tag, self.get_selected_post()) {
+            (Some(tag), Some(post)) if !post.tags.contains(&tag) => {
+                let mut post = post.clone();
+                post.tags.insert(tag);
+                post
+            }
+            _ => return false,
+        };
+        self.tag_input_value.clear();
+        self.edit_post(post);
+        true
+    }
+    pub fn remove_selected_post_tag(&mut self, tag: &str) -> bool {
+        let post = match self.get_selected_post() {
+            Some(post) if post.tags.contains(tag) => {
+                let mut post = post.clone();
+                post.tags.remove(tag);
+                post
+            }
+            _ => return false,
+        };
+        self.edit_post(post);
+        true
+    }
+    pub fn set_editor_mode(&mut self, editor_mode: EditorMode) {
+        self.editor_mode = editor_mode;
+        self.history_selection = None;
+    }
+    pub fn set_search_input_value(&mut self, value: String) {
+        self.search_input_value = value;
+        self.search_results = None;
+    }
     pub fn set_selected_post(&mut self, post_id: Option<Uuid>) {
+        if let Some(previous_post_id) = self.selected_post_id.filter(|previous_post_id| Some(*previous_post_id) != post_id) {
+            self.end_edit_session(previous_post_id);
+        }
         self.selected_post_id = post_id;
+        self.tag_input_value.clear();
+        self.pending_rename = None;
+        self.history_selection = None;
         self.text_editor_state = text_editor::Content::with_text(&self.get_selected_post().unwrap().content)
     }
     pub fn add_post(&mut self, title: &str, content: &str) -> Post {
-        let new_post = Post::new(title, content);
+        let new_post = Post {
+            category_id: self.selected_category_id,
+            ..Post::new(title, content)
+        };
+        self.capture_post(new_post.id);
+        self.search_index.insert(&new_post);
+        self.link_index.insert(&new_post);
+        self.count_tags(&new_post, true);
+        self.pending_changes.posts.insert(new_post.id);
+        self.data.post_order.insert(0, new_post.id);
         self.data.posts.insert(new_post.id, new_post.clone());
         new_post
     }
 
+    fn take_post(&mut self, post_id: Uuid) -> Option<Post> {
+        self.capture_post(post_id);
+        self.search_index.remove(&post_id);
+        self.link_index.remove(&post_id);
+        self.pending_changes.posts.insert(post_id);
+        let removed_post = self.data.posts.remove(&post_id)?;
+        self.data.post_order.retain(|order_id| *order_id != post_id);
+        self.count_tags(&removed_post, false);
+        if self.selected_post_id == Some(post_id) {
+            self.selected_post_id = None;
+        }
+        Some(removed_post)
+    }
+
+    /// Removes the post for good, along with its history
     pub fn remove_post(&mut self, post_id: Uuid) -> Option<Post> {
-        self.data.posts.remove(&post_id)
+        self.data.history.remove(&post_id);
+        self.take_post(post_id)
+    }
+
+    /// Moves the post to the trash, its history is kept in case it's restored
+    pub fn trash_post(&mut self, post_id: Uuid) -> Option<&TrashedPost> {
+        let post = self.take_post(post_id)?;
+        self.data.trash.insert(post_id, TrashedPost {
+            post,
+            deleted_at: Utc::now(),
+        });
+        self.data.trash.get(&post_id)
+    }
+
+    pub fn restore_post(&mut self, post_id: Uuid) -> Option<Post> {
+        self.capture_post(post_id);
+        let TrashedPost { mut post, .. } = self.data.trash.remove(&post_id)?;
+        // the category may have been removed while the post was in the trash
+        if post.category_id.is_some_and(|category_id| !self.data.categories.contains_key(&category_id)) {
+            post.category_id = None;
+        }
+        // restored posts come back on top of the manual order, like new ones
+        if !self.data.post_order.contains(&post_id) {
+            self.data.post_order.insert(0, post_id);
+        }
+        self.update_post(post.clone());
+        Some(post)
     }
 
+    pub fn purge_post(&mut self, post_id: Uuid) -> Option<TrashedPost> {
+        self.capture_post(post_id);
+        let trashed_post = self.data.trash.remove(&post_id)?;
+        self.data.history.remove(&post_id);
+        self.data.post_order.retain(|order_id| *order_id != post_id);
+        self.pending_changes.posts.insert(post_id);
+        Some(trashed_post)
+    }
+
+    pub fn empty_trash(&mut self) -> bool {
+        let post_ids: Vec<Uuid> = self.data.trash.keys().copied().collect();
+        for post_id in &post_ids {
+            self.purge_post(*post_id);
+        }
+        !post_ids.is_empty()
+    }
+
+    /// Purges posts that have been in the trash for longer than `retention`, returns how many
+    pub fn purge_expired_trash(&mut self, retention: chrono::Duration) -> usize {
+        let now = Utc::now();
+        let expired: Vec<Uuid> = self.data.trash.values()
+            .filter(|trashed_post| now - trashed_post.deleted_at > retention)
+            .map(|trashed_post| trashed_post.post.id)
+            .collect();
+        for post_id in &expired {
+            self.purge_post(*post_id);
+        }
+        expired.len()
+    }
+
+    /// Trashed posts, most recently deleted first
+    pub fn get_ordered_trash_list(&self) -> Vec<&TrashedPost> {
+        let mut trash_list: Vec<&TrashedPost> = self.data.trash.values().collect();
+        trash_list.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at));
+        trash_list
+    }
+
+    pub fn toggle_trash(&mut self) {
+        self.is_trash_open = !self.is_trash_open;
+    }
+
+    /// Updates the post as changed by the user, bumping `updated_at` unless nothing changed
+    pub fn edit_post(&mut self, edited_post: Post) -> Option<Post> {
+        if self.data.posts.get(&edited_post.id) == Some(&edited_post) {
+            return None;
+        }
+        self.start_edit_session(edited_post.id);
+        self.update_post(Post {
+            updated_at: Utc::now(),
+            ..edited_post
+        })
+    }
+
+    /// Flips `is_pinned`, it's not an edit of the post so `updated_at` stays
+    pub fn toggle_post_pinned(&mut self, post_id: Uuid) -> Option<Post> {
+        let post = self.data.posts.get(&post_id)?;
+        self.update_post(Post {
+            is_pinned: !post.is_pinned,
+            ..post.clone()
+        })
+    }
+    pub fn toggle_post_favourite(&mut self, post_id: Uuid) -> Option<Post> {
+        let post = self.data.posts.get(&post_id)?;
+        self.update_post(Post {
+            is_favourite: !post.is_favourite,
+            ..post.clone()
+        })
+    }
     pub fn update_post(&mut self, updated_post: Post) -> Option<Post> {
-        self.data.posts.insert(updated_post.id, updated_post)
+        self.capture_post(updated_post.id);
+        // posts saved before history existed get their stored version as the first revision
+        if !self.data.history.contains_key(&updated_post.id) {
+            self.record_revision(updated_post.id);
+        }
+        self.search_index.insert(&updated_post);
+        self.link_index.insert(&updated_post);
+        self.count_tags(&updated_post, true);
+        self.pending_changes.posts.insert(updated_post.id);
+        let previous_post = self.data.posts.insert(updated_post.id, updated_post)?;
+        self.count_tags(&previous_post, false);
+        Some(previous_post)
     }
 
     pub fn get_selected_post(&self) -> Option<&Post> {
@@ -70,11 +764,17 @@ impl NotesState {
         }
     }
     pub fn update_selected_post_title(&mut self, next_title: String) {
-        match self.get_selected_post() {
+        match self.get_selected_post().cloned() {
             Some(post) => {
-                self.update_post(Post {
+                if self.pending_rename.as_ref().map(|rename| rename.post_id) != Some(post.id) {
+                    self.pending_rename = Some(PendingRename {
+                        post_id: post.id,
+                        previous_title: post.title.clone(),
+                    });
+                }
+                self.edit_post(Post {
                     title: next_title,
-                    ..post.clone()
+                    ..post
                 })
             }
             None => {
@@ -85,7 +785,7 @@ impl NotesState {
     pub fn update_selected_post_content(&mut self, next_content: String) {
         match self.get_selected_post() {
             Some(post) => {
-                self.update_post(Post {
+                self.edit_post(Post {
                     content: next_content,
                     ..post.clone()
                 });
@@ -95,6 +795,298 @@ impl NotesState {
             }
         };
     }
+    pub fn update_selected_post_category(&mut self, category_id: Option<Uuid>) {
+        match self.get_selected_post() {
+            Some(post) => {
+                self.edit_post(Post {
+                    category_id,
+                    ..post.clone()
+                });
+            }
+            None => {
+                panic!("Failed to update selected post category")
+            }
+        };
+    }
+
+    /// Post a `[[link]]` target refers to
+    pub fn resolve_link(&self, target: &str) -> Option<Uuid> {
+        wiki_links::resolve(target, self.data.posts.values())
+    }
+    /// Posts linking to `post`, ordered by title
+    pub fn get_backlinks(&self, post: &Post) -> Vec<&Post> {
+        let mut backlinks: Vec<&Post> = self.link_index.get_backlinks(post)
+            .into_iter()
+            .filter_map(|post_id| self.data.posts.get(&post_id))
+            .collect();
+        backlinks.sort_by(|a, b| a.title.to_lowercase().cmp(&b.title.to_lowercase()));
+        backlinks
+    }
+    /// Posts still linking to the previous title of a renamed post, with the renamed post as it was
+    fn get_renamed_links(&self) -> Option<(Post, Vec<&Post>)> {
+        let rename = self.pending_rename.as_ref()?;
+        let post = self.data.posts.get(&rename.post_id)?;
+        if post.title.trim().to_lowercase() == rename.previous_title.trim().to_lowercase() {
+            return None;
+        }
+        let previous_post = Post {
+            title: rename.previous_title.clone(),
+            ..post.clone()
+        };
+        // links by id follow the rename, only the ones by the previous title break
+        let posts = self.link_index.get_title_links(&rename.previous_title)
+            .into_iter()
+            .filter_map(|post_id| self.data.posts.get(&post_id))
+            .collect();
+        Some((previous_post, posts))
+    }
+    /// Number of posts whose links would break because of the pending rename
+    pub fn get_renamed_links_count(&self) -> usize {
+        self.get_renamed_links().map_or(0, |(_, posts)| posts.len())
+    }
+    /// Points the links to the previous title of the renamed post to its current title
+    pub fn rewrite_renamed_links(&mut self) -> bool {
+        let updated_posts: Vec<Post> = match self.get_renamed_links() {
+            Some((previous_post, posts)) => {
+                let title = &self.data.posts[&previous_post.id].title;
+                posts.into_iter()
+                    .filter_map(|post| Some(Post {
+                        content: wiki_links::rewrite_links(&post.content, &previous_post, title)?,
+                        ..post.clone()
+                    }))
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+        self.pending_rename = None;
+        let is_changed = !updated_posts.is_empty();
+        for post in updated_posts {
+            if Some(post.id) == self.selected_post_id {
+                self.text_editor_state = text_editor::Content::with_text(&post.content);
+            }
+            self.edit_post(post);
+        }
+        is_changed
+    }
+    pub fn keep_renamed_links(&mut self) {
+        self.pending_rename = None;
+    }
+
+    fn post_snapshot(&self, post_id: Uuid) -> PostSnapshot {
+        PostSnapshot {
+            post: self.data.posts.get(&post_id).cloned(),
+            trashed: self.data.trash.get(&post_id).cloned(),
+        }
+    }
+    /// Remembers how the post was before the undo step being recorded first touched it
+    fn capture_post(&mut self, post_id: Uuid) {
+        let is_captured = match &self.undo_stack.recording {
+            Some(recording) => recording.posts.contains_key(&post_id),
+            None => true,
+        };
+        if !is_captured {
+            let snapshot = self.post_snapshot(post_id);
+            if let Some(recording) = &mut self.undo_stack.recording {
+                recording.posts.insert(post_id, snapshot);
+            }
+        }
+    }
+    fn capture_category(&mut self, category_id: Uuid) {
+        let category = self.data.categories.get(&category_id).cloned();
+        if let Some(recording) = &mut self.undo_stack.recording {
+            recording.categories.entry(category_id).or_insert(category);
+        }
+    }
+    /// Runs `edit` as one undo step, the posts and categories it touches are snapshotted
+    /// before and after
+    pub fn record<T>(&mut self, action: UndoAction, edit: impl FnOnce(&mut Self) -> T) -> T {
+        self.undo_stack.recording = Some(undo::Recording {
+            action,
+            posts: HashMap::new(),
+            categories: HashMap::new(),
+            post_order: self.data.post_order.clone(),
+        });
+        let result = edit(self);
+        if let Some(recording) = self.undo_stack.recording.take() {
+            let posts: HashMap<Uuid, (PostSnapshot, PostSnapshot)> = recording.posts.into_iter()
+                .map(|(post_id, before)| (post_id, (before, self.post_snapshot(post_id))))
+                .filter(|(_, (before, after))| before != after)
+                .collect();
+            let categories: HashMap<Uuid, (Option<Category>, Option<Category>)> = recording.categories.into_iter()
+                .map(|(category_id, before)| (category_id, (before, self.data.categories.get(&category_id).cloned())))
+                .filter(|(_, (before, after))| before != after)
+                .collect();
+            let post_order = Some((recording.post_order, self.data.post_order.clone()))
+                .filter(|(before, after)| before != after);
+            if !posts.is_empty() || !categories.is_empty() || post_order.is_some() {
+                self.undo_stack.push(undo::UndoStep {
+                    action,
+                    posts,
+                    categories,
+                    post_order,
+                    recorded_at: Utc::now(),
+                });
+            }
+        }
+        result
+    }
+    /// Puts a post back the way a snapshot has it, through the same paths as regular edits
+    /// so the search index, tag counts and pending changes stay right
+    fn apply_post_snapshot(&mut self, post_id: Uuid, snapshot: PostSnapshot) {
+        match snapshot.post {
+            Some(post) => {
+                self.update_post(post);
+            }
+            None => {
+                self.take_post(post_id);
+            }
+        }
+        match snapshot.trashed {
+            Some(trashed_post) => self.data.trash.insert(post_id, trashed_post),
+            None => self.data.trash.remove(&post_id),
+        };
+        self.pending_changes.posts.insert(post_id);
+        if self.selected_post_id == Some(post_id) {
+            if let Some(post) = self.data.posts.get(&post_id) {
+                self.text_editor_state = text_editor::Content::with_text(&post.content);
+            }
+        }
+    }
+    fn apply_category_snapshot(&mut self, category_id: Uuid, category: Option<Category>) {
+        self.pending_changes.categories.insert(category_id);
+        match category {
+            Some(category) => {
+                self.data.categories.insert(category_id, category);
+            }
+            None => {
+                self.data.categories.remove(&category_id);
+                if self.selected_category_id == Some(category_id) {
+                    self.selected_category_id = None;
+                }
+            }
+        }
+    }
+    pub fn undo(&mut self) -> bool {
+        let Some(step) = self.undo_stack.pop_undo() else {
+            return false;
+        };
+        for (category_id, (before, _)) in step.categories {
+            self.apply_category_snapshot(category_id, before);
+        }
+        for (post_id, (before, _)) in step.posts {
+            self.apply_post_snapshot(post_id, before);
+        }
+        if let Some((before, _)) = step.post_order {
+            self.data.post_order = before;
+        }
+        true
+    }
+    pub fn redo(&mut self) -> bool {
+        let Some(step) = self.undo_stack.pop_redo() else {
+            return false;
+        };
+        for (category_id, (_, after)) in step.categories {
+            self.apply_category_snapshot(category_id, after);
+        }
+        for (post_id, (_, after)) in step.posts {
+            self.apply_post_snapshot(post_id, after);
+        }
+        if let Some((_, after)) = step.post_order {
+            self.data.post_order = after;
+        }
+        true
+    }
+
+    pub fn get_ordered_categories_list(&self) -> Vec<&Category> {
+        let mut categories_list: Vec<&Category> = self.data.categories.values().collect();
+        categories_list.sort_by(|a, b| a.title.to_lowercase().cmp(&b.title.to_lowercase()));
+        categories_list
+    }
+    pub fn get_category_options(&self) -> Vec<CategoryOption> {
+        let mut options = vec![CategoryOption::none()];
+        options.extend(
+            self.get_ordered_categories_list()
+                .into_iter()
+                .map(CategoryOption::from)
+        );
+        options
+    }
+    pub fn get_selected_category(&self) -> Option<&Category> {
+        match self.selected_category_id {
+            Some(category_id) => self.data.categories.get(&category_id),
+            None => None
+        }
+    }
+    pub fn set_selected_category(&mut self, category_id: Option<Uuid>) {
+        self.selected_category_id = category_id;
+        self.is_favourites_selected = false;
+    }
+    pub fn select_favourites(&mut self) {
+        self.selected_category_id = None;
+        self.is_favourites_selected = true;
+    }
+    pub fn add_category(&mut self, title: &str) -> Category {
+        let new_category = Category::new(title);
+        self.capture_category(new_category.id);
+        self.pending_changes.categories.insert(new_category.id);
+        self.data.categories.insert(new_category.id, new_category.clone());
+        new_category
+    }
+    pub fn update_category_title(&mut self, category_id: Uuid, next_title: String) -> Option<Category> {
+        let category = self.data.categories.get(&category_id)?.clone();
+        self.capture_category(category_id);
+        self.pending_changes.categories.insert(category_id);
+        self.data.categories.insert(category_id, Category {
+            title: next_title,
+            updated_at: Utc::now(),
+            ..category
+        })
+    }
+    /// Removes the category, posts assigned to it are kept and become uncategorized
+    pub fn remove_category(&mut self, category_id: Uuid) -> Option<Category> {
+        let post_ids: Vec<Uuid> = self.data.posts.values()
+            .filter(|post| post.category_id == Some(category_id))
+            .map(|post| post.id)
+            .collect();
+        for post_id in post_ids {
+            self.capture_post(post_id);
+            if let Some(post) = self.data.posts.get_mut(&post_id) {
+                post.category_id = None;
+            }
+            self.pending_changes.posts.insert(post_id);
+        }
+        self.capture_category(category_id);
+        self.pending_changes.categories.insert(category_id);
+        if self.selected_category_id == Some(category_id) {
+            self.selected_category_id = None;
+        }
+        self.data.categories.remove(&category_id)
+    }
+}
+
+
+/// Three-way merge of `external` into `local`, `resolve` picks the value of keys changed on both sides
+fn merge_map<T: Clone + PartialEq>(
+    base: &HashMap<Uuid, T>,
+    local: &mut HashMap<Uuid, T>,
+    external: &HashMap<Uuid, T>,
+    mut resolve: impl FnMut(&Uuid, Option<&T>, Option<&T>) -> Option<T>,
+) {
+    let ids: HashSet<Uuid> = base.keys().chain(local.keys()).chain(external.keys()).copied().collect();
+    for id in ids {
+        let (base_value, local_value, external_value) = (base.get(&id), local.get(&id), external.get(&id));
+        if local_value == external_value || external_value == base_value {
+            continue;
+        }
+        let value = match local_value == base_value {
+            true => external_value.cloned(),
+            false => resolve(&id, local_value, external_value),
+        };
+        match value {
+            Some(value) => local.insert(id, value),
+            None => local.remove(&id),
+        };
+    }
 }
 
 
@@ -104,10 +1096,20 @@ impl Post {
             id: Uuid::new_v4(),
             title: String::from(title),
             content: String::from(content),
+            category_id: None,
+            tags: BTreeSet::new(),
+            is_pinned: false,
+            is_favourite: false,
             created_at: Utc::now(),
             updated_at: Utc::now(),
         }
     }
+    /// Entered tags together with the `#hashtags` found in the content
+    pub fn get_tags(&self) -> BTreeSet<String> {
+        let mut tags = tags::extract_hashtags(&self.content);
+        tags.extend(self.tags.iter().cloned());
+        tags
+    }
     pub fn is_selected(&self, selected_post_id: &Option<Uuid>) -> bool {
         match selected_post_id {
             Some(selected_post_id) => { self.id == selected_post_id.clone() }
@@ -117,15 +1119,440 @@ impl Post {
 }
 
 
+impl From<&Post> for Revision {
+    fn from(post: &Post) -> Self {
+        Self {
+            title: post.title.clone(),
+            content: post.content.clone(),
+            saved_at: Utc::now(),
+        }
+    }
+}
+
+
+impl RevisionOption {
+    pub fn current() -> Self {
+        Self {
+            saved_at: None,
+            title: String::from("Current version"),
+        }
+    }
+}
+
+impl From<&Revision> for RevisionOption {
+    fn from(revision: &Revision) -> Self {
+        Self {
+            saved_at: Some(revision.saved_at),
+            title: format!("{}", revision.saved_at.format(REVISION_DATE_FORMAT)),
+        }
+    }
+}
+
+impl std::fmt::Display for RevisionOption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.title)
+    }
+}
+
+
+impl Category {
+    pub fn new(title: &str) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            title: String::from(title),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+    pub fn is_selected(&self, selected_category_id: &Option<Uuid>) -> bool {
+        *selected_category_id == Some(self.id)
+    }
+}
+
+
+impl Changes {
+    pub fn merge(&mut self, other: Changes) {
+        self.posts.extend(other.posts);
+        self.categories.extend(other.categories);
+    }
+    pub fn is_empty(&self) -> bool {
+        self.posts.is_empty() && self.categories.is_empty()
+    }
+}
+
+
+impl CategoryOption {
+    pub fn none() -> Self {
+        Self {
+            id: None,
+            title: String::from("No category"),
+        }
+    }
+}
+
+impl From<&Category> for CategoryOption {
+    fn from(category: &Category) -> Self {
+        Self {
+            id: Some(category.id),
+            title: category.title.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for CategoryOption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.title)
+    }
+}
+
+
+impl PostSort {
+    pub const ALL: [PostSort; 5] = [
+        Self::Updated,
+        Self::Created,
+        Self::TitleAsc,
+        Self::TitleDesc,
+        Self::Manual,
+    ];
+}
+
+impl std::fmt::Display for PostSort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Self::Updated => "Recently edited",
+            Self::Created => "Recently created",
+            Self::TitleAsc => "Title A–Z",
+            Self::TitleDesc => "Title Z–A",
+            Self::Manual => "Manual",
+        })
+    }
+}
+
+
 impl Default for NotesState {
     fn default() -> Self {
         Self {
             data: Default::default(),
             selected_post_id: None,
+            selected_category_id: None,
+            is_favourites_selected: false,
             is_loading: false,
             is_dirty: false,
+            revision: 0,
+            saving_revision: None,
+            pending_changes: Default::default(),
+            saving_changes: Default::default(),
+            synced_data: Default::default(),
+            saving_data: None,
+            external_conflict: None,
             text_editor_state: iced::widget::text_editor::Content::with_text(""),
+            editor_mode: EditorMode::default(),
+            post_sort: PostSort::default(),
             search_input_value: String::from(""),
+            search_index: Default::default(),
+            link_index: Default::default(),
+            search_results: None,
+            tag_counts: Default::default(),
+            selected_tags: Default::default(),
+            tag_match: TagMatch::default(),
+            tag_input_value: String::from(""),
+            pending_rename: None,
+            history_selection: None,
+            last_edited_at: Default::default(),
+            is_trash_open: false,
+            undo_stack: Default::default(),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post_with_tags(content: &str, tags: &[&str]) -> Post {
+        Post {
+            tags: tags.iter().map(|tag| String::from(*tag)).collect(),
+            ..Post::new("Title", content)
+        }
+    }
+
+    fn counts(state: &NotesState) -> Vec<(&str, usize)> {
+        state.tag_counts.iter().map(|(tag, count)| (tag.as_str(), *count)).collect()
+    }
+
+    #[test]
+    fn set_data_counts_entered_tags_and_hashtags() {
+        let mut state = NotesState::default();
+        let mut data = Data::default();
+        for post in [post_with_tags("#rust and #notes", &["work"]), post_with_tags("Just #rust", &[])] {
+            data.posts.insert(post.id, post);
+        }
+        state.set_data(data);
+        assert_eq!(counts(&state), vec![("notes", 1), ("rust", 2), ("work", 1)]);
+    }
+
+    #[test]
+    fn editing_a_post_moves_its_counts_to_the_new_tags() {
+        let mut state = NotesState::default();
+        let post = post_with_tags("#draft", &["work"]);
+        state.update_post(post.clone());
+        state.edit_post(Post {
+            content: String::from("#final"),
+            ..post
+        });
+        assert_eq!(counts(&state), vec![("final", 1), ("work", 1)]);
+    }
+
+    #[test]
+    fn tag_of_the_last_post_is_removed_and_unselected() {
+        let mut state = NotesState::default();
+        let post = post_with_tags("", &["once"]);
+        let other = post_with_tags("", &["kept"]);
+        state.update_post(post.clone());
+        state.update_post(other);
+        state.toggle_selected_tag(String::from("once"));
+        state.trash_post(post.id);
+        assert_eq!(counts(&state), vec![("kept", 1)]);
+        assert!(state.selected_tags.is_empty());
+    }
+
+    #[test]
+    fn undo_and_redo_keep_the_counts_in_step() {
+        let mut state = NotesState::default();
+        let post = post_with_tags("", &["work"]);
+        state.update_post(post.clone());
+        state.record(UndoAction::EditTags, |state| {
+            state.edit_post(Post {
+                tags: BTreeSet::from([String::from("home")]),
+                ..post.clone()
+            })
+        });
+        assert_eq!(counts(&state), vec![("home", 1)]);
+        state.undo();
+        assert_eq!(counts(&state), vec![("work", 1)]);
+        state.redo();
+        assert_eq!(counts(&state), vec![("home", 1)]);
+    }
+
+    #[test]
+    fn backlinks_follow_edits_of_the_linking_posts() {
+        let mut state = NotesState::default();
+        let target = Post::new("Target", "");
+        let by_title = Post::new("By title", "See [[target]]");
+        let by_id = Post::new("By id", &format!("See [[{}|it]]", target.id));
+        for post in [&target, &by_title, &by_id] {
+            state.update_post(post.clone());
+        }
+        let backlink_titles = |state: &NotesState| -> Vec<String> {
+            state.get_backlinks(&state.data.posts[&target.id]).iter().map(|post| post.title.clone()).collect()
+        };
+        assert_eq!(backlink_titles(&state), vec!["By id", "By title"]);
+        state.edit_post(Post {
+            content: String::from("No link any more"),
+            ..by_title.clone()
+        });
+        assert_eq!(backlink_titles(&state), vec!["By id"]);
+        state.take_post(by_id.id);
+        assert!(backlink_titles(&state).is_empty());
+    }
+
+    #[test]
+    fn rename_counts_only_the_links_by_the_previous_title() {
+        let mut state = NotesState::default();
+        let target = Post::new("Old title", "");
+        state.update_post(target.clone());
+        state.update_post(Post::new("By title", "[[Old Title]]"));
+        state.update_post(Post::new("By id", &format!("[[{}]]", target.id)));
+        state.selected_post_id = Some(target.id);
+        state.update_selected_post_title(String::from("New title"));
+        assert_eq!(state.get_renamed_links_count(), 1);
+        assert!(state.rewrite_renamed_links());
+        assert_eq!(state.get_backlinks(&state.data.posts[&target.id]).len(), 2);
+    }
+
+    #[test]
+    fn edits_without_a_pause_share_one_revision() {
+        let mut state = NotesState::default();
+        let post = Post::new("Title", "first");
+        state.set_data(Data { posts: HashMap::from([(post.id, post.clone())]), ..Default::default() });
+        for content in ["second", "third", "fourth"] {
+            state.edit_post(Post { content: String::from(content), ..state.data.posts[&post.id].clone() });
+            state.start_saving();
+            state.finish_saving(true);
+        }
+        let contents: Vec<&str> = state.get_post_history(post.id).iter().map(|revision| revision.content.as_str()).collect();
+        assert_eq!(contents, vec!["first"]);
+    }
+
+    #[test]
+    fn editing_after_a_pause_or_switching_posts_records_a_revision() {
+        let mut state = NotesState::default();
+        let post = Post::new("Title", "first");
+        let other = Post::new("Other", "");
+        state.set_data(Data { posts: HashMap::from([(post.id, post.clone()), (other.id, other.clone())]), ..Default::default() });
+        state.set_selected_post(Some(post.id));
+        state.edit_post(Post { content: String::from("second"), ..post.clone() });
+        state.last_edited_at.insert(post.id, Utc::now() - chrono::Duration::from_std(REVISION_IDLE_GAP).unwrap());
+        state.edit_post(Post { content: String::from("third"), ..post.clone() });
+        state.set_selected_post(Some(other.id));
+        let contents: Vec<&str> = state.get_post_history(post.id).iter().map(|revision| revision.content.as_str()).collect();
+        assert_eq!(contents, vec!["first", "second", "third"]);
+        assert!(state.pending_changes.posts.contains(&post.id));
+    }
+
+    #[test]
+    fn long_session_is_revised_once_the_interval_passed() {
+        let mut state = NotesState::default();
+        let post = Post::new("Title", "first");
+        state.set_data(Data { posts: HashMap::from([(post.id, post.clone())]), ..Default::default() });
+        state.edit_post(Post { content: String::from("second"), ..post.clone() });
+        state.data.history.get_mut(&post.id).unwrap()[0].saved_at -= chrono::Duration::from_std(MIN_REVISION_INTERVAL).unwrap();
+        state.start_saving();
+        assert_eq!(state.get_post_history(post.id).len(), 2);
+    }
+
+    #[test]
+    fn revisions_are_pruned_by_age() {
+        let mut state = NotesState::default();
+        let post = Post::new("Title", "current");
+        let old_revision = Revision {
+            saved_at: Utc::now() - chrono::Duration::days(MAX_REVISION_AGE_DAYS + 1),
+            ..Revision::from(&Post::new("Title", "ancient"))
+        };
+        state.set_data(Data {
+            posts: HashMap::from([(post.id, post.clone())]),
+            history: HashMap::from([(post.id, vec![old_revision])]),
+            ..Default::default()
+        });
+        state.edit_post(Post { content: String::from("edited"), ..post.clone() });
+        let contents: Vec<&str> = state.get_post_history(post.id).iter().map(|revision| revision.content.as_str()).collect();
+        assert_eq!(contents, vec!["current"]);
+    }
+
+    #[test]
+    fn new_edit_after_undo_and_redo_is_undone_on_its_own() {
+        let mut state = NotesState::default();
+        let post = Post::new("Title", "Content");
+        state.set_data(Data { posts: HashMap::from([(post.id, post.clone())]), ..Default::default() });
+        state.selected_post_id = Some(post.id);
+        state.record(UndoAction::EditTitle, |state| state.update_selected_post_title(String::from("Renamed")));
+        assert!(state.undo());
+        assert_eq!(state.data.posts[&post.id].title, "Title");
+        assert!(state.redo());
+        assert_eq!(state.data.posts[&post.id].title, "Renamed");
+        state.record(UndoAction::EditContent, |state| state.update_selected_post_content(String::from("Edited")));
+        assert!(!state.undo_stack.can_redo());
+        assert!(state.undo());
+        assert_eq!(state.data.posts[&post.id].title, "Renamed");
+        assert_eq!(state.data.posts[&post.id].content, "Content");
+    }
+
+    #[test]
+    fn moving_a_post_is_undone_and_removed_posts_leave_the_order() {
+        let mut state = NotesState::default();
+        state.set_post_sort(PostSort::Manual);
+        let first = state.add_post("First", "");
+        let second = state.add_post("Second", "");
+        assert_eq!(state.data.post_order, vec![second.id, first.id]);
+        assert!(state.record(UndoAction::MovePost, |state| state.move_post(first.id, -1)));
+        assert_eq!(state.data.post_order, vec![first.id, second.id]);
+        assert!(state.undo());
+        assert_eq!(state.data.post_order, vec![second.id, first.id]);
+        assert!(state.redo());
+        assert_eq!(state.data.post_order, vec![first.id, second.id]);
+
+        state.record(UndoAction::DeletePost, |state| state.trash_post(first.id).is_some());
+        assert_eq!(state.data.post_order, vec![second.id]);
+        assert!(state.undo());
+        assert_eq!(state.data.post_order, vec![first.id, second.id]);
+        state.trash_post(first.id);
+        state.purge_post(first.id);
+        state.take_post(second.id);
+        assert!(state.data.post_order.is_empty());
+    }
+
+    fn text_map(entries: &[(Uuid, &str)]) -> HashMap<Uuid, String> {
+        entries.iter().map(|(id, text)| (*id, String::from(*text))).collect()
+    }
+
+    /// Merges without a resolver, both-sided changes fail the test
+    fn merge_one_sided(base: &HashMap<Uuid, String>, local: &mut HashMap<Uuid, String>, external: &HashMap<Uuid, String>) {
+        merge_map(base, local, external, |id, _, _| panic!("{} changed on both sides", id));
+    }
+
+    #[test]
+    fn merge_map_keeps_local_only_changes() {
+        let (changed, added) = (Uuid::new_v4(), Uuid::new_v4());
+        let base = text_map(&[(changed, "base")]);
+        let mut local = text_map(&[(changed, "local"), (added, "new")]);
+        merge_one_sided(&base, &mut local, &base.clone());
+        assert_eq!(local, text_map(&[(changed, "local"), (added, "new")]));
+    }
+
+    #[test]
+    fn merge_map_takes_external_only_changes() {
+        let (changed, added) = (Uuid::new_v4(), Uuid::new_v4());
+        let base = text_map(&[(changed, "base")]);
+        let mut local = base.clone();
+        merge_one_sided(&base, &mut local, &text_map(&[(changed, "external"), (added, "new")]));
+        assert_eq!(local, text_map(&[(changed, "external"), (added, "new")]));
+    }
+
+    #[test]
+    fn merge_map_resolves_changes_on_both_sides() {
+        let (both, same) = (Uuid::new_v4(), Uuid::new_v4());
+        let base = text_map(&[(both, "base"), (same, "base")]);
+        let mut local = text_map(&[(both, "local"), (same, "same edit")]);
+        let external = text_map(&[(both, "external"), (same, "same edit")]);
+        let mut resolved = Vec::new();
+        merge_map(&base, &mut local, &external, |id, local, external| {
+            resolved.push(*id);
+            Some(format!("{} + {}", local.unwrap(), external.unwrap()))
+        });
+        assert_eq!(resolved, vec![both]);
+        assert_eq!(local, text_map(&[(both, "local + external"), (same, "same edit")]));
+    }
+
+    #[test]
+    fn merge_map_removal_on_one_side_wins_over_an_unchanged_other_side() {
+        let (removed_here, removed_there, edited_and_removed) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let base = text_map(&[(removed_here, "base"), (removed_there, "base"), (edited_and_removed, "base")]);
+        let mut local = text_map(&[(removed_there, "base"), (edited_and_removed, "local")]);
+        let external = text_map(&[(removed_here, "base")]);
+        let mut resolved = Vec::new();
+        merge_map(&base, &mut local, &external, |id, local, external| {
+            resolved.push(*id);
+            local.or(external).cloned()
+        });
+        assert_eq!(resolved, vec![edited_and_removed]);
+        assert_eq!(local, text_map(&[(edited_and_removed, "local")]));
+    }
+
+    #[test]
+    fn merge_external_keeps_the_edited_selected_post_and_the_undo_stack() {
+        let mut state = NotesState::default();
+        let selected = Post::new("Selected", "base");
+        let other = Post::new("Other", "base");
+        state.set_data(Data { posts: HashMap::from([(selected.id, selected.clone()), (other.id, other.clone())]), ..Default::default() });
+        state.selected_post_id = Some(selected.id);
+        state.record(UndoAction::EditContent, |state| state.update_selected_post_content(String::from("local")));
+
+        let mut external = state.synced_data.clone();
+        for post in external.posts.values_mut() {
+            post.content = String::from("external");
+            post.updated_at = Utc::now() + chrono::Duration::minutes(1);
+        }
+        state.merge_external(external);
+
+        assert_eq!(state.data.posts[&selected.id].content, "local");
+        assert_eq!(state.data.posts[&other.id].content, "external");
+        let conflict = state.external_conflict.as_ref().unwrap();
+        assert_eq!(conflict.post_id, selected.id);
+        assert_eq!(conflict.external_post.as_ref().unwrap().content, "external");
+        assert!(state.pending_changes.posts.contains(&selected.id));
+        // saved with the local version, so the external one is kept in the history
+        assert_eq!(state.get_post_history(selected.id).last().unwrap().content, "external");
+        assert!(state.undo());
+        assert_eq!(state.data.posts[&selected.id].content, "base");
+    }
+}
@@ -0,0 +1,108 @@
+use iced::{Alignment, Element, Length, theme, widget};
+use iced::widget::text_input;
+use uuid::Uuid;
+
+use crate::notes::{FontSize, Message, models};
+use crate::notes::fuzzy::fuzzy_score;
+use crate::notes::settings::{Settings, ShortcutAction};
+
+const PALETTE_RESULTS_LIMIT: usize = 12;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PaletteCommand {
+    Action(ShortcutAction),
+    OpenNote(Uuid),
+}
+
+/// Ctrl+P overlay state, entries are recomputed from the query on every view
+#[derive(Debug, Default)]
+pub struct CommandPalette {
+    pub query: String,
+    pub selected_index: usize,
+}
+
+pub struct PaletteEntry {
+    pub command: PaletteCommand,
+    pub label: String,
+    pub hint: String,
+}
+
+pub fn input_id() -> text_input::Id {
+    text_input::Id::new("command_palette")
+}
+
+
+impl CommandPalette {
+    /// Actions and notes matching the query, best first
+    pub fn entries(&self, notes: &models::NotesState, settings: &Settings) -> Vec<PaletteEntry> {
+        let actions = ShortcutAction::ALL.into_iter()
+            .filter(|action| *action != ShortcutAction::CommandPalette)
+            .map(|action| PaletteEntry {
+                command: PaletteCommand::Action(action),
+                label: String::from(action.label()),
+                hint: settings.get_binding(action).to_string(),
+            });
+        let posts = notes.get_ordered_posts_list()
+            .into_iter()
+            .map(|post| PaletteEntry {
+                command: PaletteCommand::OpenNote(post.id),
+                label: post.title.clone(),
+                hint: String::from("Note"),
+            });
+        let mut entries: Vec<(i64, PaletteEntry)> = actions.chain(posts)
+            .filter_map(|entry| Some((fuzzy_score(&self.query, &entry.label)?, entry)))
+            .collect();
+        if !self.query.trim().is_empty() {
+            entries.sort_by(|a, b| b.0.cmp(&a.0));
+        }
+        entries.into_iter()
+            .take(PALETTE_RESULTS_LIMIT)
+            .map(|(_, entry)| entry)
+            .collect()
+    }
+    pub fn move_selection(&mut self, offset: isize, entries_count: usize) {
+        if entries_count == 0 {
+            self.selected_index = 0;
+            return;
+        }
+        let index = self.selected_index as isize + offset;
+        self.selected_index = index.rem_euclid(entries_count as isize) as usize;
+    }
+}
+
+
+pub fn view<'a>(palette: &CommandPalette, entries: Vec<PaletteEntry>) -> Element<'a, Message> {
+    let input = widget::text_input("Type a command or a note title", &palette.query)
+        .id(input_id())
+        .on_input(Message::PaletteInputChanged)
+        .on_submit(Message::PaletteSubmitted)
+        .size(FontSize::Body);
+    let mut list = widget::Column::new().spacing(2);
+    if entries.is_empty() {
+        list = list.push(widget::text("Nothing found").size(FontSize::Tooltip));
+    }
+    for (index, entry) in entries.into_iter().enumerate() {
+        let row = widget::row(vec![
+            widget::text(entry.label).size(FontSize::Tooltip).width(Length::Fill).into(),
+            widget::text(entry.hint).size(FontSize::Caption).into(),
+        ])
+            .spacing(8)
+            .align_items(Alignment::Center);
+        list = list.push(
+            widget::button(row)
+                .width(Length::Fill)
+                .on_press(Message::PaletteCommandSelected(entry.command))
+                .style(match index == palette.selected_index {
+                    true => theme::Button::Primary,
+                    false => theme::Button::Text,
+                })
+        );
+    }
+    widget::container(
+        widget::column(vec![input.into(), list.into()]).spacing(8)
+    )
+        .max_width(600)
+        .padding(8)
+        .style(theme::Container::Box)
+        .into()
+}
@@ -1,33 +1,187 @@
-use iced::{alignment, Element, Length, widget};
+use iced::{Alignment, alignment, Element, Length, theme, widget};
 use iced::widget::text_editor;
 
-use crate::notes::{buttons, common, FontSize, Message, models};
+use crate::notes::{buttons, code_highlight, common, FontSize, history_panel, markdown, Message, models, tag_list, tags};
+use crate::notes::flow::Flow;
+use crate::notes::models::EditorMode;
 
-pub fn details_view<'a>(state: &'a models::NotesState, selected_post: &'a models::Post) -> Element<'a, Message> {
+fn rename_links_banner<'a>(count: usize) -> Element<'a, Message> {
+    let notes = if count == 1 { "note links" } else { "notes link" };
+    widget::container(
+        widget::row(vec![
+            widget::text(format!("{} {} to the previous title", count, notes))
+                .size(FontSize::Tooltip)
+                .width(Length::Fill)
+                .into(),
+            widget::button(widget::text("Update links").size(FontSize::Tooltip))
+                .on_press(Message::ButtonRewriteLinksPressed)
+                .into(),
+            widget::button(widget::text("Keep").size(FontSize::Tooltip))
+                .on_press(Message::ButtonKeepLinksPressed)
+                .style(theme::Button::Text)
+                .into(),
+        ])
+            .spacing(8)
+            .align_items(Alignment::Center)
+    )
+        .width(Length::Fill)
+        .padding(8)
+        .style(theme::Container::Box)
+        .into()
+}
+
+fn external_conflict_banner<'a>(conflict: &models::ExternalConflict) -> Element<'a, Message> {
+    let label = match conflict.external_post {
+        Some(_) => "This note was also changed outside the app",
+        None => "This note was removed outside the app",
+    };
+    widget::container(
+        widget::row(vec![
+            widget::text(label)
+                .size(FontSize::Tooltip)
+                .width(Length::Fill)
+                .into(),
+            widget::button(widget::text("Keep mine").size(FontSize::Tooltip))
+                .on_press(Message::ButtonKeepLocalPostPressed)
+                .into(),
+            widget::button(widget::text("Use theirs").size(FontSize::Tooltip))
+                .on_press(Message::ButtonUseExternalPostPressed)
+                .style(theme::Button::Secondary)
+                .into(),
+        ])
+            .spacing(8)
+            .align_items(Alignment::Center)
+    )
+        .width(Length::Fill)
+        .padding(8)
+        .style(theme::Container::Box)
+        .into()
+}
+
+fn backlinks_row<'a>(backlinks: Vec<&'a models::Post>) -> Element<'a, Message> {
+    let mut links: Vec<Element<Message>> = vec![
+        widget::text("Linked from").size(FontSize::Caption).into()
+    ];
+    links.extend(backlinks.into_iter().map(|post| {
+        widget::button(widget::text(&post.title).size(FontSize::Caption))
+            .padding([0, 4])
+            .style(theme::Button::Text)
+            .on_press(Message::SelectPost(post.id))
+            .into()
+    }));
+    Flow::with_children(links)
+        .spacing(4.0)
+        .into()
+}
+
+pub fn details_view<'a>(state: &'a models::NotesState, selected_post: &'a models::Post, is_dark_theme: bool) -> Element<'a, Message> {
     let title = widget::text_input("Enter Title", &selected_post.title)
         .on_input(Message::PostTitleUpdated)
         .size(FontSize::Header1);
 
     let created_at = common::post_date_created(selected_post)
+        .size(FontSize::Caption)
+        .horizontal_alignment(alignment::Horizontal::Left);
+    let updated_at = common::post_date_updated(selected_post)
         .width(Length::Fill)
         .size(FontSize::Caption)
         .horizontal_alignment(alignment::Horizontal::Left);
 
 
-    let content = text_editor(&state.text_editor_state)
-        .on_action(Message::OnTextEditorAction);
+    let category = widget::pick_list(
+        state.get_category_options(),
+        Some(match selected_post.category_id.and_then(|id| state.data.categories.get(&id)) {
+            Some(category) => models::CategoryOption::from(category),
+            None => models::CategoryOption::none(),
+        }),
+        Message::PostCategorySelected,
+    )
+        .text_size(FontSize::Caption);
 
-    let post_component = widget::Column::new()
+    // hashtags from the content can only be removed by editing the content
+    let hashtags = tags::extract_hashtags(&selected_post.content);
+    let mut tag_chips: Vec<Element<Message>> = selected_post.tags.iter()
+        .filter(|tag| !hashtags.contains(*tag))
+        .map(|tag| tag_list::tag_chip(
+            format!("#{} ×", tag),
+            false,
+            Some(Message::PostTagRemoved(tag.clone())),
+        ))
+        .chain(hashtags.iter().map(|tag| tag_list::tag_chip(format!("#{}", tag), false, None)))
+        .collect();
+    tag_chips.push(
+        widget::text_input("Add tag", &state.tag_input_value)
+            .on_input(Message::TagInputChanged)
+            .on_submit(Message::PostTagSubmitted)
+            .size(FontSize::Caption)
+            .width(120)
+            .into()
+    );
+    let tags_row = Flow::with_children(tag_chips)
+        .spacing(4.0)
+        .line_spacing(4.0);
+
+    let editor = || -> Element<'a, Message> {
+        text_editor(&state.text_editor_state)
+            .on_action(Message::OnTextEditorAction)
+            .highlight::<code_highlight::CodeHighlighter>(
+                code_highlight::Settings { is_dark_theme },
+                |highlight, _theme| highlight.to_format(),
+            )
+            .into()
+    };
+    let preview = || -> Element<'a, Message> {
+        widget::scrollable(markdown::view(&selected_post.content, is_dark_theme))
+            .height(Length::Fill)
+            .into()
+    };
+    let content: Element<Message> = match (&state.history_selection, state.editor_mode) {
+        (Some(selection), _) => history_panel::panel_history(state, selection),
+        (None, EditorMode::Edit) => editor(),
+        (None, EditorMode::Preview) => preview(),
+        (None, EditorMode::Split) => widget::row(vec![
+            widget::container(editor()).width(Length::FillPortion(1)).into(),
+            widget::container(preview()).width(Length::FillPortion(1)).into(),
+        ])
+            .spacing(16)
+            .into(),
+    };
+
+    let mut post_component = widget::Column::new()
         .spacing(16)
         .push(common::controls_row(vec![
+            buttons::IconButtonVariant::button_editor_mode(EditorMode::Edit, state.editor_mode),
+            buttons::IconButtonVariant::button_editor_mode(EditorMode::Preview, state.editor_mode),
+            buttons::IconButtonVariant::button_editor_mode(EditorMode::Split, state.editor_mode),
+            buttons::IconButtonVariant::button_history(state.history_selection.is_some()),
+            buttons::IconButtonVariant::button_post_pin(selected_post),
+            buttons::IconButtonVariant::button_post_favourite(selected_post),
             buttons::IconButtonVariant::button_post_delete(selected_post).into()
         ]))
-        .push(title)
-        .push(created_at)
-        .push(content);
+        .push(title);
+    if let Some(conflict) = state.external_conflict.as_ref().filter(|conflict| conflict.post_id == selected_post.id) {
+        post_component = post_component.push(external_conflict_banner(conflict));
+    }
+    let renamed_links_count = state.get_renamed_links_count();
+    if renamed_links_count > 0 {
+        post_component = post_component.push(rename_links_banner(renamed_links_count));
+    }
+    post_component = post_component
+        .push(widget::row(vec![created_at.into(), updated_at.into(), category.into()])
+            .spacing(8)
+            .align_items(Alignment::Center))
+        .push(tags_row);
+    let backlinks = state.get_backlinks(selected_post);
+    if !backlinks.is_empty() {
+        post_component = post_component.push(backlinks_row(backlinks));
+    }
+    let post_component = post_component.push(content);
 
     widget::Container::new(post_component)
-        .max_width(800)
+        .max_width(match state.editor_mode {
+            EditorMode::Split => 1600,
+            _ => 800,
+        })
         .width(Length::Fill)
         .padding(16)
         .center_x()
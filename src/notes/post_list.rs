@@ -1,26 +1,108 @@
-use iced::{Element, font, Font, Length, theme, widget};
+use iced::{Alignment, Color, Element, font, Font, Length, theme, widget};
 
-use crate::notes::{buttons, common, FontSize, Message, models, post_details};
+use crate::notes::{buttons, common, DEFAULT_DATE_FORMAT, FontSize, Message, models, post_details, search};
+use crate::notes::icons::{Icons, material_icon};
 
-fn panel_post_list_item(post: &models::Post, is_selected: bool) -> Element<Message> {
-    let btn = widget::button(
-        widget::Column::new()
-            .push(
-                widget::text(&post.title).size(14).font(Font {
-                    weight: font::Weight::Bold,
-                    ..Font::default()
+const HIGHLIGHT_COLOR: Color = Color { r: 0.95, g: 0.55, b: 0.1, a: 1.0 };
+
+fn highlighted_text<'a>(text: &str, terms: &[String], size: u16, font: Font) -> Element<'a, Message> {
+    let ranges = search::match_ranges_any(text, terms);
+    search::split_by_matches(text, &ranges)
+        .into_iter()
+        .fold(
+            widget::Row::new(),
+            |row, (fragment, is_match)| {
+                let fragment = widget::text(fragment).size(size);
+                row.push(if is_match {
+                    fragment
+                        .font(Font {
+                            weight: font::Weight::Bold,
+                            ..font
+                        })
+                        .style(theme::Text::Color(HIGHLIGHT_COLOR))
+                } else {
+                    fragment.font(font)
                 })
-            ).width((FontSize::Body as i32 * 12) as f32)
+            })
+        .into()
+}
+
+fn panel_post_list_item<'a>(post: &'a models::Post, is_selected: bool, terms: &[String], is_movable: bool) -> Element<'a, Message> {
+    let mut title = widget::Row::new()
+        .spacing(4)
+        .align_items(Alignment::Center);
+    if post.is_pinned {
+        title = title.push(material_icon(Icons::PushPin).size(12));
+    }
+    if post.is_favourite {
+        title = title.push(material_icon(Icons::Star).size(12));
+    }
+    let mut item = widget::Column::new()
+        .push(
+            title.push(highlighted_text(&post.title, terms, 14, Font {
+                weight: font::Weight::Bold,
+                ..Font::default()
+            }))
+        ).width((FontSize::Body as i32 * 12) as f32);
+    if let Some(snippet) = search::content_snippet(&post.content, terms) {
+        item = item.push(highlighted_text(&snippet, terms, 12, Font::default()));
+    }
+    let tags = post.get_tags();
+    if !tags.is_empty() {
+        let tags = tags.iter().map(|tag| format!("#{}", tag)).collect::<Vec<_>>().join(" ");
+        item = item.push(widget::text(tags).size(10));
+    }
+    let btn = widget::button(
+        item
             .push(
-                common::post_date_created(post).size(10)
+                common::post_date_updated(post).size(10)
             )
     )
-        .on_press(Message::SelectPost(post.id));
-    if is_selected {
-        btn.style(theme::Button::Positive).into()
+        .on_press(Message::SelectPost(post.id))
+        .style(match is_selected {
+            true => theme::Button::Positive,
+            false => theme::Button::Text,
+        });
+    if !is_movable {
+        return btn.into();
+    }
+    widget::row(vec![
+        btn.width(Length::Fill).into(),
+        widget::column(vec![
+            buttons::IconButtonVariant::button_post_move(post.id, -1),
+            buttons::IconButtonVariant::button_post_move(post.id, 1),
+        ]).into(),
+    ])
+        .align_items(Alignment::Center)
+        .width((FontSize::Body as i32 * 12) as f32)
+        .into()
+}
+
+pub fn search_input_id() -> widget::text_input::Id {
+    widget::text_input::Id::new("search")
+}
+
+fn panel_search<'a>(state: &'a models::NotesState, found: usize) -> Element<'a, Message> {
+    let input = widget::text_input("Search", &state.search_input_value)
+        .id(search_input_id())
+        .on_input(Message::SearchInputChanged)
+        .size(14)
+        .width((FontSize::Body as i32 * 12) as f32);
+    let mut search = widget::Column::new()
+        .spacing(4)
+        .push(input);
+    if !state.get_search_query().is_empty() {
+        search = search.push(
+            widget::text(format!("Found: {}", found)).size(FontSize::Caption)
+        );
     } else {
-        btn.style(theme::Button::Text).into()
+        search = search.push(
+            widget::pick_list(models::PostSort::ALL, Some(state.post_sort), Message::PostSortSelected)
+                .text_size(FontSize::Caption)
+                .width((FontSize::Body as i32 * 12) as f32)
+        );
     }
+    search.into()
 }
 
 fn panel_post_list_empty<'a>() -> Element<'a, Message> {
@@ -31,9 +113,79 @@ fn panel_post_list_empty<'a>() -> Element<'a, Message> {
 }
 
 
+fn panel_list_controls<'a>(state: &'a models::NotesState) -> Element<'a, Message> {
+    widget::row(vec![
+        buttons::IconButtonVariant::button_post_add(),
+        widget::horizontal_space(Length::Fill).into(),
+        buttons::IconButtonVariant::button_trash(state.is_trash_open),
+    ])
+        .width((FontSize::Body as i32 * 12) as f32)
+        .into()
+}
+
+fn panel_trash_list_item<'a>(trashed_post: &'a models::TrashedPost) -> Element<'a, Message> {
+    widget::row(vec![
+        widget::column(vec![
+            widget::text(&trashed_post.post.title).size(14).font(Font {
+                weight: font::Weight::Bold,
+                ..Font::default()
+            }).into(),
+            widget::text(format!("Deleted {}", trashed_post.deleted_at.format(DEFAULT_DATE_FORMAT)))
+                .size(10)
+                .into(),
+        ])
+            .width(Length::Fill)
+            .into(),
+        buttons::IconButtonVariant::button_post_restore(trashed_post.post.id),
+        buttons::IconButtonVariant::button_post_purge(trashed_post.post.id),
+    ])
+        .spacing(4)
+        .align_items(Alignment::Center)
+        .width((FontSize::Body as i32 * 12) as f32)
+        .into()
+}
+
+/// Deleted posts with restore and purge buttons, shown instead of the posts list
+fn panel_trash_list<'a>(state: &'a models::NotesState) -> Element<'a, Message> {
+    let trash_list = state.get_ordered_trash_list();
+    let mut items = widget::Column::new().spacing(8);
+    if trash_list.is_empty() {
+        items = items.push(widget::text("Trash is empty"));
+    } else {
+        items = items.push(
+            widget::button(widget::text("Empty trash").size(FontSize::Caption))
+                .on_press(Message::ButtonEmptyTrashPressed)
+                .style(theme::Button::Destructive)
+        );
+    }
+    for trashed_post in trash_list {
+        items = items.push(panel_trash_list_item(trashed_post));
+    }
+    widget::container(widget::column(vec![
+        panel_list_controls(state),
+        widget::scrollable(
+            widget::row(vec![
+                widget::horizontal_space(4).into(),
+                items.into(),
+                widget::horizontal_space(14).into(),
+            ])
+        ).into(),
+    ]).spacing(8))
+        .into()
+}
+
+
 pub fn panel_posts_list<'a>(state: &'a models::NotesState) -> Element<'a, Message> {
-    if state.data.posts.len() > 0 {
-        let posts: Element<Message> = state.get_ordered_posts_list()
+    if state.is_trash_open {
+        return panel_trash_list(state);
+    }
+    let posts_list = state.get_ordered_posts_list();
+    let found = posts_list.len();
+    let terms = state.get_search_query().highlight_terms();
+    // search results keep their ranking, arranging them by hand would be lost
+    let is_movable = state.post_sort == models::PostSort::Manual && state.get_search_query().is_empty();
+    if posts_list.len() > 0 {
+        let posts: Element<Message> = posts_list
             .into_iter()
             .fold(
                 widget::Column::new().spacing(8),
@@ -42,10 +194,13 @@ pub fn panel_posts_list<'a>(state: &'a models::NotesState) -> Element<'a, Messag
                         panel_post_list_item(
                             &post,
                             post.is_selected(&state.selected_post_id),
+                            &terms,
+                            is_movable,
                         ))
                 }).into();
         widget::container(widget::column(vec![
-            buttons::IconButtonVariant::button_post_add(),
+            panel_list_controls(state),
+            panel_search(state, found),
             widget::scrollable(
                 widget::row(vec![
                     widget::horizontal_space(4).into(),
@@ -53,13 +208,14 @@ pub fn panel_posts_list<'a>(state: &'a models::NotesState) -> Element<'a, Messag
                     widget::horizontal_space(14).into(),
                 ])
             ).into(),
-        ]))
+        ]).spacing(8))
 
 
             .into()
     } else {
         widget::container(widget::column(vec![
-            buttons::IconButtonVariant::button_post_add(),
+            panel_list_controls(state),
+            panel_search(state, found),
             widget::scrollable(
                 widget::row(vec![
                     widget::horizontal_space(4).into(),
@@ -67,7 +223,7 @@ pub fn panel_posts_list<'a>(state: &'a models::NotesState) -> Element<'a, Messag
                     widget::horizontal_space(14).into(),
                 ])
             ).into(),
-        ]))
+        ]).spacing(8))
 
 
             .into()
@@ -75,10 +231,10 @@ pub fn panel_posts_list<'a>(state: &'a models::NotesState) -> Element<'a, Messag
 }
 
 
-pub fn panel_post_selected<'a>(state: &'a models::NotesState) -> Element<'a, Message> {
+pub fn panel_post_selected<'a>(state: &'a models::NotesState, is_dark_theme: bool) -> Element<'a, Message> {
     match state.get_selected_post() {
         Some(post) => {
-            post_details::details_view(state, post).into()
+            post_details::details_view(state, post, is_dark_theme).into()
         }
         None => {
             post_details::details_view_empty()
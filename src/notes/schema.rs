@@ -0,0 +1,256 @@
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::notes::models::Data;
+use crate::notes::storage::StorageError;
+
+/// Version written into every saved notes file, bump it together with a new entry in `MIGRATIONS`
+pub const SCHEMA_VERSION: u64 = 6;
+
+pub static VERSION_FIELD: &str = "version";
+
+type Migration = fn(Value) -> Result<Value, String>;
+
+/// `MIGRATIONS[n]` upgrades a document from version `n` to version `n + 1`
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [
+    migrate_v0_to_v1,
+    migrate_v1_to_v2,
+    migrate_v2_to_v3,
+    migrate_v3_to_v4,
+    migrate_v4_to_v5,
+    migrate_v5_to_v6,
+];
+
+#[derive(Serialize)]
+struct VersionedDocument<'a, T: Serialize> {
+    version: u64,
+    #[serde(flatten)]
+    data: &'a T,
+}
+
+
+/// Notes as a versioned JSON document, for backends storing parts of it separately
+pub fn encode_value<T: Serialize>(notes: &T) -> Result<Value, StorageError> {
+    serde_json::to_value(VersionedDocument {
+        version: SCHEMA_VERSION,
+        data: notes,
+    })
+        .map_err(StorageError::save_format)
+}
+
+pub fn encode<T: Serialize>(notes: &T, is_pretty: bool) -> Result<String, StorageError> {
+    let document = VersionedDocument {
+        version: SCHEMA_VERSION,
+        data: notes,
+    };
+    match is_pretty {
+        true => serde_json::to_string_pretty(&document),
+        false => serde_json::to_string(&document),
+    }
+        .map_err(StorageError::save_format)
+}
+
+/// Parses a notes file of any known version, upgrading it to the current one,
+/// files written by a newer version of the app are refused instead of being misread
+pub fn decode(path: &Path, contents: &str) -> Result<Data, StorageError> {
+    let document: Value = serde_json::from_str(contents)
+        .map_err(|error| StorageError::load_format(path, error))?;
+    decode_value(path, document)
+}
+
+pub fn decode_value(path: &Path, mut document: Value) -> Result<Data, StorageError> {
+    // files written before versioning have no version field
+    let version = match document.get(VERSION_FIELD) {
+        None => 0,
+        // a version that isn't a number makes the file unreadable, not a version 0 file
+        Some(version) => serde_json::from_value::<u64>(version.clone())
+            .map_err(|error| StorageError::load_format(path, error))?,
+    };
+    if version > SCHEMA_VERSION {
+        return Err(StorageError::UnsupportedVersion {
+            path: path.to_path_buf(),
+            version,
+        });
+    }
+
+    for (from_version, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        document = migration(document).map_err(|reason| StorageError::MigrationError {
+            path: path.to_path_buf(),
+            version: from_version as u64,
+            reason,
+        })?;
+    }
+
+    serde_json::from_value(document)
+        .map_err(|error| StorageError::load_format(path, error))
+}
+
+
+/// Posts got an optional `category_id`
+fn migrate_v0_to_v1(mut document: Value) -> Result<Value, String> {
+    let root = document.as_object_mut().ok_or("document is not an object")?;
+    root.entry("categories").or_insert_with(|| json!({}));
+    let posts = root.entry("posts")
+        .or_insert_with(|| json!({}))
+        .as_object_mut()
+        .ok_or("posts is not an object")?;
+    for post in posts.values_mut() {
+        post.as_object_mut()
+            .ok_or("post is not an object")?
+            .entry("category_id")
+            .or_insert(Value::Null);
+    }
+    root.insert(VERSION_FIELD.to_string(), json!(1));
+    Ok(document)
+}
+
+/// Posts got a list of `tags`
+fn migrate_v1_to_v2(mut document: Value) -> Result<Value, String> {
+    let root = document.as_object_mut().ok_or("document is not an object")?;
+    let posts = root.entry("posts")
+        .or_insert_with(|| json!({}))
+        .as_object_mut()
+        .ok_or("posts is not an object")?;
+    for post in posts.values_mut() {
+        post.as_object_mut()
+            .ok_or("post is not an object")?
+            .entry("tags")
+            .or_insert_with(|| json!([]));
+    }
+    root.insert(VERSION_FIELD.to_string(), json!(2));
+    Ok(document)
+}
+
+/// Revision `history` of the posts is kept next to them
+fn migrate_v2_to_v3(mut document: Value) -> Result<Value, String> {
+    let root = document.as_object_mut().ok_or("document is not an object")?;
+    root.entry("history").or_insert_with(|| json!({}));
+    root.insert(VERSION_FIELD.to_string(), json!(3));
+    Ok(document)
+}
+
+/// Deleted posts go to the `trash` instead of being removed
+fn migrate_v3_to_v4(mut document: Value) -> Result<Value, String> {
+    let root = document.as_object_mut().ok_or("document is not an object")?;
+    root.entry("trash").or_insert_with(|| json!({}));
+    root.insert(VERSION_FIELD.to_string(), json!(4));
+    Ok(document)
+}
+
+/// Posts can be arranged by hand in `post_order`
+fn migrate_v4_to_v5(mut document: Value) -> Result<Value, String> {
+    let root = document.as_object_mut().ok_or("document is not an object")?;
+    root.entry("post_order").or_insert_with(|| json!([]));
+    root.insert(VERSION_FIELD.to_string(), json!(5));
+    Ok(document)
+}
+
+/// Posts can be pinned and marked as favourites
+fn migrate_v5_to_v6(mut document: Value) -> Result<Value, String> {
+    let root = document.as_object_mut().ok_or("document is not an object")?;
+    let posts = root.entry("posts")
+        .or_insert_with(|| json!({}))
+        .as_object_mut()
+        .ok_or("posts is not an object")?;
+    for post in posts.values_mut() {
+        let post = post.as_object_mut().ok_or("post is not an object")?;
+        post.entry("is_pinned").or_insert(json!(false));
+        post.entry("is_favourite").or_insert(json!(false));
+    }
+    root.insert(VERSION_FIELD.to_string(), json!(6));
+    Ok(document)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn path() -> PathBuf {
+        PathBuf::from("notes.json")
+    }
+
+    fn v0_document(post_id: Uuid) -> Value {
+        json!({
+            "categories": {},
+            "posts": {
+                post_id.to_string(): {
+                    "id": post_id,
+                    "title": "Old post",
+                    "content": "Written before versioning",
+                    "created_at": "2023-01-01T00:00:00Z",
+                    "updated_at": "2023-01-02T00:00:00Z",
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn every_migration_bumps_the_version_by_one() {
+        let mut document = v0_document(Uuid::new_v4());
+        for (from_version, migration) in MIGRATIONS.iter().enumerate() {
+            document = migration(document).unwrap();
+            assert_eq!(document[VERSION_FIELD], json!(from_version as u64 + 1));
+        }
+        assert_eq!(document[VERSION_FIELD], json!(SCHEMA_VERSION));
+    }
+
+    #[test]
+    fn v0_document_upgrades_to_current_data() {
+        let post_id = Uuid::new_v4();
+        let data = decode_value(&path(), v0_document(post_id)).unwrap();
+        let post = &data.posts[&post_id];
+
+        assert_eq!(post.title, "Old post");
+        assert_eq!(post.category_id, None);
+        assert!(post.tags.is_empty());
+        assert!(!post.is_pinned && !post.is_favourite);
+        assert!(data.history.is_empty() && data.trash.is_empty() && data.post_order.is_empty());
+    }
+
+    #[test]
+    fn encoded_data_decodes_unchanged() {
+        let post = crate::notes::models::Post::new("Title", "Content");
+        let mut data = Data::default();
+        data.posts.insert(post.id, post.clone());
+        data.post_order.push(post.id);
+
+        let decoded = decode(&path(), &encode(&data, false).unwrap()).unwrap();
+
+        assert_eq!(decoded.posts[&post.id], post);
+        assert_eq!(decoded.post_order, vec![post.id]);
+    }
+
+    #[test]
+    fn newer_version_is_refused() {
+        let document = json!({ "version": SCHEMA_VERSION + 1, "categories": {}, "posts": {} });
+
+        assert!(matches!(
+            decode_value(&path(), document),
+            Err(StorageError::UnsupportedVersion { version, .. }) if version == SCHEMA_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn version_that_is_not_a_number_is_a_format_error() {
+        let document = json!({ "version": "two", "categories": {}, "posts": {} });
+
+        assert!(matches!(decode_value(&path(), document), Err(StorageError::LoadFileFormatError { .. })));
+    }
+
+    #[test]
+    fn failed_migration_reports_its_version() {
+        let document = json!({ "version": 1, "categories": {}, "posts": [] });
+
+        assert!(matches!(
+            decode_value(&path(), document),
+            Err(StorageError::MigrationError { version: 1, .. })
+        ));
+    }
+}
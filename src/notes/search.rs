@@ -0,0 +1,437 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Range;
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::notes::models::Post;
+
+const SNIPPET_CONTEXT_CHARS: usize = 24;
+const TITLE_TERM_WEIGHT: f64 = 2.0;
+const RECENCY_HALF_LIFE_DAYS: f64 = 30.0;
+
+/// Incremental inverted index over posts title and content
+///
+/// Title and content are tokenized into one positional stream, content positions
+/// start after a gap so a phrase never spans the title/content boundary.
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    postings: BTreeMap<String, HashMap<Uuid, Vec<usize>>>,
+    documents: HashMap<Uuid, IndexedDocument>,
+}
+
+#[derive(Debug)]
+struct IndexedDocument {
+    terms: HashSet<String>,
+    title_len: usize,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryTerm {
+    pub text: String,
+    pub is_prefix: bool,
+}
+
+/// Parsed search query
+///
+/// `word` and `word*` are prefix terms when written with a star or being the last word
+/// still being typed, `"some words"` is a phrase and `-word` excludes posts containing it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    pub terms: Vec<QueryTerm>,
+    pub phrases: Vec<Vec<String>>,
+    pub excluded: Vec<QueryTerm>,
+}
+
+
+pub fn tokenize(text: &str) -> impl Iterator<Item=String> + '_ {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+}
+
+
+impl Query {
+    pub fn parse(input: &str) -> Self {
+        let mut query = Query::default();
+        let mut rest = input;
+        while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+            rest = &rest[start..];
+            if let Some(quoted) = rest.strip_prefix('"') {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                let phrase: Vec<String> = tokenize(&quoted[..end]).collect();
+                match phrase.len() {
+                    0 => {}
+                    1 => query.terms.push(QueryTerm { text: phrase[0].clone(), is_prefix: false }),
+                    _ => query.phrases.push(phrase),
+                }
+                rest = quoted.get(end + 1..).unwrap_or("");
+                continue;
+            }
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let word = &rest[..end];
+            let is_last = end == rest.len();
+            rest = &rest[end..];
+
+            let (word, is_excluded) = match word.strip_prefix('-') {
+                Some(word) => (word, true),
+                None => (word, false),
+            };
+            let (word, is_prefix) = match word.strip_suffix('*') {
+                Some(word) => (word, true),
+                None => (word, is_last && !is_excluded),
+            };
+            let tokens: Vec<String> = tokenize(word).collect();
+            let last_index = tokens.len().saturating_sub(1);
+            for (index, text) in tokens.into_iter().enumerate() {
+                let term = QueryTerm { text, is_prefix: is_prefix && index == last_index };
+                if is_excluded {
+                    query.excluded.push(term);
+                } else {
+                    query.terms.push(term);
+                }
+            }
+        }
+        query
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty() && self.phrases.is_empty() && self.excluded.is_empty()
+    }
+
+    /// Words worth highlighting in the rendered results
+    pub fn highlight_terms(&self) -> Vec<String> {
+        self.terms.iter()
+            .map(|term| term.text.clone())
+            .chain(self.phrases.iter().flatten().cloned())
+            .collect()
+    }
+}
+
+
+impl SearchIndex {
+    pub fn build<'a>(posts: impl IntoIterator<Item=&'a Post>) -> Self {
+        let mut index = Self::default();
+        for post in posts {
+            index.insert(post);
+        }
+        index
+    }
+
+    pub fn insert(&mut self, post: &Post) {
+        self.remove(&post.id);
+        let title_len = tokenize(&post.title).count();
+        let tokens = tokenize(&post.title)
+            .enumerate()
+            .chain(tokenize(&post.content).enumerate().map(|(position, token)| (title_len + 1 + position, token)));
+        let mut terms = HashSet::new();
+        for (position, token) in tokens {
+            self.postings
+                .entry(token.clone())
+                .or_default()
+                .entry(post.id)
+                .or_default()
+                .push(position);
+            terms.insert(token);
+        }
+        self.documents.insert(post.id, IndexedDocument {
+            terms,
+            title_len,
+            updated_at: post.updated_at,
+        });
+    }
+
+    pub fn remove(&mut self, post_id: &Uuid) {
+        let Some(document) = self.documents.remove(post_id) else {
+            return;
+        };
+        for term in document.terms {
+            if let Some(postings) = self.postings.get_mut(&term) {
+                postings.remove(post_id);
+                if postings.is_empty() {
+                    self.postings.remove(&term);
+                }
+            }
+        }
+    }
+
+    /// Post ids matching all terms and phrases and none of the excluded terms, best match first
+    pub fn search(&self, query: &Query) -> Vec<Uuid> {
+        let mut scores: Option<HashMap<Uuid, f64>> = None;
+
+        for term in &query.terms {
+            let term_scores = self.term_scores(term);
+            scores = Some(match scores {
+                None => term_scores,
+                Some(scores) => intersect(scores, term_scores),
+            });
+        }
+        for phrase in &query.phrases {
+            let phrase_scores = self.phrase_scores(phrase);
+            scores = Some(match scores {
+                None => phrase_scores,
+                Some(scores) => intersect(scores, phrase_scores),
+            });
+        }
+
+        let mut scores = scores.unwrap_or_else(|| {
+            self.documents.keys().map(|post_id| (*post_id, 0.0)).collect()
+        });
+        for term in &query.excluded {
+            for post_id in self.term_scores(term).keys() {
+                scores.remove(post_id);
+            }
+        }
+
+        let now = Utc::now();
+        let mut ranked: Vec<(Uuid, f64)> = scores.into_iter()
+            .map(|(post_id, score)| {
+                let age_days = self.documents.get(&post_id)
+                    .map(|document| (now - document.updated_at).num_hours().max(0) as f64 / 24.0)
+                    .unwrap_or(0.0);
+                (post_id, (score + 1.0) * (1.0 + (0.5_f64).powf(age_days / RECENCY_HALF_LIFE_DAYS)))
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        ranked.into_iter().map(|(post_id, _score)| post_id).collect()
+    }
+
+    fn matching_postings(&self, term: &QueryTerm) -> Vec<&HashMap<Uuid, Vec<usize>>> {
+        if term.is_prefix {
+            self.postings
+                .range(term.text.clone()..)
+                .take_while(|(text, _)| text.starts_with(&term.text))
+                .map(|(_, postings)| postings)
+                .collect()
+        } else {
+            self.postings.get(&term.text).into_iter().collect()
+        }
+    }
+
+    fn term_scores(&self, term: &QueryTerm) -> HashMap<Uuid, f64> {
+        let mut scores = HashMap::new();
+        for postings in self.matching_postings(term) {
+            for (post_id, positions) in postings {
+                let title_len = self.documents.get(post_id).map(|document| document.title_len).unwrap_or(0);
+                let frequency: f64 = positions.iter()
+                    .map(|position| if *position < title_len { TITLE_TERM_WEIGHT } else { 1.0 })
+                    .sum();
+                *scores.entry(*post_id).or_insert(0.0) += frequency;
+            }
+        }
+        scores
+    }
+
+    fn phrase_scores(&self, phrase: &[String]) -> HashMap<Uuid, f64> {
+        let mut scores = HashMap::new();
+        let Some(first) = self.postings.get(&phrase[0]) else {
+            return scores;
+        };
+        for (post_id, positions) in first {
+            let occurrences = positions.iter()
+                .filter(|start| {
+                    phrase.iter().enumerate().skip(1).all(|(offset, word)| {
+                        self.postings.get(word)
+                            .and_then(|postings| postings.get(post_id))
+                            .map(|positions| positions.binary_search(&(**start + offset)).is_ok())
+                            .unwrap_or(false)
+                    })
+                })
+                .count();
+            if occurrences > 0 {
+                scores.insert(*post_id, (occurrences * phrase.len()) as f64);
+            }
+        }
+        scores
+    }
+}
+
+fn intersect(scores: HashMap<Uuid, f64>, other: HashMap<Uuid, f64>) -> HashMap<Uuid, f64> {
+    scores.into_iter()
+        .filter_map(|(post_id, score)| other.get(&post_id).map(|other_score| (post_id, score + other_score)))
+        .collect()
+}
+
+
+/// Byte ranges of every case-insensitive occurrence of `needle` in `haystack`
+pub fn match_ranges(haystack: &str, needle: &str) -> Vec<Range<usize>> {
+    let needle: Vec<char> = needle.chars().flat_map(char::to_lowercase).collect();
+    let mut ranges = Vec::new();
+    if needle.is_empty() {
+        return ranges;
+    }
+    let mut skip_until = 0;
+    for (start, _) in haystack.char_indices() {
+        if start < skip_until {
+            continue;
+        }
+        let mut lowered = haystack[start..]
+            .char_indices()
+            .flat_map(|(offset, c)| c.to_lowercase().map(move |lower| (offset + c.len_utf8(), lower)));
+        let mut end = start;
+        let is_match = needle.iter().all(|expected| match lowered.next() {
+            Some((offset, lower)) if lower == *expected => {
+                end = start + offset;
+                true
+            }
+            _ => false
+        });
+        if is_match {
+            ranges.push(start..end);
+            skip_until = end;
+        }
+    }
+    ranges
+}
+
+/// Sorted, non overlapping byte ranges of any of the `needles` in `haystack`
+pub fn match_ranges_any(haystack: &str, needles: &[String]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = needles.iter()
+        .flat_map(|needle| match_ranges(haystack, needle))
+        .collect();
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Splits `text` into `(fragment, is_match)` pairs so matched parts can be rendered highlighted
+pub fn split_by_matches<'a>(text: &'a str, ranges: &[Range<usize>]) -> Vec<(&'a str, bool)> {
+    let mut fragments = Vec::new();
+    let mut position = 0;
+    for range in ranges {
+        if range.start > position {
+            fragments.push((&text[position..range.start], false));
+        }
+        fragments.push((&text[range.clone()], true));
+        position = range.end;
+    }
+    if position < text.len() {
+        fragments.push((&text[position..], false));
+    }
+    fragments
+}
+
+/// One line excerpt of `content` around the first match of any of the `needles`
+pub fn content_snippet(content: &str, needles: &[String]) -> Option<String> {
+    let first = match_ranges_any(content, needles).into_iter().next()?;
+    let start = content[..first.start]
+        .char_indices()
+        .rev()
+        .nth(SNIPPET_CONTEXT_CHARS - 1)
+        .map(|(index, _)| index)
+        .unwrap_or(0);
+    let end = content[first.end..]
+        .char_indices()
+        .nth(SNIPPET_CONTEXT_CHARS)
+        .map(|(index, _)| first.end + index)
+        .unwrap_or(content.len());
+    let mut snippet = content[start..end].replace(['\n', '\r', '\t'], " ");
+    if start > 0 {
+        snippet.insert(0, '…');
+    }
+    if end < content.len() {
+        snippet.push('…');
+    }
+    Some(snippet)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(text: &str, is_prefix: bool) -> QueryTerm {
+        QueryTerm { text: String::from(text), is_prefix }
+    }
+
+    #[test]
+    fn parse_makes_last_word_a_prefix() {
+        let query = Query::parse("Rust notes");
+
+        assert_eq!(query.terms, vec![term("rust", false), term("notes", true)]);
+    }
+
+    #[test]
+    fn parse_reads_phrases_stars_and_exclusions() {
+        let query = Query::parse("\"iced widgets\" async* -draft done");
+
+        assert_eq!(query.phrases, vec![vec![String::from("iced"), String::from("widgets")]]);
+        assert_eq!(query.terms, vec![term("async", true), term("done", true)]);
+        assert_eq!(query.excluded, vec![term("draft", false)]);
+    }
+
+    #[test]
+    fn parse_turns_one_word_phrase_into_exact_term() {
+        let query = Query::parse("\"rust\" ");
+
+        assert_eq!(query.terms, vec![term("rust", false)]);
+        assert!(query.phrases.is_empty());
+    }
+
+    #[test]
+    fn parse_of_blank_input_is_empty() {
+        assert!(Query::parse("  \"\" - ").is_empty());
+    }
+
+    #[test]
+    fn search_needs_every_term() {
+        let rust = Post::new("Rust", "ownership and borrowing");
+        let iced = Post::new("Iced", "rust gui with widgets");
+        let index = SearchIndex::build([&rust, &iced]);
+
+        assert_eq!(index.search(&Query::parse("rust widgets")), vec![iced.id]);
+        assert!(index.search(&Query::parse("python")).is_empty());
+    }
+
+    #[test]
+    fn search_ranks_title_matches_first() {
+        let in_content = Post::new("Notes", "about rust");
+        let in_title = Post::new("Rust", "notes");
+        let index = SearchIndex::build([&in_content, &in_title]);
+
+        assert_eq!(index.search(&Query::parse("rust ")), vec![in_title.id, in_content.id]);
+    }
+
+    #[test]
+    fn search_matches_prefixes_phrases_and_exclusions() {
+        let first = Post::new("Borrow checker", "lifetimes explained");
+        let second = Post::new("Checker", "borrow later");
+        let index = SearchIndex::build([&first, &second]);
+
+        let mut prefix = index.search(&Query::parse("lifet"));
+        prefix.sort();
+        assert_eq!(prefix, vec![first.id]);
+        assert_eq!(index.search(&Query::parse("\"borrow checker\"")), vec![first.id]);
+        assert_eq!(index.search(&Query::parse("borrow -lifetimes ")), vec![second.id]);
+    }
+
+    #[test]
+    fn phrase_does_not_span_title_and_content() {
+        let post = Post::new("Rust", "book");
+        let index = SearchIndex::build([&post]);
+
+        assert!(index.search(&Query::parse("\"rust book\"")).is_empty());
+    }
+
+    #[test]
+    fn reinserted_post_drops_old_terms() {
+        let mut post = Post::new("Draft", "old text");
+        let mut index = SearchIndex::build([&post]);
+        post.content = String::from("new text");
+        index.insert(&post);
+
+        assert!(index.search(&Query::parse("old ")).is_empty());
+        assert_eq!(index.search(&Query::parse("new ")), vec![post.id]);
+
+        index.remove(&post.id);
+        assert!(index.search(&Query::parse("text ")).is_empty());
+        assert!(index.postings.is_empty());
+    }
+}
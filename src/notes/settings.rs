@@ -0,0 +1,335 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use iced::keyboard::{self, key::Named};
+use serde::{Deserialize, Serialize};
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+
+use crate::notes::models::PostSort;
+
+/// Action a keyboard shortcut or a command palette entry runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShortcutAction {
+    NewNote,
+    DeleteNote,
+    FocusSearch,
+    NextNote,
+    PreviousNote,
+    ToggleTheme,
+    SaveNow,
+    CommandPalette,
+    Undo,
+    Redo,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BindingKey {
+    /// Lowercased character
+    Character(String),
+    Named(Named),
+}
+
+/// Key combination written like `Ctrl+Shift+Z`, `Ctrl` stands for `Cmd` on macOS
+#[derive(Debug, Clone, PartialEq, Eq, SerializeDisplay, DeserializeFromStr)]
+pub struct KeyBinding {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub key: BindingKey,
+}
+
+/// User preferences kept in the settings file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Settings {
+    /// Overrides of `ShortcutAction::default_binding`
+    #[serde(default)]
+    pub key_bindings: BTreeMap<ShortcutAction, KeyBinding>,
+    #[serde(default)]
+    pub post_sort: PostSort,
+}
+
+const NAMED_KEYS: [(&str, Named); 23] = [
+    ("Up", Named::ArrowUp),
+    ("Down", Named::ArrowDown),
+    ("Left", Named::ArrowLeft),
+    ("Right", Named::ArrowRight),
+    ("Delete", Named::Delete),
+    ("Backspace", Named::Backspace),
+    ("Enter", Named::Enter),
+    ("Escape", Named::Escape),
+    ("Tab", Named::Tab),
+    ("Home", Named::Home),
+    ("End", Named::End),
+    ("PageUp", Named::PageUp),
+    ("PageDown", Named::PageDown),
+    ("F1", Named::F1),
+    ("F2", Named::F2),
+    ("F3", Named::F3),
+    ("F4", Named::F4),
+    ("F5", Named::F5),
+    ("F6", Named::F6),
+    ("F7", Named::F7),
+    ("F8", Named::F8),
+    ("F9", Named::F9),
+    ("F10", Named::F10),
+];
+
+
+impl ShortcutAction {
+    pub const ALL: [ShortcutAction; 10] = [
+        Self::NewNote,
+        Self::DeleteNote,
+        Self::FocusSearch,
+        Self::NextNote,
+        Self::PreviousNote,
+        Self::ToggleTheme,
+        Self::SaveNow,
+        Self::CommandPalette,
+        Self::Undo,
+        Self::Redo,
+    ];
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::NewNote => "New note",
+            Self::DeleteNote => "Delete note",
+            Self::FocusSearch => "Search notes",
+            Self::NextNote => "Next note",
+            Self::PreviousNote => "Previous note",
+            Self::ToggleTheme => "Toggle theme",
+            Self::SaveNow => "Save now",
+            Self::CommandPalette => "Command palette",
+            Self::Undo => "Undo",
+            Self::Redo => "Redo",
+        }
+    }
+    fn default_binding(&self) -> &'static str {
+        match self {
+            Self::NewNote => "Ctrl+N",
+            Self::DeleteNote => "Ctrl+Delete",
+            Self::FocusSearch => "Ctrl+F",
+            Self::NextNote => "Alt+Down",
+            Self::PreviousNote => "Alt+Up",
+            Self::ToggleTheme => "Ctrl+Shift+L",
+            Self::SaveNow => "Ctrl+S",
+            Self::CommandPalette => "Ctrl+P",
+            Self::Undo => "Ctrl+Z",
+            Self::Redo => "Ctrl+Shift+Z",
+        }
+    }
+}
+
+
+impl KeyBinding {
+    pub fn matches(&self, key: &keyboard::Key, modifiers: keyboard::Modifiers) -> bool {
+        let is_key = match (&self.key, key.as_ref()) {
+            (BindingKey::Character(expected), keyboard::Key::Character(pressed)) => *expected == pressed.to_lowercase(),
+            (BindingKey::Named(expected), keyboard::Key::Named(pressed)) => *expected == pressed,
+            _ => false,
+        };
+        is_key
+            && modifiers.command() == self.ctrl
+            && modifiers.alt() == self.alt
+            && modifiers.shift() == self.shift
+    }
+}
+
+impl FromStr for KeyBinding {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut binding = KeyBinding {
+            ctrl: false,
+            alt: false,
+            shift: false,
+            key: BindingKey::Character(String::new()),
+        };
+        let parts: Vec<&str> = value.split('+').map(str::trim).collect();
+        let (key, modifiers) = parts.split_last().ok_or("empty key binding")?;
+        for modifier in modifiers {
+            match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" | "cmd" | "command" => binding.ctrl = true,
+                "alt" | "option" => binding.alt = true,
+                "shift" => binding.shift = true,
+                _ => return Err(format!("unknown modifier \"{}\" in \"{}\"", modifier, value)),
+            }
+        }
+        binding.key = match NAMED_KEYS.iter().find(|(name, _)| name.eq_ignore_ascii_case(key)) {
+            Some((_, named)) => BindingKey::Named(*named),
+            None if key.chars().count() == 1 => BindingKey::Character(key.to_lowercase()),
+            None => return Err(format!("unknown key \"{}\" in \"{}\"", key, value)),
+        };
+        Ok(binding)
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        match &self.key {
+            BindingKey::Character(character) => write!(f, "{}", character.to_uppercase()),
+            BindingKey::Named(named) => {
+                let name = NAMED_KEYS.iter()
+                    .find(|(_, key)| key == named)
+                    .map_or("?", |(name, _)| *name);
+                write!(f, "{}", name)
+            }
+        }
+    }
+}
+
+
+impl Settings {
+    pub fn get_binding(&self, action: ShortcutAction) -> KeyBinding {
+        self.key_bindings.get(&action)
+            .cloned()
+            .unwrap_or_else(|| action.default_binding().parse().expect("default key bindings are valid"))
+    }
+    /// Whether the action is bound to other keys than its default.
+    /// The settings file lists every binding, so being in `key_bindings` doesn't tell
+    fn is_rebound(&self, action: ShortcutAction) -> bool {
+        self.key_bindings.get(&action)
+            .is_some_and(|binding| Ok(binding) != action.default_binding().parse::<KeyBinding>().as_ref())
+    }
+    /// Action bound to the pressed keys, rebound actions win over the ones keeping their default,
+    /// so rebinding a default's keys takes them over
+    pub fn find_action(&self, key: &keyboard::Key, modifiers: keyboard::Modifiers) -> Option<ShortcutAction> {
+        let mut actions: Vec<ShortcutAction> = ShortcutAction::ALL.into_iter()
+            .filter(|action| self.get_binding(*action).matches(key, modifiers))
+            .collect();
+        actions.sort_by_key(|action| !self.is_rebound(*action));
+        actions.first().copied()
+    }
+    /// Key combinations bound to more than one action, a rebound one of those runs before the others
+    pub fn get_duplicate_bindings(&self) -> Vec<(KeyBinding, Vec<ShortcutAction>)> {
+        let mut duplicates: Vec<(KeyBinding, Vec<ShortcutAction>)> = Vec::new();
+        for action in ShortcutAction::ALL {
+            let binding = self.get_binding(action);
+            match duplicates.iter_mut().find(|(other, _)| *other == binding) {
+                Some((_, actions)) => actions.push(action),
+                None => duplicates.push((binding, vec![action])),
+            }
+        }
+        duplicates.retain(|(_, actions)| actions.len() > 1);
+        duplicates
+    }
+    /// Settings with every binding written out, so the file shows what can be changed
+    fn with_all_bindings(&self) -> Self {
+        Self {
+            key_bindings: ShortcutAction::ALL.into_iter()
+                .map(|action| (action, self.get_binding(action)))
+                .collect(),
+            ..self.clone()
+        }
+    }
+}
+
+/// Reads the settings file, a missing file is created with the defaults
+/// and an unreadable one is reported and ignored
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load(path: &std::path::Path) -> Settings {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => {
+            let settings: Settings = serde_json::from_str(&contents).unwrap_or_else(|error| {
+                eprintln!("Ignoring settings file {}: {}", path.display(), error);
+                Settings::default()
+            });
+            for (binding, actions) in settings.get_duplicate_bindings() {
+                let labels: Vec<&str> = actions.iter().map(ShortcutAction::label).collect();
+                eprintln!("{} is bound to more than one action in {}: {}", binding, path.display(), labels.join(", "));
+            }
+            settings
+        }
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            let settings = Settings::default();
+            if let Err(error) = save(path, &settings) {
+                eprintln!("Failed to create settings file {}: {}", path.display(), error);
+            }
+            settings
+        }
+        Err(error) => {
+            eprintln!("Failed to read settings file {}: {}", path.display(), error);
+            Settings::default()
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save(path: &std::path::Path, settings: &Settings) -> std::io::Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    let contents = serde_json::to_string_pretty(&settings.with_all_bindings())
+        .map_err(std::io::Error::other)?;
+    std::fs::write(path, contents)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn load(_path: &std::path::Path) -> Settings {
+    Settings::default()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn save(_path: &std::path::Path, _settings: &Settings) -> std::io::Result<()> {
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings_with(bindings: &[(ShortcutAction, &str)]) -> Settings {
+        Settings {
+            key_bindings: bindings.iter().map(|(action, binding)| (*action, binding.parse().unwrap())).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn user_binding_takes_over_the_keys_of_a_default() {
+        let settings = settings_with(&[(ShortcutAction::SaveNow, "Ctrl+N")]);
+        let key = keyboard::Key::Character("n".into());
+        assert_eq!(settings.find_action(&key, keyboard::Modifiers::COMMAND), Some(ShortcutAction::SaveNow));
+        let key = keyboard::Key::Character("f".into());
+        assert_eq!(settings.find_action(&key, keyboard::Modifiers::COMMAND), Some(ShortcutAction::FocusSearch));
+    }
+
+    #[test]
+    fn keys_bound_twice_are_reported() {
+        assert!(Settings::default().get_duplicate_bindings().is_empty());
+        let settings = settings_with(&[(ShortcutAction::SaveNow, "Ctrl+N")]);
+        let duplicates = settings.get_duplicate_bindings();
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].0.to_string(), "Ctrl+N");
+        assert_eq!(duplicates[0].1, vec![ShortcutAction::NewNote, ShortcutAction::SaveNow]);
+    }
+
+    #[test]
+    #[cfg(not(target_arch = "wasm32"))]
+    fn rebound_key_in_the_created_file_takes_over_a_default() {
+        let dir = std::env::temp_dir().join(format!("iced_notes_test_{}", uuid::Uuid::new_v4().simple()));
+        let path = dir.join("settings.json");
+        load(&path);
+        let mut document: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(document["key_bindings"]["new_note"], "Ctrl+N");
+        document["key_bindings"]["save_now"] = serde_json::json!("Ctrl+N");
+        std::fs::write(&path, document.to_string()).unwrap();
+
+        let settings = load(&path);
+        let key = keyboard::Key::Character("n".into());
+        assert_eq!(settings.find_action(&key, keyboard::Modifiers::COMMAND), Some(ShortcutAction::SaveNow));
+        save(&path, &settings).unwrap();
+        assert_eq!(load(&path).find_action(&key, keyboard::Modifiers::COMMAND), Some(ShortcutAction::SaveNow));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
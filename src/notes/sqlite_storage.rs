@@ -0,0 +1,506 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use rusqlite::{Connection, OptionalExtension, params};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::notes::{schema, search};
+use crate::notes::models::{Category, Changes, Data, Post};
+use crate::notes::storage::{self, Storage, StorageError, StorageFuture};
+
+static META_DATA_KEY: &str = "data";
+
+/// Posts and categories are stored as their JSON documents, one row each,
+/// as are the revision history of each post and the trashed posts,
+/// the rest of `Data` lives in the `meta` table, so new fields need no table changes.
+/// Title and content are duplicated into columns feeding the FTS5 index.
+static SCHEMA: &str = "
+    PRAGMA journal_mode = WAL;
+    CREATE TABLE IF NOT EXISTS meta (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS categories (
+        id TEXT PRIMARY KEY,
+        document TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS posts (
+        id TEXT PRIMARY KEY,
+        title TEXT NOT NULL,
+        content TEXT NOT NULL,
+        updated_at TEXT NOT NULL,
+        document TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS history (
+        post_id TEXT PRIMARY KEY,
+        document TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS trash (
+        post_id TEXT PRIMARY KEY,
+        document TEXT NOT NULL
+    );
+    CREATE VIRTUAL TABLE IF NOT EXISTS posts_fts USING fts5(
+        title, content, content = 'posts', content_rowid = 'rowid'
+    );
+    CREATE TRIGGER IF NOT EXISTS posts_fts_insert AFTER INSERT ON posts BEGIN
+        INSERT INTO posts_fts (rowid, title, content) VALUES (new.rowid, new.title, new.content);
+    END;
+    CREATE TRIGGER IF NOT EXISTS posts_fts_delete AFTER DELETE ON posts BEGIN
+        INSERT INTO posts_fts (posts_fts, rowid, title, content) VALUES ('delete', old.rowid, old.title, old.content);
+    END;
+    CREATE TRIGGER IF NOT EXISTS posts_fts_update AFTER UPDATE ON posts BEGIN
+        INSERT INTO posts_fts (posts_fts, rowid, title, content) VALUES ('delete', old.rowid, old.title, old.content);
+        INSERT INTO posts_fts (rowid, title, content) VALUES (new.rowid, new.title, new.content);
+    END;
+";
+
+/// Embedded SQLite backend writing only the rows that changed
+#[derive(Debug, Clone)]
+pub struct SqliteStorage {
+    path: PathBuf,
+    /// Opened and set up on first use, then kept for every operation
+    connection: Arc<Mutex<Option<Connection>>>,
+}
+
+/// Row level operation prepared on the UI thread and applied in a blocking task
+enum Write {
+    UpsertPost { id: Uuid, title: String, content: String, updated_at: String, document: String },
+    DeletePost(Uuid),
+    UpsertCategory { id: Uuid, document: String },
+    DeleteCategory(Uuid),
+    UpsertHistory { post_id: Uuid, document: String },
+    DeleteHistory(Uuid),
+    UpsertTrash { post_id: Uuid, document: String },
+    DeleteTrash(Uuid),
+    Meta(String),
+    Clear,
+}
+
+
+impl SqliteStorage {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, connection: Default::default() }
+    }
+
+    fn open(path: &Path) -> Result<Connection, StorageError> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .map_err(|error| StorageError::save_file(dir, error))?;
+        }
+        let mut connection = Connection::open(path)
+            .map_err(|error| StorageError::load_file(path, io::Error::other(error)))?;
+        connection.execute_batch(SCHEMA)
+            .map_err(|error| StorageError::load_file(path, io::Error::other(error)))?;
+        Self::move_trash_out_of_meta(path, &mut connection)?;
+        Ok(connection)
+    }
+
+    /// Databases written before the trash table kept the trash in the meta row
+    fn move_trash_out_of_meta(path: &Path, connection: &mut Connection) -> Result<(), StorageError> {
+        let load_error = |error: rusqlite::Error| StorageError::load_file(path, io::Error::other(error));
+        let meta: Option<String> = connection
+            .query_row("SELECT value FROM meta WHERE key = ?1", params![META_DATA_KEY], |row| row.get(0))
+            .optional()
+            .map_err(load_error)?;
+        let Some(mut meta) = meta.map(|meta| serde_json::from_str::<Value>(&meta)).transpose()
+            .map_err(|error| StorageError::load_format(path, error))? else {
+            return Ok(());
+        };
+        let Some(Value::Object(trash)) = meta.as_object_mut().and_then(|meta| meta.remove("trash")) else {
+            return Ok(());
+        };
+        let transaction = connection.transaction().map_err(load_error)?;
+        for (post_id, document) in trash {
+            transaction.execute(
+                "INSERT OR IGNORE INTO trash (post_id, document) VALUES (?1, ?2)",
+                params![post_id, document.to_string()],
+            ).map_err(load_error)?;
+        }
+        transaction.execute(
+            "UPDATE meta SET value = ?2 WHERE key = ?1",
+            params![META_DATA_KEY, meta.to_string()],
+        ).map_err(load_error)?;
+        transaction.commit().map_err(load_error)
+    }
+
+    fn run<T: Send + 'static>(
+        &self,
+        task: impl FnOnce(&Path, &mut Connection) -> Result<T, StorageError> + Send + 'static,
+    ) -> StorageFuture<T> {
+        let path = self.path.clone();
+        let connection = self.connection.clone();
+        Box::pin(async_std::task::spawn_blocking(move || {
+            let mut connection = connection.lock().unwrap_or_else(|error| error.into_inner());
+            let connection = match &mut *connection {
+                Some(connection) => connection,
+                None => connection.insert(Self::open(&path)?),
+            };
+            task(&path, connection)
+        }))
+    }
+
+    /// Commits land in the write-ahead log first, so it's checked along with the database
+    fn read_modified_at(path: &Path) -> Option<std::time::SystemTime> {
+        let wal_path = sibling_path(path, "-wal");
+        storage::latest_modified_at([path, wal_path.as_path()])
+    }
+
+    /// Returns the modification time right after the commit, taken while the connection is still held
+    fn write(&self, writes: Vec<Write>) -> StorageFuture<Option<std::time::SystemTime>> {
+        self.run(move |path, connection| {
+            let save_error = |error: rusqlite::Error| StorageError::write(path, io::Error::other(error));
+            let transaction = connection.transaction().map_err(save_error)?;
+            for write in writes {
+                let result = match write {
+                    Write::UpsertPost { id, title, content, updated_at, document } => transaction.execute(
+                        "INSERT INTO posts (id, title, content, updated_at, document) VALUES (?1, ?2, ?3, ?4, ?5)
+                         ON CONFLICT (id) DO UPDATE SET
+                            title = excluded.title,
+                            content = excluded.content,
+                            updated_at = excluded.updated_at,
+                            document = excluded.document",
+                        params![id.to_string(), title, content, updated_at, document],
+                    ),
+                    Write::DeletePost(id) => transaction.execute(
+                        "DELETE FROM posts WHERE id = ?1",
+                        params![id.to_string()],
+                    ),
+                    Write::UpsertCategory { id, document } => transaction.execute(
+                        "INSERT INTO categories (id, document) VALUES (?1, ?2)
+                         ON CONFLICT (id) DO UPDATE SET document = excluded.document",
+                        params![id.to_string(), document],
+                    ),
+                    Write::DeleteCategory(id) => transaction.execute(
+                        "DELETE FROM categories WHERE id = ?1",
+                        params![id.to_string()],
+                    ),
+                    Write::UpsertHistory { post_id, document } => transaction.execute(
+                        "INSERT INTO history (post_id, document) VALUES (?1, ?2)
+                         ON CONFLICT (post_id) DO UPDATE SET document = excluded.document",
+                        params![post_id.to_string(), document],
+                    ),
+                    Write::DeleteHistory(post_id) => transaction.execute(
+                        "DELETE FROM history WHERE post_id = ?1",
+                        params![post_id.to_string()],
+                    ),
+                    Write::UpsertTrash { post_id, document } => transaction.execute(
+                        "INSERT INTO trash (post_id, document) VALUES (?1, ?2)
+                         ON CONFLICT (post_id) DO UPDATE SET document = excluded.document",
+                        params![post_id.to_string(), document],
+                    ),
+                    Write::DeleteTrash(post_id) => transaction.execute(
+                        "DELETE FROM trash WHERE post_id = ?1",
+                        params![post_id.to_string()],
+                    ),
+                    Write::Meta(document) => transaction.execute(
+                        "INSERT INTO meta (key, value) VALUES (?1, ?2)
+                         ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+                        params![META_DATA_KEY, document],
+                    ),
+                    Write::Clear => transaction.execute_batch(
+                        "DELETE FROM posts; DELETE FROM categories; DELETE FROM history; DELETE FROM trash;"
+                    ).map(|_| 0),
+                };
+                result.map_err(save_error)?;
+            }
+            transaction.commit().map_err(save_error)?;
+            Ok(Self::read_modified_at(path))
+        })
+    }
+
+    fn upsert_post_write(post: &Post) -> Result<Write, StorageError> {
+        Ok(Write::UpsertPost {
+            id: post.id,
+            title: post.title.clone(),
+            content: post.content.clone(),
+            updated_at: post.updated_at.to_rfc3339(),
+            document: serde_json::to_string(post).map_err(StorageError::save_format)?,
+        })
+    }
+
+    fn upsert_category_write(category: &Category) -> Result<Write, StorageError> {
+        Ok(Write::UpsertCategory {
+            id: category.id,
+            document: serde_json::to_string(category).map_err(StorageError::save_format)?,
+        })
+    }
+
+    /// Revisions of a post, or their removal once the post is purged
+    fn history_write(data: &Data, post_id: Uuid) -> Result<Write, StorageError> {
+        let is_kept = data.posts.contains_key(&post_id) || data.trash.contains_key(&post_id);
+        match data.history.get(&post_id) {
+            Some(history) if is_kept => Ok(Write::UpsertHistory {
+                post_id,
+                document: serde_json::to_string(history).map_err(StorageError::save_format)?,
+            }),
+            _ => Ok(Write::DeleteHistory(post_id)),
+        }
+    }
+
+    /// The post in the trash, or its removal once it's restored or purged
+    fn trash_write(data: &Data, post_id: Uuid) -> Result<Write, StorageError> {
+        match data.trash.get(&post_id) {
+            Some(trashed_post) => Ok(Write::UpsertTrash {
+                post_id,
+                document: serde_json::to_string(trashed_post).map_err(StorageError::save_format)?,
+            }),
+            None => Ok(Write::DeleteTrash(post_id)),
+        }
+    }
+
+    /// Everything except posts, categories, history and trash, with the schema version
+    fn meta_write(data: &Data) -> Result<Write, StorageError> {
+        let mut document = schema::encode_value(data)?;
+        if let Some(document) = document.as_object_mut() {
+            document.remove("posts");
+            document.remove("categories");
+            document.remove("history");
+            document.remove("trash");
+        }
+        Ok(Write::Meta(document.to_string()))
+    }
+
+    fn read_data(path: &Path, connection: &Connection) -> Result<Data, StorageError> {
+        let load_error = |error: rusqlite::Error| StorageError::load_file(path, io::Error::other(error));
+        let format_error = |error: serde_json::Error| StorageError::load_format(path, error);
+        let read_documents = |sql: &str| -> Result<serde_json::Map<String, Value>, StorageError> {
+            let mut statement = connection.prepare(sql).map_err(load_error)?;
+            let rows = statement
+                .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+                .map_err(load_error)?;
+            let mut documents = serde_json::Map::new();
+            for row in rows {
+                let (id, document) = row.map_err(load_error)?;
+                documents.insert(id, serde_json::from_str(&document).map_err(format_error)?);
+            }
+            Ok(documents)
+        };
+
+        let meta: Option<String> = connection
+            .query_row("SELECT value FROM meta WHERE key = ?1", params![META_DATA_KEY], |row| row.get(0))
+            .optional()
+            .map_err(load_error)?;
+        let posts = read_documents("SELECT id, document FROM posts")?;
+        let categories = read_documents("SELECT id, document FROM categories")?;
+        let history = read_documents("SELECT post_id, document FROM history")?;
+        let trash = read_documents("SELECT post_id, document FROM trash")?;
+        let Some(meta) = meta else {
+            if posts.is_empty() && categories.is_empty() {
+                // a freshly created database
+                return Ok(Data::default());
+            }
+            return schema::decode_value(path, Value::Object(
+                [("posts".to_string(), Value::Object(posts)), ("categories".to_string(), Value::Object(categories))]
+                    .into_iter()
+                    .collect()
+            ));
+        };
+
+        let mut document: Value = serde_json::from_str(&meta).map_err(format_error)?;
+        if let Some(root) = document.as_object_mut() {
+            root.insert("posts".to_string(), Value::Object(posts));
+            root.insert("categories".to_string(), Value::Object(categories));
+            root.insert("history".to_string(), Value::Object(history));
+            root.insert("trash".to_string(), Value::Object(trash));
+        }
+        schema::decode_value(path, document)
+    }
+
+    /// FTS5 match expression, `None` when there is nothing positive to match
+    fn match_expression(query: &search::Query) -> Option<String> {
+        let quote = |text: &str| format!("\"{}\"", text.replace('"', "\"\""));
+        let positive: Vec<String> = query.terms.iter()
+            .map(|term| match term.is_prefix {
+                true => format!("{}*", quote(&term.text)),
+                false => quote(&term.text),
+            })
+            .chain(query.phrases.iter().map(|phrase| quote(&phrase.join(" "))))
+            .collect();
+        if positive.is_empty() {
+            return None;
+        }
+        let mut expression = format!("({})", positive.join(" AND "));
+        for term in &query.excluded {
+            expression.push_str(" NOT ");
+            expression.push_str(&quote(&term.text));
+            if term.is_prefix {
+                expression.push('*');
+            }
+        }
+        Some(expression)
+    }
+}
+
+
+/// `path` with `suffix` appended to its file name, the way SQLite names the files next to a database
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(suffix);
+    path.with_file_name(file_name)
+}
+
+
+impl Storage for SqliteStorage {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn load(&self) -> StorageFuture<Data> {
+        self.run(|path, connection| Self::read_data(path, connection))
+    }
+
+    fn save(&self, data: &Data) -> StorageFuture<()> {
+        let writes = std::iter::once(Ok(Write::Clear))
+            .chain(std::iter::once(Self::meta_write(data)))
+            .chain(data.categories.values().map(Self::upsert_category_write))
+            .chain(data.posts.values().map(Self::upsert_post_write))
+            .chain(data.history.keys().map(|post_id| Self::history_write(data, *post_id)))
+            .chain(data.trash.keys().map(|post_id| Self::trash_write(data, *post_id)))
+            .collect::<Result<Vec<Write>, StorageError>>();
+        match writes {
+            Ok(writes) => {
+                let write = self.write(writes);
+                Box::pin(async move { write.await.map(|_| ()) })
+            }
+            Err(error) => Box::pin(async { Err(error) }),
+        }
+    }
+
+    /// Moves the database together with its write-ahead log and shared memory files,
+    /// which hold commits not yet in the database file
+    fn set_aside(&self) -> StorageFuture<Option<PathBuf>> {
+        let path = self.path.clone();
+        let connection = self.connection.clone();
+        Box::pin(async_std::task::spawn_blocking(move || {
+            // closed first, a new one is opened on the fresh database
+            connection.lock().unwrap_or_else(|error| error.into_inner()).take();
+            let broken_path = sibling_path(&path, &format!(".broken-{}", chrono::Utc::now().format("%Y%m%d%H%M%S")));
+            match std::fs::rename(&path, &broken_path) {
+                Ok(()) => {}
+                Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+                Err(error) => return Err(StorageError::save_file(&broken_path, error)),
+            }
+            for suffix in ["-wal", "-shm"] {
+                let broken_sibling = sibling_path(&broken_path, suffix);
+                match std::fs::rename(sibling_path(&path, suffix), &broken_sibling) {
+                    Err(error) if error.kind() != io::ErrorKind::NotFound => {
+                        return Err(StorageError::save_file(&broken_sibling, error));
+                    }
+                    _ => {}
+                }
+            }
+            Ok(Some(broken_path))
+        }))
+    }
+
+    fn save_changes(&self, data: &Data, changes: &Changes) -> StorageFuture<Option<std::time::SystemTime>> {
+        let writes = std::iter::once(Self::meta_write(data))
+            .chain(changes.categories.iter().map(|category_id| match data.categories.get(category_id) {
+                Some(category) => Self::upsert_category_write(category),
+                None => Ok(Write::DeleteCategory(*category_id)),
+            }))
+            .chain(changes.posts.iter().map(|post_id| match data.posts.get(post_id) {
+                Some(post) => Self::upsert_post_write(post),
+                None => Ok(Write::DeletePost(*post_id)),
+            }))
+            .chain(changes.posts.iter().map(|post_id| Self::history_write(data, *post_id)))
+            .chain(changes.posts.iter().map(|post_id| Self::trash_write(data, *post_id)))
+            .collect::<Result<Vec<Write>, StorageError>>();
+        match writes {
+            Ok(writes) => self.write(writes),
+            Err(error) => Box::pin(async { Err(error) }),
+        }
+    }
+
+    fn modified_at(&self) -> StorageFuture<Option<std::time::SystemTime>> {
+        let path = self.path.clone();
+        Box::pin(async_std::task::spawn_blocking(move || Ok(Self::read_modified_at(&path))))
+    }
+
+    fn search(&self, query: &str) -> StorageFuture<Option<Vec<Uuid>>> {
+        let Some(expression) = Self::match_expression(&search::Query::parse(query)) else {
+            return Box::pin(async { Ok(None) });
+        };
+        self.run(move |path, connection| {
+            let load_error = |error: rusqlite::Error| StorageError::load_file(path, io::Error::other(error));
+            // title matches weigh twice as much as content ones, recent posts win ties
+            let mut statement = connection.prepare(
+                "SELECT posts.id FROM posts_fts
+                 JOIN posts ON posts.rowid = posts_fts.rowid
+                 WHERE posts_fts MATCH ?1
+                 ORDER BY bm25(posts_fts, 2.0, 1.0), posts.updated_at DESC"
+            ).map_err(load_error)?;
+            let rows = statement
+                .query_map(params![expression], |row| row.get::<_, String>(0))
+                .map_err(load_error)?;
+            let mut post_ids = Vec::new();
+            for row in rows {
+                if let Ok(post_id) = Uuid::parse_str(&row.map_err(load_error)?) {
+                    post_ids.push(post_id);
+                }
+            }
+            Ok(Some(post_ids))
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notes::models::TrashedPost;
+
+    #[test]
+    fn trash_kept_in_the_meta_row_is_moved_to_its_table() {
+        let path = std::env::temp_dir().join(format!("iced_notes_test_{}.sqlite3", Uuid::new_v4().simple()));
+        let post = Post::new("Trashed", "Content");
+        let mut data = Data::default();
+        data.trash.insert(post.id, TrashedPost { post: post.clone(), deleted_at: chrono::Utc::now() });
+        {
+            let connection = SqliteStorage::open(&path).unwrap();
+            let meta = schema::encode_value(&data).unwrap();
+            connection.execute("INSERT INTO meta (key, value) VALUES (?1, ?2)", params![META_DATA_KEY, meta.to_string()]).unwrap();
+        }
+
+        let storage = SqliteStorage::new(path.clone());
+        let loaded = async_std::task::block_on(storage.load()).unwrap();
+        assert_eq!(loaded.trash, data.trash);
+        let (meta, rows): (String, usize) = {
+            let connection = storage.connection.lock().unwrap();
+            let connection = connection.as_ref().unwrap();
+            (
+                connection.query_row("SELECT value FROM meta", [], |row| row.get(0)).unwrap(),
+                connection.query_row("SELECT COUNT(*) FROM trash", [], |row| row.get(0)).unwrap(),
+            )
+        };
+        assert!(!meta.contains("\"trash\""));
+        assert_eq!(rows, 1);
+        drop(storage);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(sibling_path(&path, suffix));
+        }
+    }
+
+    #[test]
+    fn search_returns_every_match() {
+        let path = std::env::temp_dir().join(format!("iced_notes_test_{}.sqlite3", Uuid::new_v4().simple()));
+        let mut data = Data::default();
+        for index in 0..600 {
+            let post = Post::new(&format!("Apple {}", index), "Content");
+            data.posts.insert(post.id, post);
+        }
+
+        let storage = SqliteStorage::new(path.clone());
+        let found = async_std::task::block_on(async {
+            storage.save(&data).await.unwrap();
+            storage.search("apple").await.unwrap()
+        });
+        assert_eq!(found.map(|post_ids| post_ids.len()), Some(600));
+        drop(storage);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(sibling_path(&path, suffix));
+        }
+    }
+}
@@ -1,67 +1,510 @@
-use std::path::PathBuf;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::AtomicBool;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::atomic::Ordering;
+use std::time::SystemTime;
 
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use zeroize::Zeroizing;
 
-use crate::notes::{models::Data, get_storage_file};
+use crate::notes::encryption::{self, SealingKey};
+use crate::notes::models::{Changes, Data};
+use crate::notes::schema;
 
+/// Storage failure with the file it happened to and the underlying cause,
+/// causes are behind `Arc` so the error stays `Clone` for `Message`
 #[derive(Debug, Clone)]
 pub enum StorageError {
-    SaveFileError,
-    LoadFileError,
-    SaveFileFormatError,
-    LoadFileFormatError,
-    WriteError,
+    SaveFileError { path: PathBuf, source: Option<Arc<io::Error>> },
+    LoadFileError { path: PathBuf, source: Option<Arc<io::Error>> },
+    SaveFileFormatError { source: Arc<serde_json::Error> },
+    LoadFileFormatError { path: PathBuf, source: Arc<serde_json::Error> },
+    WriteError { path: PathBuf, source: Option<Arc<io::Error>> },
+    UnsupportedVersion { path: PathBuf, version: u64 },
+    MigrationError { path: PathBuf, version: u64, reason: String },
+    /// Markdown note with a broken YAML header
+    FrontMatterError { path: PathBuf, reason: String },
+    /// Encrypted notes file opened without its passphrase
+    Locked { path: PathBuf },
+    EncryptionError { path: PathBuf, reason: String },
+}
+
+impl StorageError {
+    pub fn save_file(path: &Path, error: io::Error) -> Self {
+        Self::SaveFileError { path: path.to_path_buf(), source: Some(Arc::new(error)) }
+    }
+    pub fn load_file(path: &Path, error: io::Error) -> Self {
+        Self::LoadFileError { path: path.to_path_buf(), source: Some(Arc::new(error)) }
+    }
+    pub fn save_format(error: serde_json::Error) -> Self {
+        Self::SaveFileFormatError { source: Arc::new(error) }
+    }
+    pub fn load_format(path: &Path, error: serde_json::Error) -> Self {
+        Self::LoadFileFormatError { path: path.to_path_buf(), source: Arc::new(error) }
+    }
+    pub fn write(path: &Path, error: io::Error) -> Self {
+        Self::WriteError { path: path.to_path_buf(), source: Some(Arc::new(error)) }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::SaveFileError { path, .. } => Some(path),
+            Self::LoadFileError { path, .. } => Some(path),
+            Self::SaveFileFormatError { .. } => None,
+            Self::LoadFileFormatError { path, .. } => Some(path),
+            Self::WriteError { path, .. } => Some(path),
+            Self::UnsupportedVersion { path, .. } => Some(path),
+            Self::MigrationError { path, .. } => Some(path),
+            Self::FrontMatterError { path, .. } => Some(path),
+            Self::Locked { path } => Some(path),
+            Self::EncryptionError { path, .. } => Some(path),
+        }
+    }
+    fn io_error(&self) -> Option<&io::Error> {
+        match self {
+            Self::SaveFileError { source, .. } => source.as_deref(),
+            Self::LoadFileError { source, .. } => source.as_deref(),
+            Self::WriteError { source, .. } => source.as_deref(),
+            _ => None
+        }
+    }
+    /// Line and column of a broken JSON document
+    pub fn line_column(&self) -> Option<(usize, usize)> {
+        match self {
+            Self::LoadFileFormatError { source, .. } => Some((source.line(), source.column())),
+            _ => None
+        }
+    }
+    pub fn os_error(&self) -> Option<i32> {
+        self.io_error().and_then(io::Error::raw_os_error)
+    }
+    /// The storage file does not exist yet, which is the normal first run
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            Self::LoadFileError { source: Some(source), .. } => source.kind() == io::ErrorKind::NotFound,
+            _ => false
+        }
+    }
+    /// The notes are encrypted and waiting for the passphrase
+    pub fn is_locked(&self) -> bool {
+        matches!(self, Self::Locked { .. })
+    }
+    fn encryption(path: &Path, reason: String) -> Self {
+        Self::EncryptionError { path: path.to_path_buf(), reason }
+    }
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SaveFileError { path, .. } => write!(f, "Failed to save {}", path.display()),
+            Self::LoadFileError { path, .. } => write!(f, "Failed to open {}", path.display()),
+            Self::SaveFileFormatError { .. } => write!(f, "Failed to serialize notes"),
+            Self::LoadFileFormatError { path, .. } => write!(f, "{} is not a valid notes file", path.display()),
+            Self::WriteError { path, .. } => write!(f, "Failed to write {}", path.display()),
+            Self::UnsupportedVersion { path, version } => write!(
+                f,
+                "{} was written by a newer version of the app (schema {}, supported up to {})",
+                path.display(), version, schema::SCHEMA_VERSION,
+            ),
+            Self::MigrationError { path, version, .. } => write!(
+                f,
+                "Failed to upgrade {} from schema {}",
+                path.display(), version,
+            ),
+            Self::FrontMatterError { path, .. } => write!(f, "{} has invalid front matter", path.display()),
+            Self::Locked { path } => write!(f, "{} is encrypted, enter the passphrase to open it", path.display()),
+            Self::EncryptionError { path, .. } => write!(f, "Failed to decrypt {}", path.display()),
+        }?;
+        match self {
+            Self::SaveFileFormatError { source } => write!(f, ": {}", source),
+            Self::LoadFileFormatError { source, .. } => write!(f, ": {}", source),
+            Self::MigrationError { reason, .. } => write!(f, ": {}", reason),
+            Self::FrontMatterError { reason, .. } => write!(f, ": {}", reason),
+            Self::EncryptionError { reason, .. } => write!(f, ": {}", reason),
+            Self::UnsupportedVersion { .. } | Self::Locked { .. } => Ok(()),
+            _ => match self.io_error() {
+                Some(source) => write!(f, ": {}", source),
+                None => Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for StorageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::SaveFileFormatError { source } => Some(source.as_ref()),
+            Self::LoadFileFormatError { source, .. } => Some(source.as_ref()),
+            _ => self.io_error().map(|source| source as &(dyn std::error::Error + 'static)),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub type StorageFuture<T> = Pin<Box<dyn Future<Output=Result<T, StorageError>> + Send>>;
+#[cfg(target_arch = "wasm32")]
+pub type StorageFuture<T> = Pin<Box<dyn Future<Output=Result<T, StorageError>>>>;
+
+/// Notes storage backend
+///
+/// Futures own everything they need, so they can be handed to `Command::perform` directly.
+pub trait Storage: fmt::Debug {
+    /// Where the notes live, shown in the UI
+    fn path(&self) -> &Path;
+    fn load(&self) -> StorageFuture<Data>;
+    /// Replaces everything stored with `data`
+    fn save(&self, data: &Data) -> StorageFuture<()>;
+    /// Moves unreadable storage out of the way, so starting fresh never overwrites it
+    fn set_aside(&self) -> StorageFuture<Option<PathBuf>>;
+
+    /// Writes what changed since the last save, whole document backends just save everything.
+    /// Returns the modification time taken right after the write, so the app can tell its own write
+    /// from a change made outside of it, `None` when the backend can't tell
+    fn save_changes(&self, data: &Data, _changes: &Changes) -> StorageFuture<Option<SystemTime>> {
+        let save = self.save(data);
+        Box::pin(async move { save.await.map(|()| None) })
+    }
+    /// Post ids matching `query` best first, `None` when the backend leaves search to the in-memory index
+    fn search(&self, _query: &str) -> StorageFuture<Option<Vec<Uuid>>> {
+        Box::pin(async { Ok(None) })
+    }
+    /// Last time anything stored changed, polled to notice changes made outside the app,
+    /// `None` when the backend can't tell
+    fn modified_at(&self) -> StorageFuture<Option<SystemTime>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    /// Backup the last load fell back to because the storage couldn't be read, once,
+    /// so the app can tell the user before a save replaces the unreadable storage
+    fn take_restored_backup(&self) -> Option<PathBuf> {
+        None
+    }
+    /// Whether the notes can be encrypted with a passphrase at all
+    fn supports_encryption(&self) -> bool {
+        false
+    }
+    /// Whether saves are encrypted with a passphrase
+    fn is_encrypted(&self) -> bool {
+        false
+    }
+    /// Loads encrypted notes with the passphrase, the key is kept until `lock`
+    fn unlock(&self, _passphrase: Zeroizing<String>) -> StorageFuture<Data> {
+        Box::pin(async_error(self.path(), "this storage can't be encrypted"))
+    }
+    /// Forgets the key, loading needs `unlock` again
+    fn lock(&self) {}
+    /// Saves `data` encrypted with `new`, or unencrypted when it's `None`.
+    /// `current` has to match when the notes are already encrypted
+    fn change_passphrase(
+        &self,
+        _data: &Data,
+        _current: Option<Zeroizing<String>>,
+        _new: Option<Zeroizing<String>>,
+    ) -> StorageFuture<()> {
+        Box::pin(async_error(self.path(), "this storage can't be encrypted"))
+    }
+}
+
+fn async_error<T>(path: &Path, reason: &str) -> impl Future<Output=Result<T, StorageError>> {
+    let error = StorageError::encryption(path, String::from(reason));
+    async move { Err(error) }
+}
+
+/// Runs slow key derivation off the UI thread
+#[cfg(not(target_arch = "wasm32"))]
+async fn run_blocking<T: Send + 'static>(task: impl FnOnce() -> T + Send + 'static) -> T {
+    async_std::task::spawn_blocking(task).await
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn run_blocking<T>(task: impl FnOnce() -> T) -> T {
+    task()
+}
+
+/// Latest modification time of the files that exist, skipping the ones that can't be read
+#[cfg(not(target_arch = "wasm32"))]
+pub fn latest_modified_at<'a>(paths: impl IntoIterator<Item=&'a Path>) -> Option<SystemTime> {
+    paths.into_iter()
+        .filter_map(|path| std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok())
+        .max()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageKind {
+    Json,
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+    /// A folder with a Markdown file per post
+    #[cfg(not(target_arch = "wasm32"))]
+    Folder,
+}
+
+impl StorageKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            #[cfg(feature = "sqlite")]
+            "sqlite" => Some(Self::Sqlite),
+            #[cfg(not(target_arch = "wasm32"))]
+            "folder" => Some(Self::Folder),
+            _ => None
+        }
+    }
+    /// Guesses the backend from the file extension, JSON unless it looks like a database
+    /// or is a folder, an existing one or a path ending with a separator
+    pub fn from_path(path: &Path) -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
+        if path.is_dir() || path.as_os_str().to_string_lossy().ends_with(std::path::is_separator) {
+            return Self::Folder;
+        }
+        match path.extension().and_then(|extension| extension.to_str()) {
+            #[cfg(feature = "sqlite")]
+            Some("sqlite" | "sqlite3" | "db") => Self::Sqlite,
+            _ => Self::Json
+        }
+    }
+}
+
+pub fn open(kind: StorageKind, path: PathBuf) -> Box<dyn Storage> {
+    match kind {
+        StorageKind::Json => Box::new(JsonStorage::new(path)),
+        #[cfg(feature = "sqlite")]
+        StorageKind::Sqlite => Box::new(crate::notes::sqlite_storage::SqliteStorage::new(path)),
+        #[cfg(not(target_arch = "wasm32"))]
+        StorageKind::Folder => Box::new(crate::notes::folder_storage::FolderStorage::new(path)),
+    }
+}
+
+/// Copies all notes from one backend to another, the target is overwritten
+pub async fn migrate(from: &dyn Storage, to: &dyn Storage) -> Result<usize, StorageError> {
+    let data = from.load().await?;
+    to.save(&data).await?;
+    Ok(data.posts.len())
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JsonStorage {
     path: PathBuf,
+    /// Set while encrypted notes are unlocked, shared by the clones futures run on
+    #[serde(skip)]
+    key: Arc<Mutex<Option<SealingKey>>>,
+    /// Set when `load` fell back to the backup
+    #[serde(skip)]
+    is_restored_from_backup: Arc<AtomicBool>,
+}
+
+impl JsonStorage {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, key: Default::default(), is_restored_from_backup: Default::default() }
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    fn key(&self) -> Option<SealingKey> {
+        self.key.lock().unwrap_or_else(|error| error.into_inner()).clone()
+    }
+    fn set_key(&self, key: Option<SealingKey>) {
+        *self.key.lock().unwrap_or_else(|error| error.into_inner()) = key;
+    }
+    /// Parses the contents of the notes file, decrypting them first when they're encrypted
+    fn decode(&self, path: &Path, contents: &str) -> Result<Data, StorageError> {
+        if !encryption::is_encrypted(contents) {
+            return schema::decode(path, contents);
+        }
+        let key = self.key().ok_or_else(|| StorageError::Locked { path: path.to_path_buf() })?;
+        let plaintext = encryption::open(&key, contents).map_err(|reason| StorageError::encryption(path, reason))?;
+        schema::decode(path, &plaintext)
+    }
+    /// The notes document as written to the file, sealed when a passphrase is set
+    fn encode<T: Serialize>(&self, notes: &T, is_pretty: bool) -> Result<String, StorageError> {
+        match self.key() {
+            Some(key) => {
+                let json = schema::encode(notes, false)?;
+                encryption::seal(&key, &json).map_err(|reason| StorageError::encryption(&self.path, reason))
+            }
+            None => schema::encode(notes, is_pretty),
+        }
+    }
+    async fn unlock(self, passphrase: Zeroizing<String>) -> Result<Data, StorageError> {
+        let contents = self.read_contents().await?;
+        if !encryption::is_encrypted(&contents) {
+            return schema::decode(&self.path, &contents);
+        }
+        let (key, plaintext) = run_blocking(move || encryption::unlock(&passphrase, &contents))
+            .await
+            .map_err(|reason| StorageError::encryption(&self.path, reason))?;
+        let data = schema::decode(&self.path, &plaintext)?;
+        self.set_key(Some(key));
+        Ok(data)
+    }
+    async fn change_passphrase(
+        self,
+        data: Data,
+        current: Option<Zeroizing<String>>,
+        new: Option<Zeroizing<String>>,
+    ) -> Result<(), StorageError> {
+        let current_key = self.key();
+        let new_key = run_blocking(move || {
+            if let Some(key) = &current_key {
+                if !key.matches(&current.unwrap_or_default()) {
+                    return Err(String::from("the current passphrase is wrong"));
+                }
+            }
+            new.map(|passphrase| SealingKey::generate(&passphrase)).transpose()
+        })
+            .await
+            .map_err(|reason| StorageError::encryption(&self.path, reason))?;
+        let previous_key = self.key();
+        self.set_key(new_key);
+        let result = self.clone().save(data).await;
+        if result.is_err() {
+            self.set_key(previous_key);
+        }
+        result
+    }
 }
 
 #[cfg(not(target_arch = "wasm32"))]
 impl JsonStorage {
-    pub async fn load() -> Result<Data, StorageError> {
+    /// Previous good copy of the storage file, `load` falls back to it when the main file is broken
+    pub fn backup_path(&self) -> PathBuf {
+        Self::sibling_path(&self.path, "bak")
+    }
+    fn temp_path(&self) -> PathBuf {
+        Self::sibling_path(&self.path, "tmp")
+    }
+    fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+        let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".");
+        file_name.push(suffix);
+        path.with_file_name(file_name)
+    }
+
+    async fn read_file(path: &Path) -> Result<String, StorageError> {
         use async_std::prelude::*;
         let mut contents = String::new();
-        let mut file = async_std::fs::File::open(get_storage_file())
+        let mut file = async_std::fs::File::open(path)
             .await
-            .map_err(|_| StorageError::LoadFileError)?;
+            .map_err(|error| StorageError::load_file(path, error))?;
 
         file.read_to_string(&mut contents)
             .await
-            .map_err(|_| StorageError::LoadFileError)?;
+            .map_err(|error| StorageError::load_file(path, error))?;
 
-        serde_json::from_str(&contents)
-            .map_err(|_| StorageError::LoadFileFormatError)
+        Ok(contents)
     }
 
-    pub async fn save<'a, T: Serialize>(notes: T) -> Result<(), StorageError> {
-        use async_std::prelude::*;
+    async fn read_contents(&self) -> Result<String, StorageError> {
+        Self::read_file(&self.path).await
+    }
 
-        let json = serde_json::to_string_pretty(&notes)
-            .map_err(|_| {
-                StorageError::SaveFileFormatError
-            })?;
+    async fn read_data(&self, path: &Path) -> Result<Data, StorageError> {
+        let contents = Self::read_file(path).await?;
+        self.decode(path, &contents)
+    }
 
-        let path = get_storage_file();
+    /// Falls back to the backup when the storage file can't be parsed,
+    /// `take_restored_backup` reports it afterwards
+    pub async fn load(self) -> Result<Data, StorageError> {
+        match self.read_data(&self.path).await {
+            Err(error @ StorageError::LoadFileFormatError { .. }) => {
+                let data = self.read_data(&self.backup_path())
+                    .await
+                    .map_err(|_| error)?;
+                self.is_restored_from_backup.store(true, Ordering::Relaxed);
+                Ok(data)
+            }
+            result => result
+        }
+    }
 
-        if let Some(dir) = path.parent() {
-            async_std::fs::create_dir_all(dir)
-                .await
-                .map_err(|_| StorageError::SaveFileError)?;
+    /// Moves an unreadable storage file out of the way, so starting fresh never overwrites it
+    pub async fn set_aside(self) -> Result<Option<PathBuf>, StorageError> {
+        let broken_path = Self::sibling_path(
+            &self.path,
+            &format!("broken-{}", chrono::Utc::now().format("%Y%m%d%H%M%S")),
+        );
+        match async_std::fs::rename(&self.path, &broken_path).await {
+            Ok(()) => Ok(Some(broken_path)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(StorageError::save_file(&broken_path, error)),
         }
+    }
+
+    /// Writes into a temporary file first and renames it over the storage file,
+    /// so a crash in the middle of saving never leaves a truncated file behind.
+    pub async fn save<'a, T: Serialize>(self, notes: T) -> Result<(), StorageError> {
+        use async_std::prelude::*;
 
+        let json = self.encode(&notes, true)?;
+
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        async_std::fs::create_dir_all(&dir)
+            .await
+            .map_err(|error| StorageError::save_file(&dir, error))?;
+
+        let temp_path = self.temp_path();
         {
-            let mut file = async_std::fs::File::create(path)
+            let mut file = async_std::fs::File::create(&temp_path)
                 .await
-                .map_err(|_| StorageError::SaveFileError)?;
+                .map_err(|error| StorageError::save_file(&temp_path, error))?;
 
             file.write_all(json.as_bytes())
                 .await
-        }.map_err(|_| StorageError::WriteError)?;
+                .map_err(|error| StorageError::write(&temp_path, error))?;
+
+            file.sync_all()
+                .await
+                .map_err(|error| StorageError::write(&temp_path, error))?;
+        }
 
-        // This is a simple way to save at most once every couple seconds
-        async_std::task::sleep(std::time::Duration::from_secs(2)).await;
+        // keep the current file as backup, but only when it still loads as notes,
+        // otherwise a broken file would replace the last good backup, so it's set aside instead
+        // as it may hold edits newer than the backup.
+        // Never keep a plain copy around once the notes are encrypted
+        if let Ok(contents) = Self::read_file(&self.path).await {
+            let backup_path = self.backup_path();
+            if self.key().is_some() && !encryption::is_encrypted(&contents) {
+                match async_std::fs::remove_file(&backup_path).await {
+                    Err(error) if error.kind() != io::ErrorKind::NotFound => {
+                        return Err(StorageError::save_file(&backup_path, error));
+                    }
+                    _ => {}
+                }
+            } else {
+                match self.decode(&self.path, &contents) {
+                    Ok(_) => {
+                        async_std::fs::copy(&self.path, &backup_path)
+                            .await
+                            .map_err(|error| StorageError::save_file(&backup_path, error))?;
+                    }
+                    Err(StorageError::LoadFileFormatError { .. }) => {
+                        self.clone().set_aside().await?;
+                    }
+                    Err(_) => {}
+                }
+            }
+        }
+
+        async_std::fs::rename(&temp_path, &self.path)
+            .await
+            .map_err(|error| StorageError::save_file(&self.path, error))?;
+
+        // make the rename itself durable
+        #[cfg(unix)]
+        if let Ok(dir) = async_std::fs::File::open(&dir).await {
+            let _ = dir.sync_all().await;
+        }
 
         Ok(())
     }
@@ -69,35 +512,285 @@ impl JsonStorage {
 
 #[cfg(target_arch = "wasm32")]
 impl JsonStorage {
-    fn storage() -> Option<web_sys::JsonStorage> {
-        let window = web_sys::window()?;
+    /// `localStorage` of the global scope, taken from `globalThis` instead of `window`
+    /// so it is also found in workers and in Node with a `localStorage` polyfill
+    fn storage() -> Option<web_sys::Storage> {
+        use wasm_bindgen::JsCast;
 
-        window.local_storage().ok()?
+        let storage = js_sys::Reflect::get(&js_sys::global(), &"localStorage".into()).ok()?;
+        if storage.is_null() || storage.is_undefined() {
+            return None;
+        }
+        Some(storage.unchecked_into())
     }
 
-    async fn load() -> Result<JsonStorage, LoadError> {
-        let storage = Self::storage().ok_or(LoadError::FileError)?;
+    /// The storage path doubles as the `localStorage` key
+    fn item_key(&self) -> String {
+        self.path.to_string_lossy().into_owned()
+    }
 
-        let contents = storage
-            .get_item("state")
-            .map_err(|_| LoadError::FileError)?
-            .ok_or(LoadError::FileError)?;
+    async fn read_contents(&self) -> Result<String, StorageError> {
+        let load_error = || StorageError::LoadFileError { path: self.path.clone(), source: None };
+        let storage = Self::storage().ok_or_else(load_error)?;
 
-        serde_json::from_str(&contents).map_err(|_| LoadError::FormatError)
+        storage
+            .get_item(&self.item_key())
+            .map_err(|_| load_error())?
+            .ok_or_else(|| StorageError::load_file(&self.path, io::ErrorKind::NotFound.into()))
     }
 
-    async fn save(self) -> Result<(), SaveError> {
-        let storage = Self::storage().ok_or(SaveError::FileError)?;
+    pub async fn load(self) -> Result<Data, StorageError> {
+        let contents = self.read_contents().await?;
+        self.decode(&self.path, &contents)
+    }
 
-        let json = serde_json::to_string_pretty(&self)
-            .map_err(|_| SaveError::FormatError)?;
+    /// Moves an unreadable entry to a `.broken` key, so starting fresh never overwrites it
+    pub async fn set_aside(self) -> Result<Option<PathBuf>, StorageError> {
+        let save_error = || StorageError::SaveFileError { path: self.path.clone(), source: None };
+        let storage = Self::storage().ok_or_else(save_error)?;
+        let Some(contents) = storage.get_item(&self.item_key()).map_err(|_| save_error())? else {
+            return Ok(None);
+        };
+        let broken_key = format!("{}.broken", self.item_key());
+        storage.set_item(&broken_key, &contents).map_err(|_| save_error())?;
+        storage.remove_item(&self.item_key()).map_err(|_| save_error())?;
+        Ok(Some(PathBuf::from(broken_key)))
+    }
 
-        storage
-            .set_item("state", &json)
-            .map_err(|_| SaveError::WriteError)?;
+    pub async fn save<'a, T: Serialize>(self, notes: T) -> Result<(), StorageError> {
+        let storage = Self::storage()
+            .ok_or_else(|| StorageError::SaveFileError { path: self.path.clone(), source: None })?;
 
-        let _ = wasm_timer::Delay::new(std::time::Duration::from_secs(2)).await;
+        let json = self.encode(&notes, false)?;
+
+        // setItem throws when the storage quota is exceeded
+        storage
+            .set_item(&self.item_key(), &json)
+            .map_err(|_| StorageError::WriteError { path: self.path.clone(), source: None })?;
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+
+/// The JSON file holds one document, so every save rewrites all of it
+impl Storage for JsonStorage {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+    fn load(&self) -> StorageFuture<Data> {
+        Box::pin(self.clone().load())
+    }
+    fn save(&self, data: &Data) -> StorageFuture<()> {
+        Box::pin(self.clone().save(data.clone()))
+    }
+    fn set_aside(&self) -> StorageFuture<Option<PathBuf>> {
+        Box::pin(self.clone().set_aside())
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save_changes(&self, data: &Data, _changes: &Changes) -> StorageFuture<Option<SystemTime>> {
+        let save = self.clone().save(data.clone());
+        let path = self.path.clone();
+        Box::pin(async move {
+            save.await?;
+            Ok(async_std::task::spawn_blocking(move || latest_modified_at([path.as_path()])).await)
+        })
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    fn modified_at(&self) -> StorageFuture<Option<SystemTime>> {
+        let path = self.path.clone();
+        Box::pin(async_std::task::spawn_blocking(move || Ok(latest_modified_at([path.as_path()]))))
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    fn take_restored_backup(&self) -> Option<PathBuf> {
+        self.is_restored_from_backup.swap(false, Ordering::Relaxed).then(|| self.backup_path())
+    }
+    fn supports_encryption(&self) -> bool {
+        true
+    }
+    fn is_encrypted(&self) -> bool {
+        self.key().is_some()
+    }
+    fn unlock(&self, passphrase: Zeroizing<String>) -> StorageFuture<Data> {
+        Box::pin(self.clone().unlock(passphrase))
+    }
+    fn lock(&self) {
+        self.set_key(None);
+    }
+    fn change_passphrase(
+        &self,
+        data: &Data,
+        current: Option<Zeroizing<String>>,
+        new: Option<Zeroizing<String>>,
+    ) -> StorageFuture<()> {
+        Box::pin(self.clone().change_passphrase(data.clone(), current, new))
+    }
+}
+
+
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod native_tests {
+    use super::*;
+    use crate::notes::models::Post;
+
+    /// A fresh directory under the system temp dir, removed by the test at the end
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("iced_notes_test_{}", Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn data_with_post(title: &str) -> Data {
+        let post = Post::new(title, "Content");
+        let mut data = Data::default();
+        data.posts.insert(post.id, post);
+        data
+    }
+
+    #[test]
+    fn save_keeps_backup_when_current_file_is_not_notes() {
+        let dir = temp_dir();
+        let storage = JsonStorage::new(dir.join("notes.json"));
+        async_std::task::block_on(async {
+            storage.clone().save(data_with_post("First")).await.unwrap();
+            storage.clone().save(data_with_post("Second")).await.unwrap();
+            std::fs::write(dir.join("notes.json"), r#"{"version": 6, "posts": 1}"#).unwrap();
+            storage.clone().save(data_with_post("Third")).await.unwrap();
+        });
+        let backup = std::fs::read_to_string(storage.backup_path()).unwrap();
+
+        assert!(backup.contains("First"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn load_reports_restored_backup_and_save_sets_broken_file_aside() {
+        let dir = temp_dir();
+        let storage = JsonStorage::new(dir.join("notes.json"));
+        let broken = r#"{"version": 6, "posts": 1}"#;
+        let data = async_std::task::block_on(async {
+            storage.clone().save(data_with_post("First")).await.unwrap();
+            storage.clone().save(data_with_post("Second")).await.unwrap();
+            std::fs::write(dir.join("notes.json"), broken).unwrap();
+            storage.clone().load().await.unwrap()
+        });
+
+        assert!(data.posts.values().any(|post| post.title == "First"));
+        assert_eq!(storage.take_restored_backup(), Some(storage.backup_path()));
+        assert_eq!(storage.take_restored_backup(), None);
+
+        async_std::task::block_on(storage.clone().save(data)).unwrap();
+        let set_aside: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.to_string_lossy().contains("notes.json.broken-"))
+            .collect();
+
+        assert_eq!(set_aside.len(), 1);
+        assert_eq!(std::fs::read_to_string(&set_aside[0]).unwrap(), broken);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn save_removes_plain_backup_once_encrypted() {
+        let dir = temp_dir();
+        let storage = JsonStorage::new(dir.join("notes.json"));
+        let read = |path: &Path| std::fs::read_to_string(path).unwrap();
+        async_std::task::block_on(async {
+            storage.clone().save(data_with_post("First")).await.unwrap();
+            storage.clone().save(data_with_post("Second")).await.unwrap();
+            assert!(read(&storage.backup_path()).contains("First"));
+
+            storage.set_key(Some(encryption::test_key("passphrase")));
+            storage.clone().save(data_with_post("Third")).await.unwrap();
+            assert!(!storage.backup_path().exists());
+            assert!(encryption::is_encrypted(&read(&storage.path)));
+
+            // from now on the backup is an encrypted copy
+            storage.clone().save(data_with_post("Fourth")).await.unwrap();
+            assert!(encryption::is_encrypted(&read(&storage.backup_path())));
+        });
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+    use crate::notes::models::Post;
+
+    /// Node has no `localStorage`, a minimal in-memory one is enough for the storage code
+    fn install_local_storage() {
+        js_sys::Function::new_no_args(r#"
+            if (!globalThis.localStorage) {
+                const items = new Map();
+                globalThis.localStorage = {
+                    getItem: (key) => items.has(key) ? items.get(key) : null,
+                    setItem: (key, value) => { items.set(key, String(value)); },
+                    removeItem: (key) => { items.delete(key); },
+                };
+            }
+        "#)
+            .call0(&wasm_bindgen::JsValue::NULL)
+            .unwrap();
+    }
+
+    #[wasm_bindgen_test]
+    async fn save_then_load_returns_same_posts() {
+        install_local_storage();
+        let storage = JsonStorage::new(PathBuf::from("round_trip.json"));
+        let post = Post::new("Title", "Content");
+        let mut data = Data::default();
+        data.posts.insert(post.id, post.clone());
+
+        storage.clone().save(data).await.unwrap();
+        let loaded = storage.load().await.unwrap();
+
+        assert_eq!(loaded.posts.len(), 1);
+        assert_eq!(loaded.posts[&post.id].title, "Title");
+        assert_eq!(loaded.posts[&post.id].content, "Content");
+    }
+
+    #[wasm_bindgen_test]
+    async fn load_missing_key_is_not_found() {
+        install_local_storage();
+        let result = JsonStorage::new(PathBuf::from("missing.json")).load().await;
+
+        assert!(matches!(&result, Err(error) if error.is_not_found()));
+    }
+
+    #[wasm_bindgen_test]
+    async fn load_broken_json_is_format_error() {
+        install_local_storage();
+        JsonStorage::storage()
+            .unwrap()
+            .set_item("broken.json", "{ not json")
+            .unwrap();
+        let result = JsonStorage::new(PathBuf::from("broken.json")).load().await;
+
+        assert!(matches!(result, Err(StorageError::LoadFileFormatError { .. })));
+    }
+
+    #[wasm_bindgen_test]
+    async fn encrypted_notes_load_after_unlock() {
+        install_local_storage();
+        let storage = JsonStorage::new(PathBuf::from("encrypted.json"));
+        let post = Post::new("Secret", "Content");
+        let mut data = Data::default();
+        data.posts.insert(post.id, post.clone());
+
+        Storage::change_passphrase(&storage, &data, None, Some(Zeroizing::new(String::from("passphrase")))).await.unwrap();
+        let stored = JsonStorage::storage().unwrap().get_item("encrypted.json").unwrap().unwrap();
+        assert!(!stored.contains("Secret"));
+
+        Storage::lock(&storage);
+        assert!(matches!(storage.clone().load().await, Err(StorageError::Locked { .. })));
+        assert!(Storage::unlock(&storage, Zeroizing::new(String::from("wrong"))).await.is_err());
+        let loaded = Storage::unlock(&storage, Zeroizing::new(String::from("passphrase"))).await.unwrap();
+
+        assert_eq!(loaded.posts[&post.id].title, "Secret");
+    }
+}
@@ -0,0 +1,72 @@
+use iced::{Alignment, Element, font, Font, Length, theme, widget};
+
+use crate::notes::{category_list, FontSize, Message, models};
+use crate::notes::flow::Flow;
+
+/// Small pill button used for tags in the sidebar and the details view
+pub fn tag_chip<'a>(label: String, is_selected: bool, on_press: Option<Message>) -> Element<'a, Message> {
+    widget::button(widget::text(label).size(FontSize::Caption))
+        .padding([2, 6])
+        .on_press_maybe(on_press)
+        .style(match is_selected {
+            true => theme::Button::Positive,
+            false => theme::Button::Secondary,
+        })
+        .into()
+}
+
+fn tag_match_button<'a>(label: &str, tag_match: models::TagMatch, selected_tag_match: models::TagMatch) -> Element<'a, Message> {
+    widget::button(widget::text(label).size(FontSize::Caption))
+        .padding([2, 6])
+        .on_press(Message::TagMatchSelected(tag_match))
+        .style(match tag_match == selected_tag_match {
+            true => theme::Button::Primary,
+            false => theme::Button::Text,
+        })
+        .into()
+}
+
+
+/// Tag cloud with post counts, selected tags filter the posts list
+pub fn panel_tags(state: &models::NotesState) -> Element<Message> {
+    let mut header = widget::Row::new()
+        .spacing(4)
+        .align_items(Alignment::Center)
+        .push(widget::text("Tags").size(FontSize::Caption).width(Length::Fill).font(Font {
+            weight: font::Weight::Bold,
+            ..Font::default()
+        }));
+    if !state.selected_tags.is_empty() {
+        header = header
+            .push(tag_match_button("All", models::TagMatch::All, state.tag_match))
+            .push(tag_match_button("Any", models::TagMatch::Any, state.tag_match))
+            .push(
+                widget::button(widget::text("Clear").size(FontSize::Caption))
+                    .padding([2, 6])
+                    .on_press(Message::TagFilterCleared)
+                    .style(theme::Button::Text)
+            );
+    }
+
+    let tags = Flow::with_children(
+        state.get_ordered_tags_list()
+            .into_iter()
+            .map(|(tag, count)| tag_chip(
+                format!("#{} {}", tag, count),
+                state.selected_tags.contains(tag),
+                Some(Message::TagFilterToggled(tag.clone())),
+            ))
+            .collect()
+    )
+        .spacing(4.0)
+        .line_spacing(4.0);
+
+    widget::column(vec![
+        header.into(),
+        widget::scrollable(tags).height(Length::Fill).into(),
+    ])
+        .spacing(4)
+        .width(category_list::PANEL_WIDTH)
+        .height(Length::FillPortion(1))
+        .into()
+}
@@ -0,0 +1,49 @@
+use std::collections::BTreeSet;
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '/'
+}
+
+/// Lowercased tag without the leading `#`, `None` if it's not a usable tag
+pub fn normalize(tag: &str) -> Option<String> {
+    let tag = tag.trim()
+        .trim_start_matches('#')
+        .trim_end_matches(|c| c == '-' || c == '/')
+        .to_lowercase();
+    match tag.chars().next() {
+        Some(first) if first.is_alphabetic() && tag.chars().all(is_tag_char) => Some(tag),
+        _ => None,
+    }
+}
+
+/// `#hashtags` written in the note content, code blocks, inline code, headings
+/// and link anchors are skipped
+pub fn extract_hashtags(content: &str) -> BTreeSet<String> {
+    let mut tags = BTreeSet::new();
+    let mut is_in_code_block = false;
+    for line in content.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            is_in_code_block = !is_in_code_block;
+            continue;
+        }
+        if is_in_code_block {
+            continue;
+        }
+        let mut is_in_code = false;
+        let mut previous: Option<char> = None;
+        for (index, c) in line.char_indices() {
+            if c == '`' {
+                is_in_code = !is_in_code;
+            } else if c == '#' && !is_in_code && previous.map_or(true, |p| !is_tag_char(p) && !"#&(".contains(p)) {
+                let rest = &line[index + 1..];
+                let end = rest.find(|c: char| !is_tag_char(c)).unwrap_or(rest.len());
+                if let Some(tag) = normalize(&rest[..end]) {
+                    tags.insert(tag);
+                }
+            }
+            previous = Some(c);
+        }
+    }
+    tags
+}
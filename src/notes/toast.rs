@@ -0,0 +1,29 @@
+use iced::{Alignment, Element, Length, theme, widget};
+use uuid::Uuid;
+
+use crate::notes::{FontSize, Message};
+
+/// Shown for a few seconds after a post is moved to the trash
+pub fn undo_delete_toast<'a>(post_id: Uuid, title: &str) -> Element<'a, Message> {
+    widget::container(
+        widget::row(vec![
+            widget::text(format!("\"{}\" moved to trash", title))
+                .size(FontSize::Tooltip)
+                .width(Length::Fill)
+                .into(),
+            widget::button(widget::text("Undo").size(FontSize::Tooltip))
+                .on_press(Message::ButtonUndoDeletePressed(post_id))
+                .into(),
+            widget::button(widget::text("Dismiss").size(FontSize::Tooltip))
+                .on_press(Message::UndoToastExpired)
+                .style(theme::Button::Text)
+                .into(),
+        ])
+            .spacing(8)
+            .align_items(Alignment::Center)
+    )
+        .width(Length::Fill)
+        .padding(8)
+        .style(theme::Container::Box)
+        .into()
+}
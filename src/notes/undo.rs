@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::notes::{UNDO_COALESCE_WINDOW, UNDO_LIMIT};
+use crate::notes::models::{Category, Post, TrashedPost};
+
+/// User level operation an undo step stands for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UndoAction {
+    CreatePost,
+    DeletePost,
+    RestorePost,
+    EditTitle,
+    EditContent,
+    ChangePostCategory,
+    EditTags,
+    RewriteLinks,
+    RestoreRevision,
+    PinPost,
+    FavouritePost,
+    MovePost,
+    UseExternalVersion,
+    CreateCategory,
+    RenameCategory,
+    DeleteCategory,
+}
+
+/// Where a post is at a given moment, both `None` means it doesn't exist
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PostSnapshot {
+    pub post: Option<Post>,
+    pub trashed: Option<TrashedPost>,
+}
+
+/// Posts and categories an operation touched, as they were before and after it
+#[derive(Debug, Clone)]
+pub struct UndoStep {
+    pub action: UndoAction,
+    pub posts: HashMap<Uuid, (PostSnapshot, PostSnapshot)>,
+    pub categories: HashMap<Uuid, (Option<Category>, Option<Category>)>,
+    /// The manual order before and after, `None` if it's unchanged
+    pub post_order: Option<(Vec<Uuid>, Vec<Uuid>)>,
+    pub recorded_at: DateTime<Utc>,
+}
+
+/// Step being recorded, holds the "before" side of everything touched so far
+#[derive(Debug)]
+pub struct Recording {
+    pub action: UndoAction,
+    pub posts: HashMap<Uuid, PostSnapshot>,
+    pub categories: HashMap<Uuid, Option<Category>>,
+    pub post_order: Vec<Uuid>,
+}
+
+#[derive(Debug, Default)]
+pub struct UndoStack {
+    undo: Vec<UndoStep>,
+    redo: Vec<UndoStep>,
+    pub recording: Option<Recording>,
+}
+
+
+impl UndoAction {
+    /// Typing in a field makes one step per burst instead of one per key
+    fn is_coalescing(&self) -> bool {
+        matches!(self, Self::EditTitle | Self::EditContent | Self::RenameCategory)
+    }
+}
+
+impl UndoStep {
+    fn is_continued_by(&self, next: &UndoStep) -> bool {
+        self.action == next.action
+            && self.action.is_coalescing()
+            && self.posts.len() == next.posts.len()
+            && next.posts.keys().all(|post_id| self.posts.contains_key(post_id))
+            && self.categories.len() == next.categories.len()
+            && next.categories.keys().all(|category_id| self.categories.contains_key(category_id))
+            && (next.recorded_at - self.recorded_at).to_std().is_ok_and(|elapsed| elapsed < UNDO_COALESCE_WINDOW)
+    }
+    /// Takes the "after" side of `next`, keeping this step's "before" side
+    fn merge(&mut self, next: UndoStep) {
+        for (post_id, (_, after)) in next.posts {
+            if let Some(snapshots) = self.posts.get_mut(&post_id) {
+                snapshots.1 = after;
+            }
+        }
+        for (category_id, (_, after)) in next.categories {
+            if let Some(snapshots) = self.categories.get_mut(&category_id) {
+                snapshots.1 = after;
+            }
+        }
+        if let Some((before, after)) = next.post_order {
+            self.post_order.get_or_insert((before, Vec::new())).1 = after;
+        }
+        self.recorded_at = next.recorded_at;
+    }
+}
+
+impl UndoStack {
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.recording = None;
+    }
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+    /// A new step makes the redo steps unreachable
+    pub fn push(&mut self, step: UndoStep) {
+        self.redo.clear();
+        match self.undo.last_mut() {
+            Some(last) if last.is_continued_by(&step) => last.merge(step),
+            _ => self.undo.push(step),
+        }
+        if self.undo.len() > UNDO_LIMIT {
+            self.undo.remove(0);
+        }
+    }
+    pub fn pop_undo(&mut self) -> Option<UndoStep> {
+        let step = self.undo.pop()?;
+        self.redo.push(step.clone());
+        Some(step)
+    }
+    pub fn pop_redo(&mut self) -> Option<UndoStep> {
+        let step = self.redo.pop()?;
+        self.undo.push(step.clone());
+        Some(step)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(action: UndoAction, post: &Post, recorded_at: DateTime<Utc>) -> UndoStep {
+        let before = PostSnapshot { post: Some(post.clone()), trashed: None };
+        let after = PostSnapshot {
+            post: Some(Post { content: format!("{} edited", post.content), ..post.clone() }),
+            trashed: None,
+        };
+        UndoStep {
+            action,
+            posts: HashMap::from([(post.id, (before, after))]),
+            categories: HashMap::new(),
+            post_order: None,
+            recorded_at,
+        }
+    }
+
+    fn content(snapshot: &PostSnapshot) -> &str {
+        &snapshot.post.as_ref().unwrap().content
+    }
+
+    #[test]
+    fn typing_bursts_coalesce_into_one_step() {
+        let mut stack = UndoStack::default();
+        let post = Post::new("Title", "a");
+        let now = Utc::now();
+        stack.push(step(UndoAction::EditContent, &post, now));
+        stack.push(step(UndoAction::EditContent, &Post { content: String::from("b"), ..post.clone() }, now + chrono::Duration::seconds(1)));
+        let merged = stack.pop_undo().unwrap();
+        let (before, after) = &merged.posts[&post.id];
+        assert_eq!((content(before), content(after)), ("a", "b edited"));
+        assert!(!stack.can_undo());
+    }
+
+    #[test]
+    fn steps_apart_in_time_or_action_or_post_stay_separate() {
+        let now = Utc::now();
+        let post = Post::new("Title", "a");
+        let other = Post::new("Other", "b");
+        let window = chrono::Duration::from_std(UNDO_COALESCE_WINDOW).unwrap();
+        for next in [
+            step(UndoAction::EditContent, &post, now + window),
+            step(UndoAction::EditTitle, &post, now),
+            step(UndoAction::EditContent, &other, now),
+        ] {
+            let mut stack = UndoStack::default();
+            stack.push(step(UndoAction::EditContent, &post, now));
+            stack.push(next);
+            assert!(stack.pop_undo().is_some() && stack.pop_undo().is_some());
+        }
+        // deletions never coalesce, even of the same post right away
+        let mut stack = UndoStack::default();
+        stack.push(step(UndoAction::DeletePost, &post, now));
+        stack.push(step(UndoAction::DeletePost, &post, now));
+        assert!(stack.pop_undo().is_some() && stack.pop_undo().is_some());
+    }
+
+    #[test]
+    fn undo_redo_then_a_new_action_drops_the_redo_steps() {
+        let mut stack = UndoStack::default();
+        let post = Post::new("Title", "a");
+        let now = Utc::now();
+        stack.push(step(UndoAction::PinPost, &post, now));
+        stack.push(step(UndoAction::FavouritePost, &post, now));
+        assert_eq!(stack.pop_undo().unwrap().action, UndoAction::FavouritePost);
+        assert!(stack.can_redo());
+        assert_eq!(stack.pop_redo().unwrap().action, UndoAction::FavouritePost);
+        assert!(!stack.can_redo());
+        assert_eq!(stack.pop_undo().unwrap().action, UndoAction::FavouritePost);
+        stack.push(step(UndoAction::EditTags, &post, now));
+        assert!(!stack.can_redo());
+        assert!(stack.pop_redo().is_none());
+        assert_eq!(stack.pop_undo().unwrap().action, UndoAction::EditTags);
+        assert_eq!(stack.pop_undo().unwrap().action, UndoAction::PinPost);
+        assert!(stack.pop_undo().is_none());
+    }
+
+    #[test]
+    fn oldest_steps_beyond_the_limit_are_dropped() {
+        let mut stack = UndoStack::default();
+        let post = Post::new("Title", "a");
+        for _ in 0..UNDO_LIMIT + 5 {
+            stack.push(step(UndoAction::PinPost, &post, Utc::now()));
+        }
+        let mut count = 0;
+        while stack.pop_undo().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, UNDO_LIMIT);
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::{HashMap, HashSet};
+
+use uuid::Uuid;
+
+use crate::notes::models::Post;
+
+/// Scheme of the markdown links `[[Note Title]]` references are turned into for rendering
+pub static NOTE_URL_PREFIX: &str = "note:";
+
+/// `[[target]]` or `[[target|label]]` reference to another note
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WikiLink {
+    pub target: String,
+    pub label: Option<String>,
+}
+
+impl WikiLink {
+    fn parse(inner: &str) -> Option<Self> {
+        let (target, label) = match inner.split_once('|') {
+            Some((target, label)) => (target.trim(), Some(label.trim().to_string())),
+            None => (inner.trim(), None),
+        };
+        if target.is_empty() || target.contains('\n') {
+            return None;
+        }
+        Some(Self {
+            target: target.to_string(),
+            label,
+        })
+    }
+    fn to_source(&self) -> String {
+        match &self.label {
+            Some(label) => format!("[[{}|{}]]", self.target, label),
+            None => format!("[[{}]]", self.target),
+        }
+    }
+    /// Whether the link points to `post`, either by its id or by its title ignoring case
+    pub fn is_pointing_to(&self, post: &Post) -> bool {
+        match Uuid::parse_str(&self.target) {
+            Ok(id) => id == post.id,
+            Err(_) => self.target.to_lowercase() == post.title.trim().to_lowercase(),
+        }
+    }
+}
+
+/// What a link is matched on, the way `WikiLink::is_pointing_to` compares it with a post
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum LinkKey {
+    Id(Uuid),
+    Title(String),
+}
+
+impl LinkKey {
+    fn of_link(link: &WikiLink) -> Self {
+        match Uuid::parse_str(&link.target) {
+            Ok(id) => Self::Id(id),
+            Err(_) => Self::Title(link.target.to_lowercase()),
+        }
+    }
+    fn of_title(title: &str) -> Self {
+        Self::Title(title.trim().to_lowercase())
+    }
+}
+
+/// Posts linking to every target, kept in step with post changes like the search index
+/// so backlinks don't need every post parsed again
+#[derive(Debug, Default)]
+pub struct LinkIndex {
+    sources: HashMap<LinkKey, HashSet<Uuid>>,
+    targets: HashMap<Uuid, HashSet<LinkKey>>,
+}
+
+impl LinkIndex {
+    pub fn build<'a>(posts: impl IntoIterator<Item=&'a Post>) -> Self {
+        let mut index = Self::default();
+        for post in posts {
+            index.insert(post);
+        }
+        index
+    }
+
+    pub fn insert(&mut self, post: &Post) {
+        self.remove(&post.id);
+        let keys: HashSet<LinkKey> = extract_links(&post.content).iter().map(LinkKey::of_link).collect();
+        for key in &keys {
+            self.sources.entry(key.clone()).or_default().insert(post.id);
+        }
+        if !keys.is_empty() {
+            self.targets.insert(post.id, keys);
+        }
+    }
+
+    pub fn remove(&mut self, post_id: &Uuid) {
+        let Some(keys) = self.targets.remove(post_id) else {
+            return;
+        };
+        for key in keys {
+            if let Some(sources) = self.sources.get_mut(&key) {
+                sources.remove(post_id);
+                if sources.is_empty() {
+                    self.sources.remove(&key);
+                }
+            }
+        }
+    }
+
+    fn get_sources(&self, key: &LinkKey) -> impl Iterator<Item=Uuid> + '_ {
+        self.sources.get(key).into_iter().flatten().copied()
+    }
+
+    /// Ids of the other posts linking to `post` by its id or its title
+    pub fn get_backlinks(&self, post: &Post) -> HashSet<Uuid> {
+        self.get_sources(&LinkKey::Id(post.id))
+            .chain(self.get_sources(&LinkKey::of_title(&post.title)))
+            .filter(|source_id| *source_id != post.id)
+            .collect()
+    }
+
+    /// Ids of the posts linking to `title` by name
+    pub fn get_title_links(&self, title: &str) -> HashSet<Uuid> {
+        self.get_sources(&LinkKey::of_title(title)).collect()
+    }
+}
+
+/// Calls `replace` for every link outside of code with the link and its source text,
+/// and puts the result in place of the link
+fn replace_links(content: &str, mut replace: impl FnMut(WikiLink, &str) -> String) -> String {
+    let mut result = String::with_capacity(content.len());
+    let mut is_in_code_block = false;
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            is_in_code_block = !is_in_code_block;
+        }
+        if is_in_code_block || trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            result.push_str(line);
+            continue;
+        }
+        let mut rest = line;
+        while !rest.is_empty() {
+            let next_code = rest.find('`');
+            let next_link = rest.find("[[");
+            match (next_code, next_link) {
+                (Some(code), link) if link.map_or(true, |link| code < link) => {
+                    // inline code is copied as is up to its closing backtick
+                    let end = rest[code + 1..].find('`').map_or(rest.len(), |end| code + end + 2);
+                    result.push_str(&rest[..end]);
+                    rest = &rest[end..];
+                }
+                (_, Some(start)) => {
+                    let link = rest[start + 2..].find("]]")
+                        .and_then(|end| Some((end, WikiLink::parse(&rest[start + 2..start + 2 + end])?)));
+                    match link {
+                        Some((end, link)) => {
+                            result.push_str(&rest[..start]);
+                            result.push_str(&replace(link, &rest[start..start + 2 + end + 2]));
+                            rest = &rest[start + 2 + end + 2..];
+                        }
+                        None => {
+                            result.push_str(&rest[..start + 2]);
+                            rest = &rest[start + 2..];
+                        }
+                    }
+                }
+                _ => {
+                    result.push_str(rest);
+                    rest = "";
+                }
+            }
+        }
+    }
+    result
+}
+
+pub fn extract_links(content: &str) -> Vec<WikiLink> {
+    let mut links = Vec::new();
+    replace_links(content, |link, _| {
+        links.push(link);
+        String::new()
+    });
+    links
+}
+
+/// Turns `[[links]]` into regular markdown links with the `note:` scheme
+pub fn to_markdown_links(content: &str) -> String {
+    replace_links(content, |link, _| {
+        let label = link.label.as_deref().unwrap_or(&link.target)
+            .replace('[', "\\[")
+            .replace(']', "\\]");
+        format!("[{}](<{}{}>)", label, NOTE_URL_PREFIX, link.target.replace('>', "%3E"))
+    })
+}
+
+/// Note the target of a clicked link refers to
+pub fn resolve<'a>(target: &str, mut posts: impl Iterator<Item = &'a Post>) -> Option<Uuid> {
+    let link = WikiLink::parse(&target.replace("%3E", ">"))?;
+    posts.find(|post| link.is_pointing_to(post)).map(|post| post.id)
+}
+
+/// Content with the links pointing to `post` retargeted to `new_title`,
+/// `None` if there is no such link
+pub fn rewrite_links(content: &str, post: &Post, new_title: &str) -> Option<String> {
+    let mut is_changed = false;
+    let rewritten = replace_links(content, |link, source| {
+        if link.is_pointing_to(post) && Uuid::parse_str(&link.target).is_err() {
+            is_changed = true;
+            WikiLink {
+                target: new_title.trim().to_string(),
+                ..link
+            }.to_source()
+        } else {
+            source.to_string()
+        }
+    });
+    is_changed.then_some(rewritten)
+}